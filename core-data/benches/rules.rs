@@ -0,0 +1,81 @@
+use std::fs;
+
+use core_data::models::message::*;
+use core_data::models::workflow::Workflow;
+use core_data::rules::{Rule, RuleEngine};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use datalogic_rs::JsonLogic;
+use serde_json::{json, Value};
+
+fn pacs008_message() -> Message {
+    let xml_bytes = fs::read("examples/pacs008_001_07_cct_outgoing.xml")
+        .expect("Failed to read XML file");
+    let payload = Payload::new_inline(Some(xml_bytes), PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+    Message::new(
+        payload,
+        "tenant1".to_string(),
+        "api".to_string(),
+        "payment_processing".to_string(),
+        1,
+        "initiate".to_string(),
+        Some("Payment".to_string()),
+    )
+}
+
+/// Conditions and enrichment lookups typical of a pacs.008 workflow.
+fn rules() -> Vec<Value> {
+    vec![
+        json!({"==": [{"var": "data.document.FIToFICstmrCdtTrf.GrpHdr.NbOfTxs"}, "1"]}),
+        json!({"and": [
+            {"==": [{"var": "data.document.FIToFICstmrCdtTrf.GrpHdr.TtlIntrBkSttlmAmt.@Ccy"}, "EUR"]},
+            {"<=": [{"var": "data.document.FIToFICstmrCdtTrf.GrpHdr.TtlIntrBkSttlmAmt.$value"}, 50000]}
+        ]}),
+        json!({"in": [{"var": "data.document.FIToFICstmrCdtTrf.GrpHdr.SttlmInf.SttlmMtd"}, ["CLRG", "INGA", "INDA"]]}),
+        json!({"==": [{"var": "progress.status"}, "Processing"]}),
+        json!({"var": "data.document.FIToFICstmrCdtTrf.GrpHdr.MsgId"}),
+    ]
+}
+
+fn bench_rules(c: &mut Criterion) {
+    let mut message = pacs008_message();
+    message.parse(None, "payment_processing".to_string(), 1, "parse".to_string())
+        .expect("Failed to parse XML message");
+    let context = message.condition_context();
+    let raw = rules();
+    let compiled: Vec<Rule> = raw.iter().cloned().map(Rule::from).collect();
+
+    let mut group = c.benchmark_group("rules");
+    group.throughput(Throughput::Elements(raw.len() as u64));
+    // How conditions and enrichment rules were evaluated before: a new evaluator per call
+    group.bench_function("per_call_evaluator", |b| b.iter(|| {
+        for rule in &raw {
+            let _ = JsonLogic::new().apply(rule, &context);
+        }
+    }));
+    group.bench_function("shared_compiled", |b| b.iter(|| {
+        for rule in &compiled {
+            let _ = RuleEngine::shared().evaluate(rule, &context);
+        }
+    }));
+    group.finish();
+}
+
+fn bench_workflow(c: &mut Criterion) {
+    let content = fs::read_to_string("../sample-workflow.json").expect("Failed to read workflow file");
+    let workflows: Vec<Workflow> = vec![serde_json::from_str(&content).expect("Failed to parse workflow")];
+
+    let mut group = c.benchmark_group("workflow");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("pacs008", |b| b.iter_batched(
+        pacs008_message,
+        |mut message| {
+            let workflow = message.workflow_select(&workflows).cloned().expect("No workflow selected");
+            let _ = message.execute_workflow(&workflow);
+        },
+        BatchSize::SmallInput,
+    ));
+    group.finish();
+}
+
+criterion_group!(benches, bench_rules, bench_workflow);
+criterion_main!(benches);
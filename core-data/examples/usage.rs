@@ -33,12 +33,12 @@ fn main() {
     let enrichment_config = vec![
         EnrichmentRules {
             field: "data.metadata.processing_date".to_string(),
-            logic: json!({"var": ["processing_date"]}),
+            logic: json!({"var": ["processing_date"]}).into(),
             description: Some("Add processing date".to_string()),
         },
         EnrichmentRules {
             field: "data.metadata.transaction_type".to_string(),
-            logic: json!({"var": ["transaction_type"]}),
+            logic: json!({"var": ["transaction_type"]}).into(),
             description: Some("Add transaction type".to_string()),
         },
     ];
@@ -1 +1,3 @@
 pub mod models;
+pub mod rules;
+pub mod storage;
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument};
+
+use crate::models::task::Task;
+use crate::models::workflow::Workflow;
+use super::{
+    core::Message,
+    errors::FunctionResponseError,
+    auditlog::ChangeLog,
+    progress::{MessageStatus, StatusCode, Token},
+};
+
+/// Outcome of a manual review of a suspended message.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ApprovalDecision {
+    pub approved: bool,
+
+    /// Identity of the person or system that made the decision
+    pub approver: String,
+
+    pub reason: String,
+}
+
+impl Message {
+    /// Parks the message at `task` until `resume` is called with a decision.
+    pub fn suspend(&mut self, task: &Task) -> Result<(), FunctionResponseError> {
+        self.transition(MessageStatus::Suspended, &task.id, task.description.clone(), vec![])?;
+        self.progress.prev_status_code = None;
+        Ok(())
+    }
+
+    /// Applies the decision to a message suspended in `workflow`. An approval continues the
+    /// workflow from the parked task with a `Success` status code; a rejection ends it as
+    /// `Rejected` and compensates the tasks completed before it.
+    #[instrument(skip(self, workflow, decision), fields(message_id = %self.id, approved = decision.approved))]
+    pub fn resume(&mut self, workflow: &Workflow, decision: &ApprovalDecision) -> Result<(), FunctionResponseError> {
+        if self.progress.status != MessageStatus::Suspended {
+            return Err(FunctionResponseError::new(
+                "Approval".to_string(),
+                409,
+                format!("Message is {:?}, not awaiting approval", self.progress.status)
+            ));
+        }
+        if self.progress.workflow_id != workflow.id || self.progress.workflow_version != workflow.version {
+            return Err(FunctionResponseError::new(
+                "Approval".to_string(),
+                409,
+                format!(
+                    "Message is suspended in workflow {} version {}, not {} version {}",
+                    self.progress.workflow_id, self.progress.workflow_version, workflow.id, workflow.version
+                )
+            ));
+        }
+
+        let (status, status_code, verb) = if decision.approved {
+            (MessageStatus::Processing, StatusCode::Success, "Approved")
+        } else {
+            (MessageStatus::Rejected, StatusCode::Failure, "Rejected")
+        };
+
+        let change_log = ChangeLog::new(
+            "approval".to_string(),
+            decision.reason.clone(),
+            None,
+            serde_json::to_value(decision).ok()
+        );
+        let task_id = self.progress.prev_task.clone();
+        self.transition(
+            status.clone(),
+            &task_id,
+            format!("{} by {}: {}", verb, decision.approver, decision.reason),
+            vec![change_log]
+        )?;
+        self.progress.prev_status_code = Some(status_code.clone());
+        self.progress.tokens.push(Token {
+            task: task_id,
+            status,
+            status_code: Some(status_code),
+            fields: Vec::new(),
+        });
+
+        if !decision.approved {
+            self.compensate(workflow);
+        }
+
+        info!(
+            approver = %decision.approver,
+            task_id = %self.progress.prev_task,
+            "Approval decision applied"
+        );
+        Ok(())
+    }
+}
@@ -28,6 +28,10 @@ pub struct AuditLog {
     instance: Box<str>,
 
     changes: Box<[ChangeLog]>,
+
+    /// Entries recorded by a sub-workflow the task invoked
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    children: Vec<AuditLog>,
 }
 
 impl AuditLog {
@@ -67,6 +71,10 @@ impl AuditLog {
         &self.changes
     }
 
+    pub fn children(&self) -> &[AuditLog] {
+        &self.children
+    }
+
     pub fn start_time(&self) -> &OffsetDateTime {
         &self.start_time
     }
@@ -91,8 +99,14 @@ impl AuditLog {
             service: String::new().into_boxed_str(),
             instance: String::new().into_boxed_str(),
             changes: changes.into_boxed_slice(),
+            children: Vec::new(),
         }
     }
+
+    pub fn with_children(mut self, children: Vec<AuditLog>) -> Self {
+        self.children = children;
+        self
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -126,8 +140,8 @@ impl ChangeLog {
     pub fn new(field: String, reason: String, old_value: Option<serde_json::Value>, new_value: Option<serde_json::Value>) -> Self {
         ChangeLog {
             field: field.into_boxed_str(),
-            old_value: old_value,
-            new_value: new_value,
+            old_value,
+            new_value,
             reason: reason.into_boxed_str(),
         }
     }
@@ -0,0 +1,44 @@
+use time::OffsetDateTime;
+use tracing::{error, info, instrument};
+
+use crate::models::workflow::Workflow;
+use super::{
+    core::Message,
+    auditlog::AuditLog,
+};
+
+impl Message {
+    /// Runs the compensations of the tasks completed in this run, most recent first. A failed
+    /// compensation is audited and does not stop the others.
+    #[instrument(skip(self, workflow), fields(message_id = %self.id, workflow_id = %workflow.id))]
+    pub fn compensate(&mut self, workflow: &Workflow) {
+        // Function transactions update progress, which must keep describing the failure
+        let progress = self.progress.clone();
+
+        let completed: Vec<String> = self.progress.tokens.iter().rev().map(|t| t.task.clone()).collect();
+        for task in completed.iter().filter_map(|id| workflow.task(id)) {
+            let Some(compensation) = &task.compensation else {
+                continue;
+            };
+
+            let start_time = OffsetDateTime::now_utc();
+            match self.execute_task(workflow.id.clone(), workflow.version, compensation.task(task)) {
+                Ok(_) => info!(task_id = %task.id, "Task compensated"),
+                Err(e) => {
+                    error!(error = %e, task_id = %task.id, "Task compensation failed");
+                    self.audit.push(AuditLog::new(
+                        workflow.id.clone(),
+                        workflow.version,
+                        format!("{}.compensation", task.id),
+                        start_time,
+                        format!("Compensation for '{}' failed: {}", task.id, e),
+                        vec![]
+                    ));
+                    self.version += 1;
+                }
+            }
+        }
+
+        self.restore_progress(progress);
+    }
+}
@@ -9,6 +9,8 @@ use crate::models::message::auditlog::*;
 use crate::models::message::errors::FunctionResponseError;
 use crate::models::message::progress::*;
 
+/// One generator per process; separate generators hand out the same id within a tick.
+static IDS: std::sync::LazyLock<Sonyflake> = std::sync::LazyLock::new(|| Sonyflake::new().unwrap());
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Message {
@@ -45,6 +47,13 @@ impl Message {
         &self.audit
     }
 
+    /// When the message was created, taken from its first audit entry.
+    pub fn created_at(&self) -> OffsetDateTime {
+        self.audit.first()
+            .map(|a| *a.start_time())
+            .unwrap_or(self.progress.timestamp)
+    }
+
     pub fn data(&self) -> &Value {
         &self.data
     }
@@ -82,13 +91,15 @@ impl Message {
     }
 
     #[instrument(skip(self))]
-    pub(crate) fn transaction_begin(&mut self, workflow: String, task: String) {
+    pub(crate) fn transaction_begin(&mut self, workflow: String, workflow_version: u16, task: String) {
         debug!(
             workflow = %workflow,
+            workflow_version = workflow_version,
             task = %task,
             "Beginning transaction"
         );
         self.progress.workflow_id = workflow;
+        self.progress.workflow_version = workflow_version;
         self.progress.prev_task = task;
         self.transaction_changes = Some(Vec::new());
     }
@@ -165,8 +176,7 @@ impl Message {
     #[instrument(skip(payload, tenant, origin, workflow_id, task_id), fields(message_id))]
     pub fn new(payload: Payload, tenant: String, origin: String, workflow_id: String, workflow_version: u16, task_id: String, message_alias: Option<String>) -> Self {
         let start = std::time::Instant::now();
-        let sf = Sonyflake::new().unwrap();
-        let id = sf.next_id().unwrap();
+        let id = IDS.next_id().unwrap();
 
         debug!(
             tenant = %tenant,
@@ -211,13 +221,23 @@ impl Message {
             progress: Progress {
                 status: MessageStatus::Recieved,
                 workflow_id: workflow_id.to_string(),
+                workflow_version,
                 prev_task: task_id.to_string(),
                 prev_status_code: Some(StatusCode::Success),
                 timestamp: OffsetDateTime::now_utc(),
+                attempt: 0,
+                retry_at: None,
+                tokens: Vec::new(),
             },
             audit: vec![audit],
             transaction_changes: Some(Vec::new()),
             ephemeral_data: Value::Null,
         }
     }
+
+    /// Ties the message to a correlation id shared with related messages, such as a batch.
+    pub fn with_parent_id(mut self, parent_id: String) -> Self {
+        self.parent_id = Some(parent_id);
+        self
+    }
 }
@@ -1,20 +1,21 @@
 use time::OffsetDateTime;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use datalogic_rs::JsonLogic;
 use tracing::{debug, error, info, instrument, warn};
 use std::time::Instant;
 
+use crate::rules::{Rule, RuleEngine};
+
 use super::{
     core::Message,
     errors::FunctionResponseError,
     auditlog::{AuditLog, ChangeLog},
+    execute::TIMEOUT_CODE,
 };
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct EnrichmentRules {
     pub field: String,
-    pub logic: Value,
+    pub logic: Rule,
     pub description: Option<String>,
 }
 
@@ -35,7 +36,7 @@ impl Message {
     ) -> Result<(), FunctionResponseError> {
         let start = Instant::now();
         let start_time = OffsetDateTime::now_utc();
-        let logic = JsonLogic::new();
+        let logic = RuleEngine::shared();
         let mut changes = Vec::new();
         
         debug!(
@@ -44,7 +45,7 @@ impl Message {
         );
 
         // Begin transaction
-        self.transaction_begin(workflow_id.clone(), task_id.clone());
+        self.transaction_begin(workflow_id.clone(), workflow_version, task_id.clone());
         debug!("Transaction started");
 
         for (idx, rule) in rules.into_iter().enumerate() {
@@ -54,7 +55,7 @@ impl Message {
                 "Applying enrichment rule"
             );
 
-            let value = match logic.apply(&rule.logic, &data) {
+            let value = match logic.evaluate(&rule.logic, &data) {
                 Ok(v) => v,
                 Err(e) => {
                     error!(
@@ -67,8 +68,8 @@ impl Message {
                     debug!("Transaction rolled back due to rule application failure");
                     return Err(FunctionResponseError::new(
                         "Enrichment".to_string(),
-                        400,
-                        format!("Rule application failed: {:?}", e)
+                        if e.is_timeout() { TIMEOUT_CODE } else { 400 },
+                        format!("Rule application failed: {}", e)
                     ));
                 }
             };
@@ -1,15 +1,84 @@
 use crate::models::task::*;
 use crate::models::workflow::*;
+use time::{Duration, OffsetDateTime};
 use tracing::{debug, error, info, instrument, warn};
 
+use crate::rules::with_deadline;
+
 use super::errors::WorkflowResponseError;
 use super::{
     core::Message,
-    errors::FunctionResponseError, EnrichmentRules
+    errors::FunctionResponseError, EnrichmentRules, ValidationRule,
+    auditlog::{AuditLog, ChangeLog},
 };
 
 use super::progress::*;
 
+/// Function error code used when a task or workflow exceeds its `timeout_ms`
+pub const TIMEOUT_CODE: u32 = 408;
+
+/// Function error code used when parallel branches write the same field
+const CONFLICT_CODE: u32 = 409;
+
+/// Guards against sub-workflows that end up invoking themselves
+const MAX_SUB_WORKFLOW_DEPTH: usize = 8;
+
+/// Message state from before a task ran, used to undo a task that is failed after the fact.
+struct TaskSnapshot {
+    data: serde_json::Value,
+    metadata: serde_json::Value,
+    ephemeral_data: serde_json::Value,
+    audit_len: usize,
+    version: u16,
+}
+
+impl TaskSnapshot {
+    fn take(message: &Message) -> Self {
+        TaskSnapshot {
+            data: message.data.clone(),
+            metadata: message.metadata.clone(),
+            ephemeral_data: message.ephemeral_data.clone(),
+            audit_len: message.audit.len(),
+            version: message.version,
+        }
+    }
+
+    fn restore(&self, message: &mut Message) {
+        message.data = self.data.clone();
+        message.metadata = self.metadata.clone();
+        message.ephemeral_data = self.ephemeral_data.clone();
+        message.audit.truncate(self.audit_len);
+        message.version = self.version;
+    }
+}
+
+/// Collects the fields changed by an audit entry and any sub-workflow entries nested in it.
+fn changed_fields(audit: &AuditLog, fields: &mut Vec<String>) {
+    fields.extend(audit.changes().iter().map(|change| change.field().to_string()));
+    for child in audit.children() {
+        changed_fields(child, fields);
+    }
+}
+
+/// Whether one field path is the other or contains it, e.g. `metadata.a` and `metadata.a.b`.
+fn fields_overlap(a: &str, b: &str) -> bool {
+    let (shorter, longer) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    longer.strip_prefix(shorter).is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorkflowOutcome {
+    /// No further task matched the message
+    Finished,
+    /// A task failed with a retryable error and must be re-run once `retry_at` has passed;
+    /// the processor holds the message in its timer store until then
+    RetryScheduled { retry_at: OffsetDateTime },
+    /// An `Approval` task parked the message until a decision is made
+    Suspended { task_id: String },
+    /// A `Schedule` task warehoused the message until `due_at`
+    Scheduled { task_id: String, due_at: OffsetDateTime },
+}
+
 #[derive(Debug)]
 pub struct TaskResult {
     pub status: MessageStatus,
@@ -44,6 +113,26 @@ impl Message {
                         status_code: Some(StatusCode::Success)
                     })
             },
+            FunctionType::Validate => {
+                let rules: Vec<ValidationRule> = serde_json::from_value(task.input).map_err(|e| FunctionResponseError::new(
+                    "Validate".to_string(),
+                    400,
+                    format!("Invalid validation rules: {}", e)
+                ))?;
+                self.validate(rules, Some(task.description), workflow_id, workflow_version, task.id)
+                    .map(|_| TaskResult {
+                        status: MessageStatus::Processing,
+                        status_code: Some(StatusCode::Success)
+                    })
+            },
+            FunctionType::Schedule => {
+                // Only reached once the scheduled time has passed
+                self.scheduled_for(&task)
+                    .map(|_| TaskResult {
+                        status: MessageStatus::Processing,
+                        status_code: Some(StatusCode::Success)
+                    })
+            },
             _ => Err(FunctionResponseError::new(
                 "Execute".to_string(),
                 400,
@@ -54,37 +143,123 @@ impl Message {
         Ok(result)
     }
 
-    #[instrument(skip(self, workflow), fields(workflow_id = %workflow.id))]
-    pub fn execute_workflow(&mut self, workflow: &Workflow) -> Result<(), WorkflowResponseError> {
+    /// Runs a workflow without sub-workflows; one with `SubWorkflow` tasks is refused before any
+    /// task runs, as they could never be resolved.
+    pub fn execute_workflow(&mut self, workflow: &Workflow) -> Result<WorkflowOutcome, WorkflowResponseError> {
+        if let Some(task) = workflow.tasks.iter().find(|t| t.function == FunctionType::SubWorkflow) {
+            return Err(WorkflowResponseError::new(
+                workflow.id.clone(),
+                workflow.version,
+                400,
+                format!("Task '{}' invokes a sub-workflow; run the workflow with execute_workflow_with", task.id)
+            ));
+        }
+        self.run_workflow(workflow, &[], 0)
+    }
+
+    /// Like `execute_workflow`, resolving `SubWorkflow` tasks against `workflows`.
+    pub fn execute_workflow_with(&mut self, workflow: &Workflow, workflows: &[Workflow]) -> Result<WorkflowOutcome, WorkflowResponseError> {
+        self.run_workflow(workflow, workflows, 0)
+    }
+
+    /// Runs the workflow with its `timeout_ms` as the deadline of every rule evaluated on the way.
+    /// Each top-level pass bumps the version, as progress changes even when nothing is audited.
+    #[instrument(skip(self, workflow, workflows), fields(workflow_id = %workflow.id, workflow_version = workflow.version, depth = depth))]
+    fn run_workflow(&mut self, workflow: &Workflow, workflows: &[Workflow], depth: usize) -> Result<WorkflowOutcome, WorkflowResponseError> {
         let start = std::time::Instant::now();
+        let deadline = workflow.timeout_ms.map(|limit| start + std::time::Duration::from_millis(limit));
+        let outcome = with_deadline(deadline, || self.run_tasks(workflow, workflows, depth, start));
+        if depth == 0 {
+            self.version += 1;
+        }
+        outcome
+    }
+
+    fn run_tasks(&mut self, workflow: &Workflow, workflows: &[Workflow], depth: usize, start: std::time::Instant) -> Result<WorkflowOutcome, WorkflowResponseError> {
         debug!("Starting workflow execution");
+
+        // Pin new messages to the version they start on
+        if !self.is_pinned() && self.progress.workflow_id == workflow.id {
+            self.progress.workflow_version = workflow.version;
+        }
+
+        // Start tracking completed tasks from the step that started this run
+        if self.progress.tokens.is_empty() {
+            self.progress.tokens.push(Token {
+                task: self.progress.prev_task.clone(),
+                status: self.progress.status.clone(),
+                status_code: self.progress.prev_status_code.clone(),
+                fields: Vec::new(),
+            });
+        }
     
         let mut task_executed = true;
         let mut execution_count = 0;
+        // Combined condition context shared by the readiness checks until a task runs
+        let mut combined = None;
     
         while task_executed {
             task_executed = false;
     
             for task in &workflow.tasks {
-                if self.task_match(task.message_status.clone(), &workflow.id, &task.prev_task, 
-                    task.prev_status_code.clone(), &task.condition) {
-                    
+                if self.task_ready(workflow, task, &mut combined) {
+                    combined = None;
+
+                    // The workflow budget is checked before starting each task
+                    if let Some(error) = self.check_workflow_timeout(workflow, &task.id, start) {
+                        return Err(error);
+                    }
+
+                    if task.function == FunctionType::Approval {
+                        self.suspend(task)?;
+                        info!(
+                            task_id = %task.id,
+                            duration_ms = start.elapsed().as_millis(),
+                            "Workflow suspended for approval"
+                        );
+                        return Ok(WorkflowOutcome::Suspended { task_id: task.id.clone() });
+                    }
+
+                    // Due or unparseable schedules run below, which completes or fails them
+                    if task.function == FunctionType::Schedule {
+                        if let Ok(due_at) = self.scheduled_for(task) {
+                            if due_at > OffsetDateTime::now_utc() {
+                                self.schedule(task, due_at)?;
+                                info!(
+                                    task_id = %task.id,
+                                    due_at = %due_at,
+                                    "Workflow scheduled for later execution"
+                                );
+                                return Ok(WorkflowOutcome::Scheduled { task_id: task.id.clone(), due_at });
+                            }
+                        }
+                    }
+
                     debug!(
                         task_id = %task.id,
                         task_name = %task.name,
                         "Executing task"
                     );
     
-                    match self.execute_task(workflow.id.clone(), workflow.version, task.clone()) {
-                        Ok(task_result) => {
+                    let snapshot = self.progress.clone();
+                    let result = self.execute_task_checked(workflow, task, workflows, depth)
+                        .and_then(|(task_result, fields)| {
+                            let description = format!("{:?} after task '{}'", task_result.status, task.id);
+                            self.transition(task_result.status.clone(), &task.id, description, vec![])?;
+                            Ok((task_result, fields))
+                        });
+                    match result {
+                        Ok((task_result, fields)) => {
                             // Update progress with task result
-                            self.progress = Progress {
+                            self.progress.prev_status_code = task_result.status_code.clone();
+                            self.progress.attempt = 0;
+                            self.progress.retry_at = None;
+                            self.progress.tokens.push(Token {
+                                task: task.id.clone(),
                                 status: task_result.status.clone(),
-                                workflow_id: workflow.id.clone(),
-                                prev_task: task.id.clone(),
-                                prev_status_code: task_result.status_code,
-                                timestamp: time::OffsetDateTime::now_utc(),
-                            };
+                                status_code: task_result.status_code.clone(),
+                                fields,
+                            });
                             
                             debug!(
                                 task_id = %task.id,
@@ -96,20 +271,48 @@ impl Message {
                             execution_count += 1;
                         }
                         Err(e) => {
+                            let attempt = snapshot.attempt;
+                            if let Some(delay_ms) = self.retry(workflow, task, snapshot, &e) {
+                                if delay_ms == 0 {
+                                    task_executed = true;
+                                    continue;
+                                }
+                                let retry_at = OffsetDateTime::now_utc() + Duration::milliseconds(delay_ms as i64);
+                                self.progress.retry_at = Some(retry_at);
+                                info!(
+                                    task_id = %task.id,
+                                    attempt = self.progress.attempt,
+                                    retry_at = %retry_at,
+                                    "Task retry scheduled"
+                                );
+                                return Ok(WorkflowOutcome::RetryScheduled { retry_at });
+                            }
+
                             error!(
                                 error = %e,
                                 workflow_id = %workflow.id,
                                 task_id = %task.id,
                                 "Task execution failed"
                             );
+
+                            if e.code == TIMEOUT_CODE {
+                                self.fail_timeout(workflow, &task.id, e.message.clone());
+                                self.progress.attempt = attempt;
+                                self.compensate(workflow);
+                                return Err(WorkflowResponseError::new(
+                                    workflow.id.clone(),
+                                    workflow.version,
+                                    TIMEOUT_CODE as u16,
+                                    format!("Task execution failed: {}", e)
+                                ));
+                            }
+
                             // Update progress with failure status
-                            self.progress = Progress {
-                                status: MessageStatus::Failed,
-                                workflow_id: workflow.id.clone(),
-                                prev_task: task.id.clone(),
-                                prev_status_code: Some(StatusCode::Failure),
-                                timestamp: time::OffsetDateTime::now_utc(),
-                            };
+                            self.fail(&task.id, format!("Task execution failed: {}", e), vec![]);
+                            self.progress.prev_status_code = Some(StatusCode::Failure);
+                            self.progress.attempt = attempt;
+                            self.progress.retry_at = None;
+                            self.compensate(workflow);
                             return Err(WorkflowResponseError::new(
                                 workflow.id.clone(),
                                 workflow.version,
@@ -122,12 +325,242 @@ impl Message {
             }
         }
     
+        // Conditions that ran out of time read as false, which must not pass for a finished run
+        if !self.progress.status.is_terminal() {
+            let task_id = self.progress.prev_task.clone();
+            if let Some(error) = self.check_workflow_timeout(workflow, &task_id, start) {
+                return Err(error);
+            }
+        }
+
         info!(
             workflow_id = %workflow.id,
             duration_ms = start.elapsed().as_millis(),
             tasks_executed = execution_count,
             "Workflow execution completed"
         );
-        Ok(())
+        Ok(WorkflowOutcome::Finished)
+    }
+
+    /// Fails the message at `task_id` and compensates once the workflow has used up its `timeout_ms`.
+    fn check_workflow_timeout(&mut self, workflow: &Workflow, task_id: &str, start: std::time::Instant) -> Option<WorkflowResponseError> {
+        let limit = workflow.timeout_ms?;
+        let elapsed = start.elapsed().as_millis();
+        if elapsed <= limit as u128 {
+            return None;
+        }
+
+        let description = format!("Workflow timed out after {}ms (limit {}ms)", elapsed, limit);
+        self.fail_timeout(workflow, task_id, description.clone());
+        self.compensate(workflow);
+        Some(WorkflowResponseError::new(
+            workflow.id.clone(),
+            workflow.version,
+            TIMEOUT_CODE as u16,
+            description
+        ))
+    }
+
+    /// Runs the task, failing it when it overran `timeout_ms` or wrote a field a parallel
+    /// branch already wrote; either way its changes are undone. Rules stop being evaluated once
+    /// the timeout passes, and a task that overran it without evaluating another rule is failed
+    /// when it returns. Returns the fields it wrote.
+    fn execute_task_checked(&mut self, workflow: &Workflow, task: &Task, workflows: &[Workflow], depth: usize) -> Result<(TaskResult, Vec<String>), FunctionResponseError> {
+        let start = std::time::Instant::now();
+        let audit_len = self.audit.len();
+
+        // Fields written by tasks on other branches, which ran without seeing each other's writes
+        let parallel: Vec<Token> = self.progress.tokens.iter()
+            .filter(|t| !t.fields.is_empty() && !workflow.is_ancestor(&t.task, &task.id))
+            .cloned()
+            .collect();
+        let snapshot = (task.timeout_ms.is_some() || !parallel.is_empty()).then(|| TaskSnapshot::take(self));
+
+        let deadline = task.timeout_ms.map(|limit| start + std::time::Duration::from_millis(limit));
+        let result = with_deadline(deadline, || match task.function {
+            FunctionType::SubWorkflow => self.execute_sub_workflow(workflow, task, workflows, depth),
+            // Keep what the other branches fetched for the join to see
+            FunctionType::Fetch if !parallel.is_empty() => self
+                .fetch_merged(task.input.clone(), Some(task.description.clone()), workflow.id.clone(), workflow.version, task.id.clone())
+                .map(|_| TaskResult {
+                    status: MessageStatus::Processing,
+                    status_code: Some(StatusCode::Success)
+                }),
+            _ => self.execute_task(workflow.id.clone(), workflow.version, task.clone()),
+        })?;
+        let mut fields = Vec::new();
+        for audit in &self.audit[audit_len..] {
+            changed_fields(audit, &mut fields);
+        }
+
+        if let (Some(limit), Some(snapshot)) = (task.timeout_ms, &snapshot) {
+            let elapsed = start.elapsed().as_millis();
+            if elapsed > limit as u128 {
+                warn!(
+                    task_id = %task.id,
+                    elapsed_ms = elapsed,
+                    limit_ms = limit,
+                    "Task exceeded its timeout, discarding its changes"
+                );
+                snapshot.restore(self);
+                return Err(FunctionResponseError::new(
+                    "Timeout".to_string(),
+                    TIMEOUT_CODE,
+                    format!("Task '{}' timed out after {}ms (limit {}ms)", task.id, elapsed, limit)
+                ));
+            }
+        }
+
+        for token in &parallel {
+            if let (Some(field), Some(snapshot)) = (fields.iter().find(|f| token.fields.iter().any(|g| fields_overlap(f, g))), &snapshot) {
+                warn!(
+                    task_id = %task.id,
+                    conflicting_task = %token.task,
+                    field = %field,
+                    "Task conflicts with a parallel branch, discarding its changes"
+                );
+                let description = format!("Task '{}' wrote '{}' which parallel task '{}' also wrote", task.id, field, token.task);
+                snapshot.restore(self);
+                return Err(FunctionResponseError::new("Conflict".to_string(), CONFLICT_CODE, description));
+            }
+        }
+
+        Ok((result, fields))
+    }
+
+    /// Runs the referenced workflow to completion against this message, as if it had just been
+    /// initiated, then restores the parent's progress with the sub-workflow's final status code,
+    /// `Failure` when it failed. Its audit entries are nested under the task's own entry.
+    #[instrument(skip(self, parent, task, workflows), fields(task_id = %task.id))]
+    fn execute_sub_workflow(&mut self, parent: &Workflow, task: &Task, workflows: &[Workflow], depth: usize) -> Result<TaskResult, FunctionResponseError> {
+        let target: SubWorkflowRef = serde_json::from_value(task.input.clone())
+            .map_err(|e| FunctionResponseError::new(
+                "SubWorkflow".to_string(),
+                400,
+                format!("Invalid sub-workflow reference: {}", e)
+            ))?;
+        if depth >= MAX_SUB_WORKFLOW_DEPTH {
+            return Err(FunctionResponseError::new(
+                "SubWorkflow".to_string(),
+                400,
+                format!("Sub-workflows nested deeper than {} levels", MAX_SUB_WORKFLOW_DEPTH)
+            ));
+        }
+        let child = workflows.iter()
+            .find(|w| w.id == target.workflow_id && w.version == target.version)
+            .ok_or_else(|| FunctionResponseError::new(
+                "SubWorkflow".to_string(),
+                404,
+                format!("Sub-workflow '{}' version {} not found", target.workflow_id, target.version)
+            ))?;
+
+        let start_time = OffsetDateTime::now_utc();
+        let audit_len = self.audit.len();
+        let child_progress = Progress {
+            status: MessageStatus::Recieved,
+            workflow_id: child.id.clone(),
+            workflow_version: child.version,
+            prev_task: "initiate".to_string(),
+            prev_status_code: Some(StatusCode::Success),
+            timestamp: start_time,
+            attempt: 0,
+            retry_at: None,
+            tokens: Vec::new(),
+        };
+        let (outcome, child_progress) = self.with_progress(child_progress, |message| {
+            message.run_workflow(child, workflows, depth + 1)
+        });
+
+        let children = self.audit.split_off(audit_len);
+        self.audit.push(AuditLog::new(
+            parent.id.clone(),
+            parent.version,
+            task.id.clone(),
+            start_time,
+            format!("{} (sub-workflow {} v{})", task.description, child.id, child.version),
+            vec![]
+        ).with_children(children));
+        self.version += 1;
+
+        match outcome {
+            Ok(WorkflowOutcome::Finished) => Ok(TaskResult {
+                status: child_progress.status,
+                status_code: child_progress.prev_status_code,
+            }),
+            Ok(WorkflowOutcome::RetryScheduled { .. }) => Err(FunctionResponseError::new(
+                "SubWorkflow".to_string(),
+                503,
+                format!("Sub-workflow '{}' scheduled a retry of task '{}'", child.id, child_progress.prev_task)
+            )),
+            Ok(WorkflowOutcome::Suspended { task_id } | WorkflowOutcome::Scheduled { task_id, .. }) => Err(FunctionResponseError::new(
+                "SubWorkflow".to_string(),
+                400,
+                format!("Sub-workflow '{}' cannot park the message at task '{}'", child.id, task_id)
+            )),
+            // The parent routes on the failure like on any other task result
+            Err(e) => {
+                warn!(error = %e, sub_workflow = %child.id, "Sub-workflow failed");
+                Ok(TaskResult {
+                    status: MessageStatus::Processing,
+                    status_code: Some(StatusCode::Failure),
+                })
+            }
+        }
+    }
+
+    /// Marks the message as failed because `task_id` or the workflow ran out of time.
+    pub fn fail_timeout(&mut self, workflow: &Workflow, task_id: &str, description: String) {
+        let change_log = ChangeLog::new(
+            "progress.prev_status_code".to_string(),
+            "Execution timed out".to_string(),
+            serde_json::to_value(&self.progress.prev_status_code).ok(),
+            serde_json::to_value(StatusCode::Timeout).ok()
+        );
+        self.progress.workflow_id = workflow.id.clone();
+        self.progress.workflow_version = workflow.version;
+        self.fail(task_id, description, vec![change_log]);
+        self.progress.prev_status_code = Some(StatusCode::Timeout);
+        self.progress.attempt = 0;
+        self.progress.retry_at = None;
+    }
+
+    /// Restores progress to before the failed task and counts another attempt when the
+    /// task's retry policy allows it. Returns the delay before the retry.
+    fn retry(&mut self, workflow: &Workflow, task: &Task, snapshot: Progress, error: &FunctionResponseError) -> Option<u64> {
+        let policy = task.retry.as_ref()?;
+        let attempt = snapshot.attempt + 1;
+        if attempt >= policy.max_attempts || !policy.is_retryable(error.code) {
+            warn!(
+                task_id = %task.id,
+                attempt = attempt,
+                code = error.code,
+                "Task not retryable"
+            );
+            return None;
+        }
+
+        let delay_ms = policy.delay_ms(attempt);
+        let start_time = OffsetDateTime::now_utc();
+        self.restore_progress(snapshot);
+        self.progress.attempt = attempt;
+        self.progress.timestamp = start_time;
+
+        let change_log = ChangeLog::new(
+            "progress.attempt".to_string(),
+            format!("Retrying after error: {}", error),
+            Some(serde_json::json!(attempt - 1)),
+            Some(serde_json::json!(attempt))
+        );
+        self.audit.push(AuditLog::new(
+            workflow.id.clone(),
+            workflow.version,
+            task.id.clone(),
+            start_time,
+            format!("Retry {} of {} scheduled in {}ms", attempt, policy.max_attempts - 1, delay_ms),
+            vec![change_log]
+        ));
+        self.version += 1;
+
+        Some(delay_ms)
     }
 }
@@ -5,17 +5,43 @@ use std::time::Instant;
 use super::{
     core::Message,
     errors::FunctionResponseError,
-    auditlog::AuditLog,
+    auditlog::{AuditLog, ChangeLog},
 };
 
 impl Message {
+    /// Replaces the data fetched so far with `data`.
+    pub fn fetch(
+        &mut self,
+        data: serde_json::Value,
+        description: Option<String>,
+        workflow_id: String,
+        workflow_version: u16,
+        task_id: String
+    ) -> Result<(), FunctionResponseError> {
+        self.apply_fetch(data, false, description, workflow_id, workflow_version, task_id)
+    }
+
+    /// Merges an object into the data fetched so far, key by key, so parallel branches heading
+    /// for a join keep each other's results.
+    pub(crate) fn fetch_merged(
+        &mut self,
+        data: serde_json::Value,
+        description: Option<String>,
+        workflow_id: String,
+        workflow_version: u16,
+        task_id: String
+    ) -> Result<(), FunctionResponseError> {
+        self.apply_fetch(data, true, description, workflow_id, workflow_version, task_id)
+    }
+
     #[instrument(skip(self, data, description), fields(
         workflow_id = %workflow_id,
         task_id = %task_id
     ))]
-    pub fn fetch(
+    fn apply_fetch(
         &mut self,
         data: serde_json::Value,
+        merge: bool,
         description: Option<String>,
         workflow_id: String,
         workflow_version: u16,
@@ -28,7 +54,35 @@ impl Message {
             "Starting to run fetch function"
         );
 
-        self.ephemeral_data = data;
+        let mut changes = Vec::new();
+        match (merge, data) {
+            (true, serde_json::Value::Object(fetched)) if self.ephemeral_data.is_object() => {
+                if let Some(current) = self.ephemeral_data.as_object_mut() {
+                    for (key, value) in fetched {
+                        let old_value = current.insert(key.clone(), value.clone());
+                        changes.push(ChangeLog::new(
+                            format!("ephemeral_data.{}", key),
+                            "Fetched".to_string(),
+                            old_value,
+                            Some(value)
+                        ));
+                    }
+                }
+            }
+            (_, data) => {
+                if let Some(fetched) = data.as_object() {
+                    for (key, value) in fetched {
+                        changes.push(ChangeLog::new(
+                            format!("ephemeral_data.{}", key),
+                            "Fetched".to_string(),
+                            self.ephemeral_data.get(key).cloned(),
+                            Some(value.clone())
+                        ));
+                    }
+                }
+                self.ephemeral_data = data;
+            }
+        }
 
         // Create audit log
         let audit_log = AuditLog::new(
@@ -37,7 +91,7 @@ impl Message {
             task_id.to_string(),
             start_time,
             description.unwrap_or_else(|| "Fetch applied".to_string()),
-            vec![]
+            changes
         );
         self.audit.push(audit_log);
         self.version += 1;
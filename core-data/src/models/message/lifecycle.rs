@@ -0,0 +1,104 @@
+use time::OffsetDateTime;
+use tracing::{debug, error, warn};
+
+use super::{
+    core::Message,
+    errors::FunctionResponseError,
+    auditlog::{AuditLog, ChangeLog},
+    progress::{MessageStatus, Progress},
+};
+
+impl Message {
+    /// Moves the message to `status` at `task_id`, the only way its status changes. Every change
+    /// is audited under the current workflow, with `changes` recorded after the status change.
+    /// Staying in the same status is not a transition and is only audited when `changes` is not
+    /// empty. Fails with a 409 when the transition table does not allow the move.
+    pub fn transition(&mut self, status: MessageStatus, task_id: &str, description: String, changes: Vec<ChangeLog>) -> Result<(), FunctionResponseError> {
+        let unchanged = self.progress.status == status;
+        if !unchanged && !self.progress.status.can_transition_to(&status) {
+            warn!(
+                message_id = %self.id,
+                from = ?self.progress.status,
+                to = ?status,
+                task_id = %task_id,
+                "Rejected invalid status transition"
+            );
+            return Err(FunctionResponseError::new(
+                "Transition".to_string(),
+                409,
+                format!("Message cannot move from {:?} to {:?}", self.progress.status, status)
+            ));
+        }
+
+        let now = OffsetDateTime::now_utc();
+        let mut audit_changes = Vec::with_capacity(changes.len() + 1);
+        if !unchanged {
+            audit_changes.push(ChangeLog::new(
+                "progress.status".to_string(),
+                description.clone(),
+                serde_json::to_value(&self.progress.status).ok(),
+                serde_json::to_value(&status).ok()
+            ));
+        }
+        audit_changes.extend(changes);
+
+        debug!(from = ?self.progress.status, to = ?status, task_id = %task_id, "Message status transition");
+        self.progress.status = status;
+        self.progress.prev_task = task_id.to_string();
+        self.progress.timestamp = now;
+
+        if !audit_changes.is_empty() {
+            self.audit.push(AuditLog::new(
+                self.progress.workflow_id.clone(),
+                self.progress.workflow_version,
+                task_id.to_string(),
+                now,
+                description,
+                audit_changes
+            ));
+            self.version += 1;
+        }
+        Ok(())
+    }
+
+    /// Moves the message to `Failed`. Every status a task runs in may fail, so a refusal means
+    /// the caller ran a task the transition table does not allow, which is a bug.
+    pub(crate) fn fail(&mut self, task_id: &str, description: String, changes: Vec<ChangeLog>) {
+        let refused = self.transition(MessageStatus::Failed, task_id, description, changes).err();
+        if let Some(e) = &refused {
+            error!(error = %e, message_id = %self.id, task_id = %task_id, "Failed task left the message status unchanged");
+        }
+        debug_assert!(refused.is_none(), "Task '{}' failed in a status that cannot fail", task_id);
+    }
+
+    /// Withdraws the message before it finishes. Fails with a 409 once it is terminal.
+    pub fn cancel(&mut self, reason: String) -> Result<(), FunctionResponseError> {
+        let task_id = self.progress.prev_task.clone();
+        self.transition(MessageStatus::Cancelled, &task_id, reason, vec![])
+    }
+
+    /// Marks a failed message as corrected, so the task that failed runs again on the next
+    /// pass with a fresh retry budget. Fails with a 409 unless the message is `Failed`.
+    pub fn repair(&mut self, reason: String) -> Result<(), FunctionResponseError> {
+        let task_id = self.progress.prev_task.clone();
+        self.transition(MessageStatus::Repaired, &task_id, reason, vec![])?;
+        self.progress.attempt = 0;
+        self.progress.retry_at = None;
+        Ok(())
+    }
+
+    /// Puts `saved` progress back, keeping the current status, which only `transition` changes.
+    pub(crate) fn restore_progress(&mut self, saved: Progress) {
+        let status = std::mem::replace(&mut self.progress, saved).status;
+        self.progress.status = status;
+    }
+
+    /// Runs `f` with `progress` in place of the message's own, which is put back afterwards.
+    /// Returns what `f` returned and the progress it left behind.
+    pub(crate) fn with_progress<T>(&mut self, progress: Progress, f: impl FnOnce(&mut Self) -> T) -> (T, Progress) {
+        let own = std::mem::replace(&mut self.progress, progress);
+        let result = f(self);
+        let scoped = std::mem::replace(&mut self.progress, own);
+        (result, scoped)
+    }
+}
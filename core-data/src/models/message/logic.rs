@@ -1,19 +1,23 @@
-use serde_json::Value;
-use datalogic_rs::JsonLogic;
+use serde_json::{json, Value};
 use tracing::{debug, trace, instrument};
 use std::time::Instant;
 
+use crate::models::task::{JoinMode, Task};
+use crate::models::workflow::{ConditionContext, Workflow, WorkflowStatus};
+use crate::rules::{Rule, RuleEngine};
 use super::core::Message;
 use super::progress::MessageStatus;
-use super::progress::StatusCode;
+use super::progress::Token;
 
 impl Message {
-    #[instrument(skip(self, tenant, origin, condition), fields(
+    /// `combined` holds the combined condition context once built; see `condition_match`.
+    #[instrument(skip(self, tenant, origin, condition, combined), fields(
         message_id = %self.id,
         tenant = %tenant,
-        origin = %origin
+        origin = %origin,
+        context = ?context
     ))]
-    pub fn workflow_match(&self, tenant: &str, origin: &str, condition: &Value) -> bool {
+    pub fn workflow_match(&self, tenant: &str, origin: &str, condition: &Rule, context: &ConditionContext, combined: &mut Option<Value>) -> bool {
         let start = Instant::now();
 
         // Check tenant and origin match
@@ -26,25 +30,7 @@ impl Message {
             return false;
         }
 
-        // If condition is null or null-like, return true
-        if condition.is_null() {
-            debug!("No condition specified, automatic match");
-            return true;
-        }
-
-        // Evaluate condition against metadata
-        let logic = JsonLogic::new();
-        trace!(
-            condition = ?condition,
-            metadata = ?self.metadata,
-            "Evaluating workflow condition"
-        );
-
-        let result = logic
-            .apply(condition, &self.metadata)
-            .unwrap_or(Value::Bool(false));
-            
-        let matches = result.as_bool().unwrap_or(false);
+        let matches = self.condition_match(condition, context, combined);
         debug!(
             matches = matches,
             duration_ms = start.elapsed().as_millis(),
@@ -53,51 +39,142 @@ impl Message {
         matches
     }
 
-    #[instrument(skip(self, workflow_id, prev_task, condition), fields(
+    /// A message is pinned to a workflow version once it has moved past `Recieved`
+    /// or is waiting to retry its first task.
+    pub fn is_pinned(&self) -> bool {
+        self.progress.status != MessageStatus::Recieved || self.progress.attempt > 0
+    }
+
+    #[instrument(skip(self, workflows), fields(
+        message_id = %self.id,
+        workflow_count = workflows.len()
+    ))]
+    pub fn workflow_select<'a>(&self, workflows: &'a [Workflow]) -> Option<&'a Workflow> {
+        // In-flight messages resume on the exact version they started with
+        if self.is_pinned() {
+            let pinned = workflows.iter().find(|w| {
+                w.id == self.progress.workflow_id && w.version == self.progress.workflow_version
+            });
+            debug!(
+                workflow_id = %self.progress.workflow_id,
+                workflow_version = self.progress.workflow_version,
+                found = pinned.is_some(),
+                "Resolved pinned workflow version"
+            );
+            return pinned;
+        }
+
+        // New messages pick the latest active version of the first matching workflow
+        let mut combined = None;
+        let mut candidates = workflows.iter().filter(|w| {
+            w.status == WorkflowStatus::Active && !w.sub_workflow
+                && self.workflow_match(&w.tenant, &w.origin, &w.condition, &w.condition_context, &mut combined)
+        });
+        let first = candidates.next()?;
+        let latest = candidates
+            .filter(|w| w.id == first.id)
+            .fold(first, |latest, w| if w.version > latest.version { w } else { latest });
+
+        debug!(
+            workflow_id = %latest.id,
+            workflow_version = latest.version,
+            "Selected latest active workflow version"
+        );
+        Some(latest)
+    }
+
+    /// Whether `task` can run next: its predecessors have completed with the expected
+    /// status and status code (all of them for an `All` join) and the task has not run yet.
+    /// `combined` holds the combined condition context once built; see `condition_match`.
+    #[instrument(skip(self, workflow, task, combined), fields(
         message_id = %self.id,
-        workflow_id = %workflow_id,
-        status = ?status
+        workflow_id = %workflow.id,
+        task_id = %task.id
     ))]
-    pub fn task_match(&self, status: MessageStatus, workflow_id: &str, prev_task: &str, prev_status_code: Option<StatusCode>, condition: &Value) -> bool {
+    pub fn task_ready(&self, workflow: &Workflow, task: &Task, combined: &mut Option<Value>) -> bool {
         let start = Instant::now();
 
-        // Check basic conditions
-        if workflow_id != self.progress.workflow_id || 
-           prev_task != self.progress.prev_task || 
-           prev_status_code != self.progress.prev_status_code || 
-           status != self.progress.status {
+        if workflow.id != self.progress.workflow_id ||
+           workflow.version != self.progress.workflow_version ||
+           !self.progress.status.is_runnable() {
             trace!(
                 current_status = ?self.progress.status,
-                current_task = %self.progress.prev_task,
-                "Task match failed: status/task mismatch"
+                "Task not ready: workflow/status mismatch"
             );
             return false;
         }
 
+        // Messages from before fan-out support only carry the last completed task
+        let seed = [Token {
+            task: self.progress.prev_task.clone(),
+            status: self.progress.status.clone(),
+            status_code: self.progress.prev_status_code.clone(),
+            fields: Vec::new(),
+        }];
+        let tokens = if self.progress.tokens.is_empty() { &seed[..] } else { &self.progress.tokens[..] };
+
+        // Each task runs at most once per message
+        if tokens.iter().any(|t| t.task == task.id) {
+            return false;
+        }
+
+        let completed = |prev: &str| tokens.iter()
+            .any(|t| t.task == prev && t.status == task.message_status && t.status_code == task.prev_status_code);
+        let ready = match &task.join {
+            Some(join) if join.mode == JoinMode::All => join.tasks.iter().all(|prev| completed(prev)),
+            Some(join) => join.tasks.iter().any(|prev| completed(prev)),
+            None => completed(&task.prev_task),
+        };
+        if !ready {
+            trace!("Task not ready: waiting on predecessors");
+            return false;
+        }
+
+        let matches = self.condition_match(&task.condition, &workflow.condition_context, combined);
+        debug!(
+            matches = matches,
+            duration_ms = start.elapsed().as_millis(),
+            "Task readiness check completed"
+        );
+        matches
+    }
+
+    /// Data conditions can refer to: `{data, metadata, progress, payload_info, fetched}`.
+    pub fn condition_context(&self) -> Value {
+        json!({
+            "data": self.data,
+            "metadata": self.metadata,
+            "progress": self.progress,
+            "payload_info": self.payload.info(),
+            "fetched": self.ephemeral_data,
+        })
+    }
+
+    /// Evaluates `condition` against `context`. The combined context copies the whole message,
+    /// so it is built into `combined` the first time a condition needs it and reused until the
+    /// caller resets `combined` to `None` after changing the message.
+    fn condition_match(&self, condition: &Rule, context: &ConditionContext, combined: &mut Option<Value>) -> bool {
         // If condition is null or null-like, return true
         if condition.is_null() {
             debug!("No condition specified, automatic match");
             return true;
         }
 
-        // Evaluate condition against data
-        let logic = JsonLogic::new();
+        let context = match context {
+            ConditionContext::Metadata => &self.metadata,
+            ConditionContext::Combined => combined.get_or_insert_with(|| self.condition_context()),
+        };
+        let logic = RuleEngine::shared();
         trace!(
             condition = ?condition,
-            metadata = ?self.metadata,
-            "Evaluating task condition"
+            context = ?context,
+            "Evaluating condition"
         );
 
-        let result = logic
-            .apply(condition, &self.metadata)
-            .unwrap_or(Value::Bool(false));
-            
-        let matches = result.as_bool().unwrap_or(false);
-        debug!(
-            matches = matches,
-            duration_ms = start.elapsed().as_millis(),
-            "Task match completed"
-        );
-        matches
+        logic
+            .evaluate(condition, context)
+            .unwrap_or(Value::Bool(false))
+            .as_bool()
+            .unwrap_or(false)
     }
-}
\ No newline at end of file
+}
@@ -2,11 +2,16 @@ mod core;
 mod payload;
 mod auditlog;
 mod progress;
+mod lifecycle;
 mod parse;
 mod enrich;
 mod execute;
 mod logic;
 mod fetch;
+mod validate;
+mod approval;
+mod schedule;
+mod compensate;
 
 mod errors;
 mod iso20022;
@@ -14,5 +19,10 @@ mod iso20022;
 pub use self::core::Message;
 pub use self::payload::{Payload, PayloadFormat, PayloadSchema, Encoding, StorageType};
 pub use self::auditlog::{AuditLog, ChangeLog};
-pub use self::progress::{Progress, MessageStatus, StatusCode};
-pub use self::enrich::EnrichmentRules;
\ No newline at end of file
+pub use self::progress::{Progress, MessageStatus, StatusCode, Token};
+pub use self::enrich::EnrichmentRules;
+pub use self::validate::ValidationRule;
+pub use self::parse::PayloadError;
+pub use self::execute::{WorkflowOutcome, TIMEOUT_CODE};
+pub use self::approval::ApprovalDecision;
+pub use self::errors::{FunctionResponseError, WorkflowResponseError};
\ No newline at end of file
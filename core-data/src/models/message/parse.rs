@@ -1,6 +1,10 @@
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufReader, BufRead};
-use quick_xml::de::from_reader;
+use std::io::{BufReader, BufRead, Read};
+use std::ops::Range;
+use open_payments_common::ValidationError;
+use quick_xml::events::Event;
+use serde::Serialize;
 use time::OffsetDateTime;
 
 use super::{
@@ -13,21 +17,28 @@ use super::{
 use tracing::{debug, error, info, instrument};
 use std::time::Instant;
 
+/// Most schema rule violations reported for one payload.
+const MAX_SCHEMA_ERRORS: usize = 50;
+
+/// A problem with the payload document.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct PayloadError {
+    /// Element path of the problem, e.g. `FIToFICstmrCdtTrf.GrpHdr.MsgId`
+    pub path: String,
+
+    /// Code of the violated schema rule; absent when the document could not be read
+    pub code: Option<u32>,
+
+    pub message: String,
+}
+
 impl Message {
-    #[instrument(skip(self, description), fields(
-        workflow_id = %workflow_id,
-        task_id = %task_id
-    ))]
-    pub fn parse(&mut self, description: Option<String>, workflow_id: String, workflow_version: u16, task_id: String) -> Result<(), FunctionResponseError> {
+    fn payload_reader(&self) -> Result<Box<dyn BufRead + '_>, FunctionResponseError> {
         const BUFFER_SIZE: usize = 32 * 1024;
-        let start = Instant::now();
-        let start_time = OffsetDateTime::now_utc();
 
-        debug!("Starting message parsing");
-
-        let buf_reader: Box<dyn BufRead> = if let Some(content) = self.payload.content() {
+        if let Some(content) = self.payload.content() {
             debug!(size = content.len(), "Using inline content");
-            Box::new(BufReader::with_capacity(BUFFER_SIZE, content))
+            Ok(Box::new(BufReader::with_capacity(BUFFER_SIZE, content)))
         } else if let Some(ref url) = self.payload.url() {
             debug!(url = %url, "Opening file for parsing");
             let file = File::open(url).map_err(|e| {
@@ -38,63 +49,345 @@ impl Message {
                     format!("File open error: {:?}", e)
                 )
             })?;
-            Box::new(BufReader::with_capacity(BUFFER_SIZE, file))
+            Ok(Box::new(BufReader::with_capacity(BUFFER_SIZE, file)))
         } else {
             error!("No content or URL provided");
-            return Err(FunctionResponseError::new(
+            Err(FunctionResponseError::new(
                 "Parse".to_string(),
                 400,
                 "No content or URL provided".to_string()
-            ));
+            ))
+        }
+    }
+
+    /// Reads the payload and checks it against the schema rules, returning every violation.
+    fn read_document(&self, reader: Box<dyn BufRead + '_>) -> Result<ISO20022Message, Vec<PayloadError>> {
+        let message = deserialize_document(reader).map_err(|e| vec![PayloadError {
+            path: e.path().to_string(),
+            code: None,
+            message: e.inner().to_string(),
+        }])?;
+
+        debug!("Message parsed, validating schema");
+        let Err(first) = message.validate() else {
+            return Ok(message);
         };
+        // Read again to locate the violations, which only invalid payloads pay for
+        let mut xml = String::new();
+        match self.payload_reader().map(|mut reader| reader.read_to_string(&mut xml)) {
+            Ok(Ok(_)) => Err(schema_errors(&xml, first)),
+            _ => Err(vec![unlocated(first)]),
+        }
+    }
+
+    /// Parses and validates the payload like a `Parse` task would, without changing the message.
+    #[instrument(skip(self), fields(message_id = %self.id))]
+    pub fn validate_payload(&self) -> Result<(), Vec<PayloadError>> {
+        let reader = self.payload_reader().map_err(|e| vec![PayloadError {
+            path: String::new(),
+            code: None,
+            message: e.message,
+        }])?;
+        self.read_document(reader).map(|_| ()).map_err(|errors| {
+            debug!(errors = errors.len(), path = %errors[0].path, error = %errors[0].message, "Payload is invalid");
+            errors
+        })
+    }
 
-        match from_reader::<_, ISO20022Message>(buf_reader) {
+    #[instrument(skip(self, description), fields(
+        workflow_id = %workflow_id,
+        task_id = %task_id
+    ))]
+    pub fn parse(&mut self, description: Option<String>, workflow_id: String, workflow_version: u16, task_id: String) -> Result<(), FunctionResponseError> {
+        let start = Instant::now();
+        let start_time = OffsetDateTime::now_utc();
+
+        debug!("Starting message parsing");
+
+        let buf_reader = self.payload_reader()?;
+        match self.read_document(buf_reader) {
             Ok(message) => {
-                debug!("Message parsed, validating schema");
-                match message.validate() {
-                    Ok(()) => {
-                        self.data = serde_json::to_value(message).unwrap();
-                        let change_log = ChangeLog::new(
-                            "data".to_string(),
-                            "ISO20022 message parsed".to_string(),
-                            None,
-                            None
-                        );
-                        let audit_log = AuditLog::new(
-                            workflow_id.to_string(),
-                            workflow_version,
-                            task_id.to_string(),
-                            start_time,
-                            description.unwrap_or_else(|| "ISO20022 message parsed".to_string()),
-                            vec![change_log]
-                        );
-                        self.audit.push(audit_log);
-                        self.version += 1;
-
-                        info!(
-                            duration_ms = start.elapsed().as_millis(),
-                            "Successfully parsed message"
-                        );
-                        Ok(())
-                    }
-                    Err(validation_error) => {
-                        error!(error = ?validation_error, "Schema validation failed");
-                        Err(FunctionResponseError::new(
-                            "Parse".to_string(),
-                            400,
-                            format!("Schema validation error: {:?}", validation_error)
-                        ))
-                    }
-                }
+                self.data = serde_json::to_value(message).unwrap();
+                let change_log = ChangeLog::new(
+                    "data".to_string(),
+                    "ISO20022 message parsed".to_string(),
+                    None,
+                    None
+                );
+                let audit_log = AuditLog::new(
+                    workflow_id.to_string(),
+                    workflow_version,
+                    task_id.to_string(),
+                    start_time,
+                    description.unwrap_or_else(|| "ISO20022 message parsed".to_string()),
+                    vec![change_log]
+                );
+                self.audit.push(audit_log);
+                self.version += 1;
+
+                info!(
+                    duration_ms = start.elapsed().as_millis(),
+                    "Successfully parsed message"
+                );
+                Ok(())
+            }
+            Err(errors) if errors[0].code.is_some() => {
+                let described: Vec<String> = errors.iter().map(|e| format!("{} at '{}'", e.message, e.path)).collect();
+                error!(errors = ?described, "Schema validation failed");
+                Err(FunctionResponseError::new(
+                    "Parse".to_string(),
+                    400,
+                    format!("Schema validation error: {}", described.join("; "))
+                ))
             }
-            Err(e) => {
-                error!(error = ?e, "Failed to parse ISO20022 message");
+            Err(mut errors) => {
+                let PayloadError { path, message, .. } = errors.remove(0);
+                error!(path = %path, error = %message, "Failed to parse ISO20022 message");
                 Err(FunctionResponseError::new(
                     "Parse".to_string(),
                     400,
-                    format!("ISO20022 parsing error: {:?}", e)
+                    format!("ISO20022 parsing error at '{}': {}", path, message)
                 ))
             }
         }
     }
-}
\ No newline at end of file
+}
+
+fn deserialize_document(reader: Box<dyn BufRead + '_>) -> Result<ISO20022Message, serde_path_to_error::Error<quick_xml::DeError>> {
+    let mut deserializer = quick_xml::de::Deserializer::from_reader(reader);
+    serde_path_to_error::deserialize(&mut deserializer)
+}
+
+/// The first rule violation of a document, or `None` when it no longer reads as one.
+fn first_violation(xml: &str) -> Option<Result<(), ValidationError>> {
+    deserialize_document(Box::new(xml.as_bytes())).ok().map(|message| message.validate())
+}
+
+/// The field a rule message is about; rule messages start with it.
+fn rule_field(rule: &ValidationError) -> &str {
+    rule.message.split_whitespace().next().unwrap_or_default()
+}
+
+fn violates(xml: &str, rule: &ValidationError) -> bool {
+    matches!(first_violation(xml), Some(Err(e)) if e.code == rule.code && e.message == rule.message)
+}
+
+/// A violation whose element could not be told apart, reported under the field name.
+fn unlocated(rule: ValidationError) -> PayloadError {
+    PayloadError {
+        path: rule_field(&rule).to_string(),
+        code: Some(rule.code),
+        message: rule.message,
+    }
+}
+
+/// Text of a leaf element or an attribute value in the payload.
+struct XmlValue {
+    /// Element names below `Document`, with the position among same-named siblings, then
+    /// `@name` for an attribute
+    path: Vec<(String, usize)>,
+
+    /// Byte range of the escaped text
+    text: Range<usize>,
+
+    /// Byte range of the whole element; for an attribute, that of its element
+    element: Range<usize>,
+}
+
+/// An element whose end tag has not been read yet.
+struct OpenElement {
+    path: Vec<(String, usize)>,
+    start: usize,
+    content: usize,
+    has_children: bool,
+}
+
+/// Every leaf element text and attribute value of the payload, in document order.
+fn xml_values(xml: &str) -> Vec<XmlValue> {
+    let offset = |slice: &[u8]| slice.as_ptr() as usize - xml.as_ptr() as usize;
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut values = Vec::new();
+    let mut open: Vec<OpenElement> = Vec::new();
+    let mut siblings: Vec<HashMap<String, usize>> = vec![HashMap::new()];
+    loop {
+        let before = reader.buffer_position();
+        let (start, empty) = match reader.read_event() {
+            Ok(Event::Start(start)) => (start, false),
+            Ok(Event::Empty(start)) => (start, true),
+            Ok(Event::End(_)) => {
+                if let Some(element) = open.pop().filter(|element| !element.has_children) {
+                    values.push(XmlValue {
+                        path: element.path,
+                        text: element.content..before,
+                        element: element.start..reader.buffer_position(),
+                    });
+                }
+                siblings.pop();
+                continue;
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            Ok(_) => continue,
+        };
+
+        let name = String::from_utf8_lossy(start.local_name().as_ref()).into_owned();
+        if let Some(parent) = open.last_mut() {
+            parent.has_children = true;
+        }
+        let index = siblings.last_mut().map_or(0, |counts| {
+            let count = counts.entry(name.clone()).or_default();
+            *count += 1;
+            *count - 1
+        });
+        // The `Document` root is left out of paths
+        let mut path = open.last().map(|parent| parent.path.clone()).unwrap_or_default();
+        if !open.is_empty() {
+            path.push((name, index));
+        }
+        for attribute in start.attributes().flatten() {
+            let name = String::from_utf8_lossy(attribute.key.local_name().as_ref()).into_owned();
+            if let std::borrow::Cow::Borrowed(value) = attribute.value {
+                let mut path = path.clone();
+                path.push((format!("@{}", name), 0));
+                values.push(XmlValue { path, text: offset(value)..offset(value) + value.len(), element: before..before });
+            }
+        }
+        if !empty {
+            open.push(OpenElement { path, start: before, content: reader.buffer_position(), has_children: false });
+            siblings.push(HashMap::new());
+        }
+    }
+
+    // Attributes are removed with their element
+    for index in 0..values.len() {
+        if values[index].element.is_empty() {
+            let parent = &values[index].path[..values[index].path.len() - 1];
+            let start = values[index].element.start;
+            let element = values.iter().find(|v| v.path == parent && v.element.start == start).map(|v| v.element.clone());
+            values[index].element = element.unwrap_or(start..start);
+        }
+    }
+    values
+}
+
+/// Formats a path like `serde_path_to_error` does for malformed documents, with a position
+/// only for elements that repeat.
+fn display_path(path: &[(String, usize)], values: &[XmlValue]) -> String {
+    let mut display = String::new();
+    for (depth, (name, index)) in path.iter().enumerate() {
+        if depth > 0 {
+            display.push('.');
+        }
+        display.push_str(name);
+        let repeats = values.iter().any(|v| v.path.len() > depth && v.path[..depth] == path[..depth] && v.path[depth].0 == *name && v.path[depth].1 > 0);
+        if repeats {
+            display.push_str(&format!("[{}]", index));
+        }
+    }
+    display
+}
+
+/// Whether an element or attribute name, e.g. `CdtTrfTxInf` or `@Ccy`, is the field a rule
+/// message names, e.g. `cdt_trf_tx_inf`.
+fn names_field(name: &str, field: &str) -> bool {
+    let normalize = |s: &str| s.chars().filter(char::is_ascii_alphanumeric).map(|c| c.to_ascii_lowercase()).collect::<String>();
+    normalize(name) == normalize(field)
+}
+
+/// The document with the given byte ranges replaced.
+fn splice(xml: &str, mut edits: Vec<(Range<usize>, &str)>) -> String {
+    edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    let mut changed = xml.to_string();
+    for (range, text) in edits {
+        changed.replace_range(range, text);
+    }
+    changed
+}
+
+/// The value closest to `text` that meets a length or range rule, whose limit ends the rule
+/// message. Pattern rules (1005) carry no pattern, so there is none for them.
+fn clearing_value(text: &str, rule: &ValidationError) -> Option<String> {
+    let limit: f64 = rule.message.rsplit(' ').next()?.parse().ok()?;
+    let text = quick_xml::escape::unescape(text).ok()?;
+    let cleared = match rule.code {
+        1001 => format!("{:X<width$}", text, width = limit as usize),
+        1002 => text.chars().take(limit as usize).collect(),
+        1003 | 1004 => limit.to_string(),
+        _ => return None,
+    };
+    Some(quick_xml::escape::escape(&cleared).into_owned())
+}
+
+/// Which of the `candidates` break `rule`, and a text that does not, if one is known.
+///
+/// The validator names only the field, so the candidates are every value of a field by that
+/// name. Setting them all to a text that clears the rule, then restoring one at a time,
+/// shows which break it. Fields of the same name that cannot hold the text, e.g. code lists,
+/// are left as they are.
+fn locate<'a>(xml: &str, candidates: &[&'a XmlValue], rule: &ValidationError) -> (Vec<&'a XmlValue>, Option<String>) {
+    let with = |values: &[&XmlValue], text: &str| splice(xml, values.iter().map(|v| (v.text.clone(), text)).collect());
+    let texts: Vec<&str> = candidates.iter().map(|v| &xml[v.text.clone()]).collect();
+    let clearing: Vec<String> = texts.iter().filter_map(|text| clearing_value(text, rule)).collect();
+
+    for valid in texts.iter().copied().chain(clearing.iter().map(String::as_str)) {
+        let holding: Vec<&XmlValue> = candidates.iter().copied()
+            .filter(|v| first_violation(&with(&[v], valid)).is_some())
+            .collect();
+        // A text that breaks another rule of the field, e.g. a shorter limit elsewhere, would
+        // hide the violations instead of clearing them
+        let cleared = with(&holding, valid);
+        match first_violation(&cleared) {
+            Some(Ok(())) => {}
+            Some(Err(e)) if rule_field(&e) != rule_field(rule) => {}
+            _ => continue,
+        }
+        let others = |value: &XmlValue| holding.iter().copied().filter(|v| v.text != value.text).collect::<Vec<_>>();
+        let culprits = holding.iter().copied()
+            .filter(|value| violates(&with(&others(value), valid), rule))
+            .collect();
+        return (culprits, Some(valid.to_string()));
+    }
+    // Nothing clears the rule; only a single candidate is certain to break it
+    match candidates {
+        [only] => (vec![*only], None),
+        _ => (Vec::new(), None),
+    }
+}
+
+/// Every schema rule violation of the document with the path of the offending value. The
+/// validator stops at the first violation, so each one found is taken out of the document
+/// and the rules are run again.
+fn schema_errors(xml: &str, first: ValidationError) -> Vec<PayloadError> {
+    let mut xml = xml.to_string();
+    let mut errors: Vec<PayloadError> = Vec::new();
+    let mut rule = first;
+    while errors.len() < MAX_SCHEMA_ERRORS {
+        let values = xml_values(&xml);
+        let candidates: Vec<&XmlValue> = values.iter()
+            .filter(|v| v.path.last().is_some_and(|(name, _)| names_field(name, rule_field(&rule))))
+            .collect();
+        let (culprits, valid) = locate(&xml, &candidates, &rule);
+        if culprits.is_empty() {
+            errors.push(unlocated(rule));
+            break;
+        }
+        let new: Vec<String> = culprits.iter().map(|v| display_path(&v.path, &values))
+            .filter(|path| !errors.iter().any(|e| e.path == *path && e.message == rule.message))
+            .collect();
+        if new.is_empty() {
+            break;
+        }
+        errors.extend(new.into_iter().map(|path| PayloadError { path, code: Some(rule.code), message: rule.message.clone() }));
+
+        // Replaced with a text that clears the rule, or dropped when there is none, so the
+        // rules run on to the next violation
+        xml = match &valid {
+            Some(valid) => splice(&xml, culprits.iter().map(|v| (v.text.clone(), valid.as_str())).collect()),
+            None => splice(&xml, culprits.iter().map(|v| (v.element.clone(), "")).collect()),
+        };
+        match first_violation(&xml) {
+            Some(Err(next)) => rule = next,
+            _ => break,
+        }
+    }
+    errors
+}
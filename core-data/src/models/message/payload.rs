@@ -34,6 +34,22 @@ impl Payload {
         self.url.as_deref()
     }
 
+    /// Everything about the payload except its content.
+    pub fn info(&self) -> serde_json::Value {
+        let size = match &self.content {
+            Some(content) => content.len() as i64,
+            None => self.size,
+        };
+        serde_json::json!({
+            "storage": self.storage,
+            "url": self.url,
+            "format": self.format,
+            "schema": self.schema,
+            "encoding": self.encoding,
+            "size": size,
+        })
+    }
+
     pub fn new_inline(content: Option<Vec<u8>>, format: PayloadFormat, schema: PayloadSchema, encoding: Encoding) -> Self {
         let content = content.map(|v| v.into_boxed_slice());
         Self {
@@ -7,12 +7,41 @@ pub struct Progress {
 
     pub workflow_id: String,
 
+    #[serde(default)]
+    pub workflow_version: u16,
+
     pub prev_task: String,
     
     pub prev_status_code: Option<StatusCode>,
     
     #[serde(with = "time::serde::iso8601")]
     pub timestamp: OffsetDateTime,
+
+    /// Retries already made for the next task
+    #[serde(default)]
+    pub attempt: u32,
+
+    #[serde(default, with = "time::serde::iso8601::option")]
+    pub retry_at: Option<OffsetDateTime>,
+
+    /// One token per task completed in the current workflow run. Parallel branches each
+    /// add their own, and a task is ready once the tokens it waits on exist.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tokens: Vec<Token>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Token {
+    pub task: String,
+
+    /// Status the task left the message in, matched against its successors' `message_status`
+    pub status: MessageStatus,
+
+    pub status_code: Option<StatusCode>,
+
+    /// Fields the task changed, used to detect conflicting writes between branches
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -21,11 +50,48 @@ pub enum MessageStatus {
     Processing,
     Completed,
     Failed,
+    /// Parked by an `Approval` task until someone approves or rejects it
+    Suspended,
+    /// Warehoused by a `Schedule` task until its due time
+    Scheduled,
+    /// Withdrawn before it finished processing
+    Cancelled,
+    /// Turned down by an approver or a downstream party
+    Rejected,
+    /// Corrected after a failure and ready to be processed again
+    Repaired,
+}
+
+impl MessageStatus {
+    /// The lifecycle transition table. `Completed`, `Cancelled` and `Rejected` are terminal,
+    /// and a `Failed` message can only be repaired or cancelled.
+    pub fn can_transition_to(&self, next: &MessageStatus) -> bool {
+        use MessageStatus::*;
+        if self.is_runnable() {
+            return !matches!(next, Recieved | Repaired);
+        }
+        match self {
+            Suspended => matches!(next, Processing | Failed | Cancelled | Rejected),
+            Scheduled => matches!(next, Processing | Failed | Cancelled),
+            Failed => matches!(next, Repaired | Cancelled),
+            _ => false,
+        }
+    }
+
+    /// Statuses tasks run in. Only these may move to any status a task can return.
+    pub fn is_runnable(&self) -> bool {
+        matches!(self, MessageStatus::Recieved | MessageStatus::Processing | MessageStatus::Repaired)
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, MessageStatus::Completed | MessageStatus::Cancelled | MessageStatus::Rejected)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum StatusCode {
     Success,
     Failure,
+    Timeout,
 }
 
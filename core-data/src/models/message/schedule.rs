@@ -0,0 +1,92 @@
+use serde_json::Value;
+use time::format_description::{self, well_known::Iso8601};
+use time::{Date, Duration, OffsetDateTime, Time};
+use tracing::{info, instrument};
+
+use crate::models::task::{ScheduleSpec, Task};
+use crate::rules::RuleEngine;
+use super::{
+    core::Message,
+    errors::FunctionResponseError,
+    execute::TIMEOUT_CODE,
+    progress::{MessageStatus, StatusCode, Token},
+};
+
+impl Message {
+    /// Instant a `Schedule` task releases the message, which may already have passed.
+    pub fn scheduled_for(&self, task: &Task) -> Result<OffsetDateTime, FunctionResponseError> {
+        let spec: ScheduleSpec = serde_json::from_value(task.input.clone())
+            .map_err(|e| schedule_error(format!("Invalid schedule: {}", e)))?;
+
+        match spec {
+            ScheduleSpec::At(logic) => {
+                let value = RuleEngine::shared()
+                    .evaluate(&logic, &self.condition_context())
+                    .map_err(|e| if e.is_timeout() {
+                        FunctionResponseError::new("Schedule".to_string(), TIMEOUT_CODE, e.to_string())
+                    } else {
+                        schedule_error(format!("Schedule expression failed: {}", e))
+                    })?;
+                parse_instant(&value)
+            }
+            ScheduleSpec::Cutoff(time_of_day) => {
+                let cutoff = ["[hour]:[minute]:[second]", "[hour]:[minute]"].iter()
+                    .filter_map(|format| format_description::parse_borrowed::<2>(format).ok())
+                    .find_map(|format| Time::parse(&time_of_day, &format).ok())
+                    .ok_or_else(|| schedule_error(format!("Invalid cut-off time '{}'", time_of_day)))?;
+                let now = OffsetDateTime::now_utc();
+                let today = now.replace_time(cutoff);
+                Ok(if today > now { today } else { today + Duration::days(1) })
+            }
+        }
+    }
+
+    /// Warehouses the message at `task` until `wake` is called.
+    pub fn schedule(&mut self, task: &Task, due_at: OffsetDateTime) -> Result<(), FunctionResponseError> {
+        self.transition(MessageStatus::Scheduled, &task.id, format!("{} (until {})", task.description, due_at), vec![])?;
+        self.progress.prev_status_code = None;
+        Ok(())
+    }
+
+    /// Completes the `Schedule` task the message is waiting on, so the workflow continues from it.
+    #[instrument(skip(self), fields(message_id = %self.id))]
+    pub fn wake(&mut self) -> Result<(), FunctionResponseError> {
+        if self.progress.status != MessageStatus::Scheduled {
+            return Err(schedule_error(format!("Message is {:?}, not scheduled", self.progress.status)));
+        }
+
+        let task_id = self.progress.prev_task.clone();
+        self.transition(MessageStatus::Processing, &task_id, "Released for execution".to_string(), vec![])?;
+        self.progress.prev_status_code = Some(StatusCode::Success);
+        self.progress.tokens.push(Token {
+            task: task_id,
+            status: MessageStatus::Processing,
+            status_code: Some(StatusCode::Success),
+            fields: Vec::new(),
+        });
+
+        info!(task_id = %self.progress.prev_task, "Scheduled message released");
+        Ok(())
+    }
+}
+
+fn schedule_error(message: String) -> FunctionResponseError {
+    FunctionResponseError::new("Schedule".to_string(), 400, message)
+}
+
+/// Accepts ISO 8601 date-times, plain dates (midnight UTC) and Unix seconds.
+fn parse_instant(value: &Value) -> Result<OffsetDateTime, FunctionResponseError> {
+    match value {
+        Value::String(text) => OffsetDateTime::parse(text, &Iso8601::DEFAULT)
+            .ok()
+            .or_else(|| {
+                let format = format_description::parse_borrowed::<2>("[year]-[month]-[day]").ok()?;
+                Date::parse(text, &format).ok().map(|date| date.midnight().assume_utc())
+            })
+            .ok_or_else(|| schedule_error(format!("Invalid schedule instant '{}'", text))),
+        Value::Number(seconds) => seconds.as_i64()
+            .and_then(|seconds| OffsetDateTime::from_unix_timestamp(seconds).ok())
+            .ok_or_else(|| schedule_error(format!("Invalid schedule timestamp {}", seconds))),
+        other => Err(schedule_error(format!("Schedule expression returned {}", other))),
+    }
+}
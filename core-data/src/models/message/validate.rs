@@ -0,0 +1,83 @@
+use time::OffsetDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{debug, info, instrument, warn};
+use std::time::Instant;
+
+use crate::rules::{Rule, RuleEngine};
+
+use super::{
+    core::Message,
+    errors::FunctionResponseError,
+    auditlog::AuditLog,
+    execute::TIMEOUT_CODE,
+};
+
+/// A rule the message must satisfy, evaluated against the combined condition context.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ValidationRule {
+    pub rule: Rule,
+    pub message: String,
+}
+
+impl Message {
+    #[instrument(skip(self, rules, description), fields(
+        workflow_id = %workflow_id,
+        task_id = %task_id,
+        rule_count = rules.len()
+    ))]
+    pub fn validate(
+        &mut self,
+        rules: Vec<ValidationRule>,
+        description: Option<String>,
+        workflow_id: String,
+        workflow_version: u16,
+        task_id: String
+    ) -> Result<(), FunctionResponseError> {
+        let start = Instant::now();
+        let start_time = OffsetDateTime::now_utc();
+        let engine = RuleEngine::shared();
+        let context = self.condition_context();
+
+        debug!("Starting validation");
+
+        // Every rule is checked so the caller sees all failures at once
+        let mut failures = Vec::new();
+        for rule in &rules {
+            match engine.evaluate(&rule.rule, &context) {
+                Ok(Value::Bool(true)) => {}
+                Ok(_) => failures.push(rule.message.clone()),
+                Err(e) if e.is_timeout() => {
+                    return Err(FunctionResponseError::new("Validate".to_string(), TIMEOUT_CODE, e.to_string()));
+                }
+                Err(e) => failures.push(format!("{} ({})", rule.message, e)),
+            }
+        }
+
+        if !failures.is_empty() {
+            warn!(failure_count = failures.len(), "Validation failed");
+            return Err(FunctionResponseError::new(
+                "Validate".to_string(),
+                422,
+                failures.join("; ")
+            ));
+        }
+
+        let audit_log = AuditLog::new(
+            workflow_id,
+            workflow_version,
+            task_id,
+            start_time,
+            description.unwrap_or_else(|| "Validation passed".to_string()),
+            Vec::new()
+        );
+        self.audit.push(audit_log);
+        self.version += 1;
+
+        info!(
+            duration_ms = start.elapsed().as_millis(),
+            "Validation completed successfully"
+        );
+        Ok(())
+    }
+}
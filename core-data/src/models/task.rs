@@ -1,9 +1,10 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::models::message::MessageStatus;
 use crate::models::message::StatusCode;
+use crate::rules::Rule;
 
-#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Task {
     pub id: String,
 
@@ -13,23 +14,155 @@ pub struct Task {
 
     pub message_status: MessageStatus, 
     
+    /// Single predecessor; left empty for join tasks
+    #[serde(default)]
     pub prev_task: String,
     
     pub prev_status_code: Option<StatusCode>,
 
-    pub condition: serde_json::Value,
+    pub condition: Rule,
 
     pub function: FunctionType,
 
     pub input: serde_json::Value,
+
+    #[serde(default)]
+    pub retry: Option<RetryPolicy>,
+
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+
+    /// Waits on several parallel branches instead of a single `prev_task`
+    #[serde(default)]
+    pub join: Option<Join>,
+
+    /// Undoes the task's side effects when a later task fails the workflow
+    #[serde(default)]
+    pub compensation: Option<Compensation>,
+}
+
+impl Task {
+    /// Tasks (or the initiating step) this task waits on.
+    pub fn predecessors(&self) -> Vec<&str> {
+        match &self.join {
+            Some(join) => join.tasks.iter().map(String::as_str).collect(),
+            None => vec![self.prev_task.as_str()],
+        }
+    }
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Compensation {
+    pub description: String,
+
+    pub function: FunctionType,
+
+    pub input: serde_json::Value,
+}
+
+impl Compensation {
+    /// Task that runs this compensation on behalf of `task`.
+    pub fn task(&self, task: &Task) -> Task {
+        Task {
+            id: format!("{}.compensation", task.id),
+            description: self.description.clone(),
+            function: self.function.clone(),
+            input: self.input.clone(),
+            retry: None,
+            timeout_ms: None,
+            join: None,
+            compensation: None,
+            ..task.clone()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Join {
+    pub tasks: Vec<String>,
+
+    pub mode: JoinMode,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum JoinMode {
+    /// Every branch must have completed
+    All,
+    /// The first completed branch triggers the task
+    Any,
+}
 
-#[derive(Debug, Deserialize, Clone, PartialEq)]
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum FunctionType {
     Parse,
     Validate,
     Fetch,
     Enrich,
     Publish,
+    SubWorkflow,
+    /// Suspends the message until it is approved or rejected
+    Approval,
+    /// Warehouses the message until a future instant
+    Schedule,
+}
+
+/// Input of a `Schedule` task: when the message may continue.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleSpec {
+    /// JSONLogic over the combined condition context returning an ISO 8601 date or date-time, or Unix seconds
+    At(Rule),
+    /// Next occurrence of a UTC time of day (`HH:MM` or `HH:MM:SS`), e.g. a batch cut-off
+    Cutoff(String),
+}
+
+/// Input of a `SubWorkflow` task: the workflow version it runs against the same message.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SubWorkflowRef {
+    pub workflow_id: String,
+
+    pub version: u16,
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Total attempts including the first one
+    pub max_attempts: u32,
+
+    pub backoff: BackoffStrategy,
+
+    /// Delay before the first retry; doubled per attempt for exponential backoff
+    #[serde(default)]
+    pub delay_ms: u64,
+
+    #[serde(default)]
+    pub max_delay_ms: Option<u64>,
+
+    /// Function error codes that may be retried. Empty retries every error.
+    #[serde(default)]
+    pub retryable_codes: Vec<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum BackoffStrategy {
+    Fixed,
+    Exponential,
+}
+
+impl RetryPolicy {
+    pub fn is_retryable(&self, code: u32) -> bool {
+        self.retryable_codes.is_empty() || self.retryable_codes.contains(&code)
+    }
+
+    /// Delay before retry number `attempt` (1 for the first retry).
+    pub fn delay_ms(&self, attempt: u32) -> u64 {
+        let delay = match self.backoff {
+            BackoffStrategy::Fixed => self.delay_ms,
+            BackoffStrategy::Exponential => self.delay_ms
+                .saturating_mul(2u64.saturating_pow(attempt.saturating_sub(1))),
+        };
+        self.max_delay_ms.map_or(delay, |max| delay.min(max))
+    }
 }
@@ -1,8 +1,12 @@
-use serde::Deserialize;
+use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use crate::models::task::*;
+use crate::models::message::{EnrichmentRules, ValidationRule};
+use crate::rules::Rule;
 
 
-#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Workflow {
     pub id: String,
 
@@ -18,21 +22,244 @@ pub struct Workflow {
 
     pub status: WorkflowStatus,
 
-    pub condition: serde_json::Value,
+    pub condition: Rule,
 
     pub tasks: Vec<Task>,
 
     pub input_topic: String,
 
     pub persist_on_complete: bool,
+
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+
+    #[serde(default)]
+    pub condition_context: ConditionContext,
+
+    /// Only run through `SubWorkflow` tasks; never selected for new messages
+    #[serde(default)]
+    pub sub_workflow: bool,
 }
 
 
-#[derive(Debug, Deserialize, Clone, PartialEq)]
+impl Workflow {
+    /// Checks the definition and its task graph, returning every problem found.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.id.trim().is_empty() {
+            errors.push("Workflow id must not be empty".to_string());
+        }
+        if self.input_topic.trim().is_empty() {
+            errors.push("Workflow input_topic must not be empty".to_string());
+        }
+
+        rule_errors(&mut errors, "Workflow condition", &self.condition);
+
+        let mut task_ids = HashSet::new();
+        for task in &self.tasks {
+            if task.id.trim().is_empty() {
+                errors.push("Task id must not be empty".to_string());
+            } else if !task_ids.insert(task.id.as_str()) {
+                errors.push(format!("Duplicate task id '{}'", task.id));
+            }
+
+            rule_errors(&mut errors, &format!("Task '{}' condition", task.id), &task.condition);
+            input_rule_errors(&mut errors, &format!("Task '{}'", task.id), &task.function, &task.input);
+
+            if task.function == FunctionType::SubWorkflow {
+                match serde_json::from_value::<SubWorkflowRef>(task.input.clone()) {
+                    Ok(target) if target.workflow_id == self.id => {
+                        errors.push(format!("Task '{}' invokes its own workflow", task.id));
+                    }
+                    Ok(_) => {}
+                    Err(_) => errors.push(format!("Task '{}' input is not a sub-workflow reference", task.id)),
+                }
+            }
+
+            if let Some(compensation) = &task.compensation {
+                // Compensations run inside the engine, so they cannot park the message or leave it
+                if matches!(compensation.function, FunctionType::Approval | FunctionType::Schedule | FunctionType::SubWorkflow | FunctionType::Publish) {
+                    errors.push(format!("Task '{}' compensation cannot be a {:?} function", task.id, compensation.function));
+                }
+                input_rule_errors(&mut errors, &format!("Task '{}' compensation", task.id), &compensation.function, &compensation.input);
+            }
+        }
+
+        for task in &self.tasks {
+            match &task.join {
+                Some(join) => {
+                    if !task.prev_task.is_empty() {
+                        errors.push(format!("Join task '{}' must not set prev_task", task.id));
+                    }
+                    if join.tasks.is_empty() {
+                        errors.push(format!("Join task '{}' waits on no tasks", task.id));
+                    }
+                    for prev in &join.tasks {
+                        if !task_ids.contains(prev.as_str()) {
+                            errors.push(format!("Join task '{}' waits on unknown task '{}'", task.id, prev));
+                        }
+                    }
+                }
+                None if task.prev_task.trim().is_empty() => {
+                    errors.push(format!("Task '{}' has no prev_task", task.id));
+                }
+                None => {}
+            }
+        }
+
+        // Tasks whose prev_task is not another task are entry points
+        if !self.tasks.is_empty() && self.tasks.iter().all(|t| t.join.is_some() || task_ids.contains(t.prev_task.as_str())) {
+            errors.push("Workflow has no entry task".to_string());
+        }
+
+        // Following predecessors from any task must never loop back on itself
+        for task in &self.tasks {
+            if self.is_ancestor(&task.id, &task.id) {
+                errors.push(format!("Task '{}' has a cyclic prev_task chain", task.id));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Field-level changes from this definition to `other`. Tasks are matched by id,
+    /// so reordering them is not a change.
+    pub fn diff(&self, other: &Workflow) -> Vec<WorkflowChange> {
+        let mut changes = Vec::new();
+        diff_values("", &Self::comparable(self), &Self::comparable(other), &mut changes);
+        changes
+    }
+
+    fn comparable(workflow: &Workflow) -> Value {
+        let mut value = serde_json::to_value(workflow).unwrap_or_default();
+        if let Some(tasks) = value.get_mut("tasks") {
+            let by_id: Map<String, Value> = tasks.as_array().into_iter().flatten()
+                .map(|task| (task["id"].as_str().unwrap_or_default().to_string(), task.clone()))
+                .collect();
+            *tasks = Value::Object(by_id);
+        }
+        value
+    }
+
+    pub fn task(&self, task_id: &str) -> Option<&Task> {
+        self.tasks.iter().find(|t| t.id == task_id)
+    }
+
+    /// Whether `ancestor` is reachable by walking back through the predecessors of `task_id`.
+    pub fn is_ancestor(&self, ancestor: &str, task_id: &str) -> bool {
+        let predecessors = |id: &str| self.tasks.iter()
+            .filter(|t| t.id == id)
+            .flat_map(|t| t.predecessors())
+            .collect::<Vec<_>>();
+
+        let mut seen = HashSet::new();
+        let mut pending = predecessors(task_id);
+        while let Some(current) = pending.pop() {
+            if current == ancestor {
+                return true;
+            }
+            if seen.insert(current) {
+                pending.extend(predecessors(current));
+            }
+        }
+        false
+    }
+}
+
+/// Adds `rule`'s compile errors, prefixed with `context`.
+fn rule_errors(errors: &mut Vec<String>, context: &str, rule: &Rule) {
+    errors.extend(rule.errors().iter().map(|e| format!("{}: {}", context, e)));
+}
+
+/// Checks that the input of an `Enrich`, `Validate` or `Schedule` function parses and that its
+/// rules compiled.
+fn input_rule_errors(errors: &mut Vec<String>, context: &str, function: &FunctionType, input: &Value) {
+    match function {
+        FunctionType::Enrich => match serde_json::from_value::<Vec<EnrichmentRules>>(input.clone()) {
+            Ok(rules) => rules.iter()
+                .for_each(|rule| rule_errors(errors, &format!("{} rule for '{}'", context, rule.field), &rule.logic)),
+            Err(_) => errors.push(format!("{} input is not a list of enrichment rules", context)),
+        },
+        FunctionType::Validate => match serde_json::from_value::<Vec<ValidationRule>>(input.clone()) {
+            Ok(rules) => rules.iter()
+                .for_each(|rule| rule_errors(errors, &format!("{} rule '{}'", context, rule.message), &rule.rule)),
+            Err(_) => errors.push(format!("{} input is not a list of validation rules", context)),
+        },
+        FunctionType::Schedule => match serde_json::from_value::<ScheduleSpec>(input.clone()) {
+            Ok(ScheduleSpec::At(rule)) => rule_errors(errors, &format!("{} schedule", context), &rule),
+            Ok(_) => {}
+            Err(_) => errors.push(format!("{} input is not a schedule", context)),
+        },
+        _ => {}
+    }
+}
+
+fn diff_values(path: &str, from: &Value, to: &Value, changes: &mut Vec<WorkflowChange>) {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            let mut keys: Vec<&String> = from.keys().chain(to.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                match (from.get(key), to.get(key)) {
+                    (Some(a), Some(b)) => diff_values(&path, a, b, changes),
+                    (a, b) => changes.push(WorkflowChange { path, from: a.cloned(), to: b.cloned() }),
+                }
+            }
+        }
+        _ if from != to => changes.push(WorkflowChange {
+            path: path.to_string(),
+            from: Some(from.clone()),
+            to: Some(to.clone()),
+        }),
+        _ => {}
+    }
+}
+
+/// One difference between two workflow definitions. `path` is dot separated, with tasks
+/// addressed by id (e.g. `tasks.validate.input`); `from` or `to` is absent when the field
+/// or task was added or removed.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct WorkflowChange {
+    pub path: String,
+
+    pub from: Option<Value>,
+
+    pub to: Option<Value>,
+}
+
+/// What the workflow's and its tasks' JSONLogic conditions are evaluated against.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub enum ConditionContext {
+    /// Only the message `metadata`, as before the combined context existed
+    #[default]
+    Metadata,
+    /// `{data, metadata, progress, payload_info, fetched}`, where `fetched` is the ephemeral data
+    Combined,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum WorkflowStatus {
     Draft,
     Active,
     Deprecated,
 }
 
+impl WorkflowStatus {
+    /// Drafts are activated, active versions deprecated, and deprecated versions may be
+    /// activated again. Nothing returns to `Draft`.
+    pub fn can_transition_to(&self, next: &WorkflowStatus) -> bool {
+        matches!(
+            (self, next),
+            (WorkflowStatus::Draft | WorkflowStatus::Deprecated, WorkflowStatus::Active)
+                | (WorkflowStatus::Active, WorkflowStatus::Deprecated)
+        )
+    }
+}
 
@@ -0,0 +1,303 @@
+mod operators;
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::LazyLock;
+use std::time::Instant;
+
+use datalogic_rs::JsonLogic;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Map, Value};
+
+/// A custom operator, called with its already evaluated arguments.
+pub type Operator = fn(&[Value]) -> Result<Value, String>;
+
+/// Argument counts of the stock JSONLogic operators; `usize::MAX` means any number.
+const STOCK_OPERATORS: &[(&str, usize, usize)] = &[
+    ("var", 0, 2), ("missing", 0, usize::MAX), ("missing_some", 2, 2), ("preserve", 0, usize::MAX),
+    ("==", 2, 2), ("===", 2, 2), ("!=", 2, 2), ("!==", 2, 2),
+    (">", 2, 2), (">=", 2, 2), ("<", 2, 3), ("<=", 2, 3),
+    ("!", 1, 1), ("!!", 1, 1), ("and", 1, usize::MAX), ("or", 1, usize::MAX),
+    ("if", 0, usize::MAX), ("?:", 3, 3),
+    ("map", 2, 2), ("filter", 2, 2), ("reduce", 2, 3), ("all", 2, 2), ("none", 2, 2), ("some", 2, 2),
+    ("merge", 0, usize::MAX), ("in", 2, 2), ("cat", 0, usize::MAX), ("substr", 2, 3),
+    ("+", 0, usize::MAX), ("*", 1, usize::MAX), ("-", 1, 2), ("/", 2, 2), ("%", 2, 2),
+    ("max", 1, usize::MAX), ("min", 1, usize::MAX),
+];
+
+fn describe_arity(arity: &RangeInclusive<usize>) -> String {
+    match (*arity.start(), *arity.end()) {
+        (min, max) if min == max => format!("exactly {}", min),
+        (min, usize::MAX) => format!("at least {}", min),
+        (min, max) => format!("{} to {}", min, max),
+    }
+}
+
+#[derive(Debug)]
+pub enum RuleError {
+    Logic(String),
+    Operator { name: String, message: String },
+    /// The deadline set with `with_deadline` passed before the rule was evaluated
+    Timeout,
+}
+
+impl RuleError {
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, RuleError::Timeout)
+    }
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::Logic(msg) => write!(f, "Rule evaluation failed: {}", msg),
+            RuleError::Operator { name, message } => write!(f, "Operator '{}' failed: {}", name, message),
+            RuleError::Timeout => write!(f, "Rule evaluation deadline exceeded"),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+thread_local! {
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Runs `f` with every rule evaluated on this thread failing with `RuleError::Timeout` once
+/// `deadline` has passed. An earlier deadline already in effect is kept.
+///
+/// Rules are checked before they are evaluated and before each custom operator runs, so a
+/// task evaluating many rules stops at the first one started after the deadline.
+pub fn with_deadline<T>(deadline: Option<Instant>, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Instant>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            DEADLINE.set(self.0);
+        }
+    }
+
+    let previous = DEADLINE.get();
+    let _restore = Restore(previous);
+    DEADLINE.set(match (previous, deadline) {
+        (Some(previous), Some(deadline)) => Some(previous.min(deadline)),
+        (previous, deadline) => previous.or(deadline),
+    });
+    f()
+}
+
+/// Whether the deadline set with `with_deadline` on this thread has passed.
+pub fn deadline_passed() -> bool {
+    DEADLINE.get().is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+fn check_deadline() -> Result<(), RuleError> {
+    if deadline_passed() {
+        return Err(RuleError::Timeout);
+    }
+    Ok(())
+}
+
+static SHARED: LazyLock<RuleEngine> = LazyLock::new(RuleEngine::new);
+
+/// A JSONLogic rule analysed once, when the workflow defining it is loaded.
+///
+/// Serializes as the plain JSONLogic value.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Rule {
+    logic: Value,
+    /// Whether custom operators have to be resolved before JSONLogic sees the rule
+    custom: bool,
+    /// Unknown operators and wrong argument counts found when compiling
+    errors: Vec<String>,
+}
+
+impl Rule {
+    /// Compiles `logic` against the shared engine's operators.
+    pub fn new(logic: Value) -> Self {
+        RuleEngine::shared().compile(logic)
+    }
+
+    pub fn logic(&self) -> &Value {
+        &self.logic
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.logic.is_null()
+    }
+
+    /// Why the rule cannot run; it fails every evaluation unless this is empty.
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+}
+
+impl From<Value> for Rule {
+    fn from(logic: Value) -> Self {
+        Rule::new(logic)
+    }
+}
+
+impl Serialize for Rule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.logic.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Value::deserialize(deserializer).map(Rule::new)
+    }
+}
+
+/// JSONLogic evaluator extended with registered custom operators.
+///
+/// Custom operators are resolved before the rule is handed to JSONLogic, so inside the
+/// bodies of `map`, `filter`, `reduce`, `all`, `some` and `none` they see the root data
+/// rather than the current element.
+#[derive(Clone)]
+pub struct RuleEngine {
+    logic: JsonLogic,
+    operators: HashMap<String, (Operator, RangeInclusive<usize>)>,
+}
+
+impl Default for RuleEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RuleEngine {
+    /// Stock JSONLogic plus the payments operator library.
+    pub fn new() -> Self {
+        let mut engine = Self {
+            logic: JsonLogic::new(),
+            operators: HashMap::new(),
+        };
+        operators::register(&mut engine);
+        engine
+    }
+
+    /// Engine with the operator library, shared by every evaluation in the process.
+    pub fn shared() -> &'static RuleEngine {
+        &SHARED
+    }
+
+    /// Adds or replaces a custom operator taking `arity` arguments.
+    pub fn register(&mut self, name: &str, arity: RangeInclusive<usize>, operator: Operator) {
+        self.operators.insert(name.to_string(), (operator, arity));
+    }
+
+    pub fn apply(&self, rule: &Value, data: &Value) -> Result<Value, RuleError> {
+        check_deadline()?;
+        let result = if self.uses_operators(rule) {
+            self.logic.apply(&self.resolve(rule, data)?, data)
+        } else {
+            self.logic.apply(rule, data)
+        };
+        result.map_err(|e| RuleError::Logic(e.to_string()))
+    }
+
+    /// Compiles `logic` for this engine; rules compiled by another engine may miss its operators.
+    /// Unknown operators and wrong argument counts are recorded in `Rule::errors`.
+    pub fn compile(&self, logic: Value) -> Rule {
+        let custom = self.uses_operators(&logic);
+        let mut errors = Vec::new();
+        self.check(&logic, &mut errors);
+        Rule { logic, custom, errors }
+    }
+
+    /// Like `apply`, without re-scanning the rule for custom operators.
+    pub fn evaluate(&self, rule: &Rule, data: &Value) -> Result<Value, RuleError> {
+        if !rule.errors.is_empty() {
+            return Err(RuleError::Logic(rule.errors.join("; ")));
+        }
+        check_deadline()?;
+        let result = if rule.custom {
+            self.logic.apply(&self.resolve(&rule.logic, data)?, data)
+        } else {
+            self.logic.apply(&rule.logic, data)
+        };
+        result.map_err(|e| RuleError::Logic(e.to_string()))
+    }
+
+    fn check(&self, rule: &Value, errors: &mut Vec<String>) {
+        match rule {
+            Value::Object(map) if map.len() == 1 => {
+                let (op, args) = map.iter().next().unwrap();
+                let count = match args {
+                    Value::Array(items) => items.len(),
+                    Value::Null => 0,
+                    _ => 1,
+                };
+                let arity = match self.operators.get(op) {
+                    Some((_, arity)) => Some(arity.clone()),
+                    None => STOCK_OPERATORS.iter()
+                        .find(|(name, _, _)| name == op)
+                        .map(|(_, min, max)| *min..=*max),
+                };
+                match arity {
+                    None => errors.push(format!("Unknown operator '{}'", op)),
+                    Some(arity) if !arity.contains(&count) => errors.push(format!(
+                        "Operator '{}' takes {} arguments, got {}", op, describe_arity(&arity), count
+                    )),
+                    Some(_) => {}
+                }
+                // Preserved values are data, not rules
+                if op != "preserve" {
+                    self.check(args, errors);
+                }
+            }
+            Value::Object(map) => errors.push(format!("Rule object has {} keys instead of one operator", map.len())),
+            Value::Array(items) => items.iter().for_each(|item| self.check(item, errors)),
+            _ => {}
+        }
+    }
+
+    fn uses_operators(&self, rule: &Value) -> bool {
+        match rule {
+            Value::Object(map) => map.iter()
+                .any(|(op, args)| self.operators.contains_key(op) || self.uses_operators(args)),
+            Value::Array(items) => items.iter().any(|item| self.uses_operators(item)),
+            _ => false,
+        }
+    }
+
+    /// Replaces every custom operator in `rule` with its result.
+    fn resolve(&self, rule: &Value, data: &Value) -> Result<Value, RuleError> {
+        match rule {
+            Value::Object(map) if map.len() == 1 => {
+                let (op, args) = map.iter().next().unwrap();
+                if let Some((operator, _)) = self.operators.get(op) {
+                    let args = match args {
+                        Value::Array(items) => items.iter()
+                            .map(|item| self.apply(item, data))
+                            .collect::<Result<Vec<_>, _>>()?,
+                        Value::Null => Vec::new(),
+                        other => vec![self.apply(other, data)?],
+                    };
+                    check_deadline()?;
+                    let result = operator(&args)
+                        .map_err(|message| RuleError::Operator { name: op.clone(), message })?;
+                    // Objects would otherwise be read as operations
+                    return Ok(match result {
+                        Value::Object(_) => json!({ "preserve": result }),
+                        other => other,
+                    });
+                }
+                if op == "preserve" {
+                    return Ok(rule.clone());
+                }
+                let mut resolved = Map::new();
+                resolved.insert(op.clone(), self.resolve(args, data)?);
+                Ok(Value::Object(resolved))
+            }
+            Value::Array(items) => items.iter()
+                .map(|item| self.resolve(item, data))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::Array),
+            _ => Ok(rule.clone()),
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+
+use regex::Regex;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde_json::Value;
+use time::format_description::well_known::Rfc3339;
+use time::{format_description, Date, Duration, OffsetDateTime, Weekday};
+
+use super::RuleEngine;
+
+pub(super) fn register(engine: &mut RuleEngine) {
+    engine.register("iban_valid", 1..=1, iban_valid);
+    engine.register("bic_valid", 1..=1, bic_valid);
+    engine.register("lei_valid", 1..=1, lei_valid);
+    engine.register("decimal_add", 1..=usize::MAX, decimal_add);
+    engine.register("decimal_mul", 1..=usize::MAX, decimal_mul);
+    engine.register("date_add_business_days", 2..=2, date_add_business_days);
+    engine.register("now", 0..=0, now);
+    engine.register("uuid", 0..=0, uuid);
+    engine.register("regex_match", 2..=2, regex_match);
+    engine.register("country_of_bic", 1..=1, country_of_bic);
+}
+
+fn arg(args: &[Value], index: usize) -> Result<&Value, String> {
+    args.get(index).ok_or_else(|| format!("missing argument {}", index + 1))
+}
+
+fn string_arg(args: &[Value], index: usize) -> Result<&str, String> {
+    arg(args, index)?.as_str().ok_or_else(|| format!("argument {} must be a string", index + 1))
+}
+
+/// Registered IBAN lengths by country; other countries only get the generic 15-34 check.
+const IBAN_LENGTHS: &[(&str, usize)] = &[
+    ("AD", 24), ("AE", 23), ("AL", 28), ("AT", 20), ("AZ", 28), ("BA", 20), ("BE", 16), ("BG", 22),
+    ("BH", 22), ("BR", 29), ("BY", 28), ("CH", 21), ("CR", 22), ("CY", 28), ("CZ", 24), ("DE", 22),
+    ("DK", 18), ("DO", 28), ("EE", 20), ("EG", 29), ("ES", 24), ("FI", 18), ("FO", 18), ("FR", 27),
+    ("GB", 22), ("GE", 22), ("GI", 23), ("GL", 18), ("GR", 27), ("GT", 28), ("HR", 21), ("HU", 28),
+    ("IE", 22), ("IL", 23), ("IQ", 23), ("IS", 26), ("IT", 27), ("JO", 30), ("KW", 30), ("KZ", 20),
+    ("LB", 28), ("LC", 32), ("LI", 21), ("LT", 20), ("LU", 20), ("LV", 21), ("MC", 27), ("MD", 24),
+    ("ME", 22), ("MK", 19), ("MR", 27), ("MT", 31), ("MU", 30), ("NL", 18), ("NO", 15), ("PK", 24),
+    ("PL", 28), ("PS", 29), ("PT", 25), ("QA", 29), ("RO", 24), ("RS", 22), ("SA", 24), ("SC", 31),
+    ("SE", 24), ("SI", 19), ("SK", 24), ("SM", 27), ("ST", 25), ("SV", 28), ("TL", 23), ("TN", 24),
+    ("TR", 26), ("UA", 29), ("VA", 22), ("VG", 24), ("XK", 20),
+];
+
+/// ISO 7064 MOD 97-10 over alphanumerics, letters counting as 10-35.
+fn mod97(text: &str) -> Option<u32> {
+    let mut remainder = 0u32;
+    for c in text.chars() {
+        let value = c.to_digit(36)?;
+        remainder = if value < 10 {
+            (remainder * 10 + value) % 97
+        } else {
+            (remainder * 100 + value) % 97
+        };
+    }
+    Some(remainder)
+}
+
+fn iban_valid(args: &[Value]) -> Result<Value, String> {
+    let Some(iban) = arg(args, 0)?.as_str() else {
+        return Ok(Value::Bool(false));
+    };
+    let iban: String = iban.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_uppercase();
+
+    let well_formed = iban.len() >= 15 && iban.len() <= 34
+        && iban.chars().all(|c| c.is_ascii_alphanumeric())
+        && iban[..2].chars().all(|c| c.is_ascii_alphabetic())
+        && iban[2..4].chars().all(|c| c.is_ascii_digit());
+    if !well_formed {
+        return Ok(Value::Bool(false));
+    }
+    if let Some((_, length)) = IBAN_LENGTHS.iter().find(|(country, _)| *country == &iban[..2]) {
+        if iban.len() != *length {
+            return Ok(Value::Bool(false));
+        }
+    }
+
+    let rearranged = format!("{}{}", &iban[4..], &iban[..4]);
+    Ok(Value::Bool(mod97(&rearranged) == Some(1)))
+}
+
+fn is_bic(bic: &str) -> bool {
+    let bytes = bic.as_bytes();
+    (bytes.len() == 8 || bytes.len() == 11)
+        && bytes[..6].iter().all(u8::is_ascii_uppercase)
+        && bytes[6..].iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+}
+
+fn bic_valid(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Bool(arg(args, 0)?.as_str().is_some_and(is_bic)))
+}
+
+fn country_of_bic(args: &[Value]) -> Result<Value, String> {
+    Ok(match arg(args, 0)?.as_str() {
+        Some(bic) if is_bic(bic) => Value::String(bic[4..6].to_string()),
+        _ => Value::Null,
+    })
+}
+
+fn lei_valid(args: &[Value]) -> Result<Value, String> {
+    let Some(lei) = arg(args, 0)?.as_str() else {
+        return Ok(Value::Bool(false));
+    };
+    let bytes = lei.as_bytes();
+    let well_formed = bytes.len() == 20
+        && bytes[..18].iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+        && bytes[18..].iter().all(u8::is_ascii_digit);
+    Ok(Value::Bool(well_formed && mod97(lei) == Some(1)))
+}
+
+fn decimal(value: &Value) -> Result<Decimal, String> {
+    let text = match value {
+        Value::Number(number) => number.to_string(),
+        Value::String(text) => text.trim().to_string(),
+        other => return Err(format!("{} is not a decimal", other)),
+    };
+    Decimal::from_str(&text)
+        .or_else(|_| Decimal::from_scientific(&text))
+        .map_err(|e| format!("'{}' is not a decimal: {}", text, e))
+}
+
+/// Active ISO 4217 currency codes with minor units, i.e. without funds codes for metals and
+/// special drawing rights. Sorted for `binary_search`.
+const CURRENCIES: &[&str] = &[
+    "AED", "AFN", "ALL", "AMD", "ANG", "AOA", "ARS", "AUD", "AWG", "AZN", "BAM", "BBD", "BDT", "BGN", "BHD", "BIF",
+    "BMD", "BND", "BOB", "BOV", "BRL", "BSD", "BTN", "BWP", "BYN", "BZD", "CAD", "CDF", "CHE", "CHF", "CHW", "CLF",
+    "CLP", "CNY", "COP", "COU", "CRC", "CUP", "CVE", "CZK", "DJF", "DKK", "DOP", "DZD", "EGP", "ERN", "ETB", "EUR",
+    "FJD", "FKP", "GBP", "GEL", "GHS", "GIP", "GMD", "GNF", "GTQ", "GYD", "HKD", "HNL", "HTG", "HUF", "IDR", "ILS",
+    "INR", "IQD", "IRR", "ISK", "JMD", "JOD", "JPY", "KES", "KGS", "KHR", "KMF", "KPW", "KRW", "KWD", "KYD", "KZT",
+    "LAK", "LBP", "LKR", "LRD", "LSL", "LYD", "MAD", "MDL", "MGA", "MKD", "MMK", "MNT", "MOP", "MRU", "MUR", "MVR",
+    "MWK", "MXN", "MXV", "MYR", "MZN", "NAD", "NGN", "NIO", "NOK", "NPR", "NZD", "OMR", "PAB", "PEN", "PGK", "PHP",
+    "PKR", "PLN", "PYG", "QAR", "RON", "RSD", "RUB", "RWF", "SAR", "SBD", "SCR", "SDG", "SEK", "SGD", "SHP", "SLE",
+    "SOS", "SRD", "SSP", "STN", "SVC", "SYP", "SZL", "THB", "TJS", "TMT", "TND", "TOP", "TRY", "TTD", "TWD", "TZS",
+    "UAH", "UGX", "USD", "USN", "UYI", "UYU", "UYW", "UZS", "VED", "VES", "VND", "VUV", "WST", "XAF", "XCD", "XCG",
+    "XOF", "XPF", "YER", "ZAR", "ZMW", "ZWG",
+];
+
+/// ISO 4217 minor units; currencies not listed use two.
+fn minor_units(currency: &str) -> u32 {
+    match currency {
+        "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF" | "UGX" | "UYI"
+        | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => 0,
+        "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+        "CLF" | "UYW" => 4,
+        _ => 2,
+    }
+}
+
+/// Operands followed by an optional ISO 4217 currency code the result is rounded for. Only a
+/// listed code counts as one, so a trailing operand like `"abc"` fails as a decimal instead.
+/// Results are strings so no precision is lost.
+fn decimal_op(args: &[Value], combine: fn(Decimal, Decimal) -> Option<Decimal>) -> Result<Value, String> {
+    let (operands, currency) = match args.last().and_then(Value::as_str) {
+        Some(code) if CURRENCIES.binary_search(&code).is_ok() => (&args[..args.len() - 1], Some(code)),
+        _ => (args, None),
+    };
+    let (first, rest) = operands.split_first().ok_or("expects at least one operand")?;
+
+    let mut result = decimal(first)?;
+    for operand in rest {
+        result = combine(result, decimal(operand)?).ok_or("decimal overflow")?;
+    }
+    if let Some(currency) = currency {
+        let units = minor_units(currency);
+        result = result.round_dp_with_strategy(units, RoundingStrategy::MidpointAwayFromZero);
+        result.rescale(units);
+    }
+    Ok(Value::String(result.to_string()))
+}
+
+fn decimal_add(args: &[Value]) -> Result<Value, String> {
+    decimal_op(args, Decimal::checked_add)
+}
+
+fn decimal_mul(args: &[Value]) -> Result<Value, String> {
+    decimal_op(args, Decimal::checked_mul)
+}
+
+/// Adds (or subtracts) weekdays to an ISO 8601 date; holidays are not considered.
+fn date_add_business_days(args: &[Value]) -> Result<Value, String> {
+    let text = string_arg(args, 0)?;
+    let days = arg(args, 1)?.as_i64().ok_or("argument 2 must be an integer")?;
+    let format = format_description::parse_borrowed::<2>("[year]-[month]-[day]").map_err(|e| e.to_string())?;
+    let mut date = Date::parse(text.get(..10).unwrap_or(text), &format)
+        .map_err(|e| format!("'{}' is not a date: {}", text, e))?;
+
+    let step = if days < 0 { Duration::days(-1) } else { Duration::days(1) };
+    let mut remaining = days.unsigned_abs();
+    while remaining > 0 {
+        date = date.checked_add(step).ok_or("date out of range")?;
+        if !matches!(date.weekday(), Weekday::Saturday | Weekday::Sunday) {
+            remaining -= 1;
+        }
+    }
+    date.format(&format).map(Value::String).map_err(|e| e.to_string())
+}
+
+fn now(_args: &[Value]) -> Result<Value, String> {
+    OffsetDateTime::now_utc().format(&Rfc3339).map(Value::String).map_err(|e| e.to_string())
+}
+
+fn uuid(_args: &[Value]) -> Result<Value, String> {
+    Ok(Value::String(uuid::Uuid::new_v4().to_string()))
+}
+
+/// `[value, pattern]`; non-string values never match.
+/// Compiled patterns shared by every evaluation. Patterns may come from message data, so the
+/// cache starts over once it holds `MAX_PATTERNS`.
+static PATTERNS: OnceLock<Mutex<HashMap<String, Regex>>> = OnceLock::new();
+const MAX_PATTERNS: usize = 256;
+
+fn compiled(pattern: &str) -> Result<Regex, String> {
+    let mut patterns = PATTERNS.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(regex) = patterns.get(pattern) {
+        return Ok(regex.clone());
+    }
+    let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+    if patterns.len() >= MAX_PATTERNS {
+        patterns.clear();
+    }
+    patterns.insert(pattern.to_string(), regex.clone());
+    Ok(regex)
+}
+
+fn regex_match(args: &[Value]) -> Result<Value, String> {
+    let pattern = compiled(string_arg(args, 1)?)?;
+    Ok(Value::Bool(arg(args, 0)?.as_str().is_some_and(|value| pattern.is_match(value))))
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::models::message::Message;
+use crate::models::workflow::Workflow;
+use super::StorageError;
+
+/// A message suspended by an `Approval` task, waiting for a decision.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ParkedMessage {
+    pub message_id: String,
+
+    pub workflow_id: String,
+
+    pub workflow_version: u16,
+
+    pub task_id: String,
+
+    /// Topic the message is republished to once it has been decided
+    pub topic: String,
+
+    #[serde(with = "time::serde::iso8601")]
+    pub parked_at: OffsetDateTime,
+
+    pub message: Message,
+
+    /// Definition the message is suspended in, so a decision does not depend on the API
+    /// sharing the processor's workflow source. Absent on messages parked before it was kept.
+    #[serde(default)]
+    pub workflow: Option<Workflow>,
+}
+
+impl ParkedMessage {
+    pub fn new(message: Message, topic: String) -> Self {
+        let progress = message.progress();
+        ParkedMessage {
+            message_id: message.id().to_string(),
+            workflow_id: progress.workflow_id.clone(),
+            workflow_version: progress.workflow_version,
+            task_id: progress.prev_task.clone(),
+            topic,
+            parked_at: OffsetDateTime::now_utc(),
+            message,
+            workflow: None,
+        }
+    }
+
+    pub fn with_workflow(mut self, workflow: Workflow) -> Self {
+        self.workflow = Some(workflow);
+        self
+    }
+}
+
+#[async_trait]
+pub trait ApprovalStore: Send + Sync {
+    /// Stores the message, replacing an earlier copy with the same id.
+    async fn park(&self, parked: ParkedMessage) -> Result<(), StorageError>;
+
+    async fn list(&self) -> Result<Vec<ParkedMessage>, StorageError>;
+
+    /// Returns the message without removing it.
+    async fn get(&self, message_id: &str) -> Result<Option<ParkedMessage>, StorageError>;
+
+    /// Removes and returns the message, so only one decision can be applied to it.
+    async fn take(&self, message_id: &str) -> Result<Option<ParkedMessage>, StorageError>;
+}
+
+/// Keeps parked messages in process memory; for tests and single-process setups.
+#[derive(Default)]
+pub struct InMemoryApprovalStore {
+    parked: Mutex<HashMap<String, ParkedMessage>>,
+}
+
+impl InMemoryApprovalStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ApprovalStore for InMemoryApprovalStore {
+    async fn park(&self, parked: ParkedMessage) -> Result<(), StorageError> {
+        self.parked.lock().unwrap().insert(parked.message_id.clone(), parked);
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<ParkedMessage>, StorageError> {
+        let mut parked: Vec<ParkedMessage> = self.parked.lock().unwrap().values().cloned().collect();
+        parked.sort_by_key(|p| p.parked_at);
+        Ok(parked)
+    }
+
+    async fn get(&self, message_id: &str) -> Result<Option<ParkedMessage>, StorageError> {
+        Ok(self.parked.lock().unwrap().get(message_id).cloned())
+    }
+
+    async fn take(&self, message_id: &str) -> Result<Option<ParkedMessage>, StorageError> {
+        Ok(self.parked.lock().unwrap().remove(message_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::message::MessageStatus;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_in_memory_approval_store() {
+        let mut message = super::super::test_message();
+        message.transition(MessageStatus::Suspended, "manual_review", "Manual review".to_string(), vec![]).unwrap();
+        let parked = ParkedMessage::new(message, "payment_incoming".to_string());
+        assert_eq!(parked.task_id, "manual_review");
+
+        let store = InMemoryApprovalStore::new();
+        store.park(parked.clone()).await.unwrap();
+        assert_eq!(store.list().await.unwrap(), vec![parked.clone()]);
+        assert_eq!(store.get(&parked.message_id).await.unwrap(), Some(parked.clone()));
+        assert_eq!(store.take(&parked.message_id).await.unwrap(), Some(parked.clone()));
+        assert_eq!(store.take(&parked.message_id).await.unwrap(), None);
+        assert_eq!(store.get(&parked.message_id).await.unwrap(), None);
+    }
+}
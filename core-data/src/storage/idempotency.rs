@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::OffsetDateTime;
+
+use super::StorageError;
+
+/// A request made under an idempotency key, and its response once it has one.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct IdempotencyRecord {
+    pub tenant: String,
+
+    pub key: String,
+
+    /// Hash of the request the key was first used with
+    pub request_hash: String,
+
+    /// Absent while the first request is still in progress
+    pub response: Option<Value>,
+
+    /// HTTP status of the stored response
+    #[serde(default)]
+    pub status: Option<u16>,
+
+    /// End of the in-progress lease, then of the replay window once completed
+    #[serde(with = "time::serde::iso8601")]
+    pub expires_at: OffsetDateTime,
+}
+
+impl IdempotencyRecord {
+    /// A reservation in progress, which another request may take over once `lease` has passed.
+    pub fn new(tenant: String, key: String, request_hash: String, lease: time::Duration) -> Self {
+        IdempotencyRecord {
+            tenant,
+            key,
+            request_hash,
+            response: None,
+            status: None,
+            expires_at: OffsetDateTime::now_utc() + lease,
+        }
+    }
+
+    pub fn is_expired(&self, now: OffsetDateTime) -> bool {
+        self.expires_at <= now
+    }
+}
+
+/// Idempotency keys, scoped per tenant.
+#[async_trait]
+pub trait IdempotencyStore: Send + Sync {
+    /// Reserves the record's key. Returns the existing record instead when the key is
+    /// already in use and has not expired, so only one request per key goes ahead.
+    async fn reserve(&self, record: &IdempotencyRecord) -> Result<Option<IdempotencyRecord>, StorageError>;
+
+    /// Stores the response and status that replays of the key return for the next `window`.
+    async fn complete(&self, tenant: &str, key: &str, status: u16, response: Value, window: time::Duration) -> Result<(), StorageError>;
+
+    /// Frees the key after a failed request, so a retry can use it again.
+    async fn release(&self, tenant: &str, key: &str) -> Result<(), StorageError>;
+}
+
+/// Keeps idempotency keys in process memory; for tests and single-process setups.
+#[derive(Default)]
+pub struct InMemoryIdempotencyStore {
+    records: Mutex<HashMap<(String, String), IdempotencyRecord>>,
+}
+
+impl InMemoryIdempotencyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for InMemoryIdempotencyStore {
+    async fn reserve(&self, record: &IdempotencyRecord) -> Result<Option<IdempotencyRecord>, StorageError> {
+        let mut records = self.records.lock().unwrap();
+        let now = OffsetDateTime::now_utc();
+        records.retain(|_, r| !r.is_expired(now));
+
+        let key = (record.tenant.clone(), record.key.clone());
+        if let Some(existing) = records.get(&key) {
+            return Ok(Some(existing.clone()));
+        }
+        records.insert(key, record.clone());
+        Ok(None)
+    }
+
+    async fn complete(&self, tenant: &str, key: &str, status: u16, response: Value, window: time::Duration) -> Result<(), StorageError> {
+        if let Some(record) = self.records.lock().unwrap().get_mut(&(tenant.to_string(), key.to_string())) {
+            record.response = Some(response);
+            record.status = Some(status);
+            record.expires_at = OffsetDateTime::now_utc() + window;
+        }
+        Ok(())
+    }
+
+    async fn release(&self, tenant: &str, key: &str) -> Result<(), StorageError> {
+        self.records.lock().unwrap().remove(&(tenant.to_string(), key.to_string()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_in_memory_idempotency_store() {
+        let store = InMemoryIdempotencyStore::new();
+        let window = time::Duration::minutes(5);
+        let record = IdempotencyRecord::new(String::from("tenant1"), String::from("key-1"), String::from("abc"), window);
+        assert!(store.reserve(&record).await.unwrap().is_none());
+
+        // In progress, then replayable once completed
+        assert_eq!(store.reserve(&record).await.unwrap().unwrap().response, None);
+        store.complete("tenant1", "key-1", 207, json!({"message_id": "42"}), window).await.unwrap();
+        let existing = store.reserve(&record).await.unwrap().unwrap();
+        assert_eq!(existing.request_hash, "abc");
+        assert_eq!(existing.response, Some(json!({"message_id": "42"})));
+        assert_eq!(existing.status, Some(207));
+
+        // A lapsed lease can be taken over, but completing extends it to the window
+        let leased = IdempotencyRecord::new(String::from("tenant1"), String::from("key-3"), String::from("abc"), time::Duration::ZERO);
+        assert!(store.reserve(&leased).await.unwrap().is_none());
+        store.complete("tenant1", "key-3", 200, json!({"message_id": "43"}), window).await.unwrap();
+        assert_eq!(store.reserve(&leased).await.unwrap().unwrap().status, Some(200));
+
+        // Keys are per tenant, and released or expired keys can be reserved again
+        let other = IdempotencyRecord::new(String::from("tenant2"), String::from("key-1"), String::from("def"), window);
+        assert!(store.reserve(&other).await.unwrap().is_none());
+        store.release("tenant2", "key-1").await.unwrap();
+        assert!(store.reserve(&other).await.unwrap().is_none());
+        let expired = IdempotencyRecord::new(String::from("tenant1"), String::from("key-2"), String::from("abc"), time::Duration::ZERO);
+        assert!(store.reserve(&expired).await.unwrap().is_none());
+        assert!(store.reserve(&expired).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,49 @@
+mod approval;
+mod idempotency;
+mod payload;
+mod repository;
+mod timer;
+mod usage;
+mod workflow;
+#[cfg(feature = "mongodb")]
+mod mongo;
+
+use std::fmt;
+
+pub use self::approval::{ApprovalStore, InMemoryApprovalStore, ParkedMessage};
+pub use self::idempotency::{IdempotencyRecord, IdempotencyStore, InMemoryIdempotencyStore};
+pub use self::payload::FilePayloadStore;
+pub use self::repository::{InMemoryMessageRepository, MessagePage, MessageQuery, MessageRepository};
+pub use self::timer::{InMemoryTimerStore, ScheduledMessage, TimerStore};
+pub use self::usage::{InMemoryUsageStore, UsageRecord, UsageStore};
+pub use self::workflow::{InMemoryWorkflowStore, WorkflowStore};
+#[cfg(feature = "mongodb")]
+pub use self::mongo::{MongoApprovalStore, MongoIdempotencyStore, MongoMessageRepository, MongoTimerStore, MongoUsageStore, MongoWorkflowStore};
+
+#[derive(Debug)]
+pub enum StorageError {
+    Backend(String),
+    Serialization(String),
+    /// A newer copy was written concurrently
+    Conflict(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Backend(msg) => write!(f, "Storage backend error: {}", msg),
+            StorageError::Serialization(msg) => write!(f, "Storage serialization error: {}", msg),
+            StorageError::Conflict(msg) => write!(f, "Storage conflict: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+/// A new message for the store tests.
+#[cfg(test)]
+pub(crate) fn test_message() -> crate::models::message::Message {
+    use crate::models::message::{Encoding, Message, Payload, PayloadFormat, PayloadSchema};
+    let payload = Payload::new_inline(None, PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+    Message::new(payload, "tenant1".to_string(), "api".to_string(), "payment_processing".to_string(), 1, "initiate".to_string(), None)
+}
@@ -0,0 +1,468 @@
+use async_trait::async_trait;
+use futures::stream::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::{CountOptions, FindOneAndDeleteOptions, FindOneAndUpdateOptions, FindOptions, ReplaceOptions, ReturnDocument};
+use mongodb::Collection;
+use time::OffsetDateTime;
+
+use crate::models::message::Message;
+use crate::models::workflow::{Workflow, WorkflowStatus};
+use super::{ApprovalStore, IdempotencyRecord, IdempotencyStore, MessagePage, MessageQuery, MessageRepository, ParkedMessage, ScheduledMessage, StorageError, TimerStore, UsageRecord, UsageStore, WorkflowStore};
+
+impl From<mongodb::error::Error> for StorageError {
+    fn from(err: mongodb::error::Error) -> Self {
+        StorageError::Backend(err.to_string())
+    }
+}
+
+impl From<mongodb::bson::ser::Error> for StorageError {
+    fn from(err: mongodb::bson::ser::Error) -> Self {
+        StorageError::Serialization(err.to_string())
+    }
+}
+
+impl From<mongodb::bson::de::Error> for StorageError {
+    fn from(err: mongodb::bson::de::Error) -> Self {
+        StorageError::Serialization(err.to_string())
+    }
+}
+
+/// Stores parked messages in the `ParkedMessage` collection, keyed by message id.
+pub struct MongoApprovalStore {
+    collection: Collection<Document>,
+}
+
+impl MongoApprovalStore {
+    pub fn new(database: &mongodb::Database) -> Self {
+        Self {
+            collection: database.collection::<Document>("ParkedMessage"),
+        }
+    }
+}
+
+#[async_trait]
+impl ApprovalStore for MongoApprovalStore {
+    async fn park(&self, parked: ParkedMessage) -> Result<(), StorageError> {
+        let mut document = mongodb::bson::to_document(&parked)?;
+        document.insert("_id", &parked.message_id);
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.collection
+            .replace_one(doc! { "_id": &parked.message_id }, document, options)
+            .await?;
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<ParkedMessage>, StorageError> {
+        let options = FindOptions::builder().sort(doc! { "parked_at": 1 }).build();
+        let mut cursor = self.collection.find(None, options).await?;
+        let mut parked = Vec::new();
+        while let Some(document) = cursor.try_next().await? {
+            parked.push(mongodb::bson::from_document(document)?);
+        }
+        Ok(parked)
+    }
+
+    async fn get(&self, message_id: &str) -> Result<Option<ParkedMessage>, StorageError> {
+        match self.collection.find_one(doc! { "_id": message_id }, None).await? {
+            Some(document) => Ok(Some(mongodb::bson::from_document(document)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn take(&self, message_id: &str) -> Result<Option<ParkedMessage>, StorageError> {
+        // Atomic, so concurrent approve and reject calls cannot both succeed
+        match self.collection.find_one_and_delete(doc! { "_id": message_id }, None).await? {
+            Some(document) => Ok(Some(mongodb::bson::from_document(document)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Stores timers in the `ScheduledMessage` collection, keyed by message id.
+pub struct MongoTimerStore {
+    collection: Collection<Document>,
+}
+
+impl MongoTimerStore {
+    pub fn new(database: &mongodb::Database) -> Self {
+        Self {
+            collection: database.collection::<Document>("ScheduledMessage"),
+        }
+    }
+
+    fn unix_ms(instant: OffsetDateTime) -> i64 {
+        (instant.unix_timestamp_nanos() / 1_000_000) as i64
+    }
+}
+
+#[async_trait]
+impl TimerStore for MongoTimerStore {
+    async fn schedule(&self, scheduled: ScheduledMessage) -> Result<(), StorageError> {
+        let mut document = mongodb::bson::to_document(&scheduled)?;
+        document.insert("_id", &scheduled.message_id);
+        // Numeric copy of `due_at` for range queries
+        document.insert("due_unix_ms", Self::unix_ms(scheduled.due_at));
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.collection
+            .replace_one(doc! { "_id": &scheduled.message_id }, document, options)
+            .await?;
+        Ok(())
+    }
+
+    async fn take_due(&self, now: OffsetDateTime, limit: usize) -> Result<Vec<ScheduledMessage>, StorageError> {
+        let filter = doc! { "due_unix_ms": { "$lte": Self::unix_ms(now) } };
+        let options = FindOneAndDeleteOptions::builder().sort(doc! { "due_unix_ms": 1 }).build();
+
+        // Claimed one at a time so several schedulers never release the same message
+        let mut due = Vec::new();
+        while due.len() < limit {
+            match self.collection.find_one_and_delete(filter.clone(), options.clone()).await? {
+                Some(document) => due.push(mongodb::bson::from_document(document)?),
+                None => break,
+            }
+        }
+        Ok(due)
+    }
+}
+
+/// Stores messages in the `Message` collection, keyed by message id.
+pub struct MongoMessageRepository {
+    collection: Collection<Document>,
+}
+
+impl MongoMessageRepository {
+    pub fn new(database: &mongodb::Database) -> Self {
+        Self {
+            collection: database.collection::<Document>("Message"),
+        }
+    }
+
+    fn filter(query: &MessageQuery) -> Result<Document, StorageError> {
+        let mut filter = Document::new();
+        if let Some(tenant) = &query.tenant {
+            filter.insert("tenant", tenant);
+        }
+        if let Some(status) = &query.status {
+            filter.insert("progress.status", mongodb::bson::to_bson(status)?);
+        }
+        if let Some(parent_id) = &query.parent_id {
+            filter.insert("parent_id", parent_id);
+        }
+        let mut created = Document::new();
+        if let Some(from) = query.from {
+            created.insert("$gte", MongoTimerStore::unix_ms(from));
+        }
+        if let Some(to) = query.to {
+            created.insert("$lt", MongoTimerStore::unix_ms(to));
+        }
+        if !created.is_empty() {
+            filter.insert("created_unix_ms", created);
+        }
+        Ok(filter)
+    }
+}
+
+fn is_duplicate_key(err: &mongodb::error::Error) -> bool {
+    matches!(&*err.kind, ErrorKind::Write(WriteFailure::WriteError(e)) if e.code == 11000)
+}
+
+#[async_trait]
+impl MessageRepository for MongoMessageRepository {
+    async fn upsert(&self, message: &Message) -> Result<(), StorageError> {
+        let message_id = message.id().to_string();
+        let mut document = mongodb::bson::to_document(message)?;
+        document.insert("_id", &message_id);
+        // Numeric copy of the creation time for range queries
+        document.insert("created_unix_ms", MongoTimerStore::unix_ms(message.created_at()));
+
+        // When a copy at the same or a newer version exists the filter misses, and the upsert's
+        // insert then collides with it on `_id`
+        let filter = doc! { "_id": &message_id, "version": { "$lt": message.version() as i32 } };
+        let options = ReplaceOptions::builder().upsert(true).build();
+        match self.collection.replace_one(filter, document, options).await {
+            Ok(_) => Ok(()),
+            Err(e) if is_duplicate_key(&e) => Err(StorageError::Conflict(format!(
+                "Message {} is already stored at version {} or later",
+                message_id, message.version()
+            ))),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get(&self, message_id: &str) -> Result<Option<Message>, StorageError> {
+        match self.collection.find_one(doc! { "_id": message_id }, None).await? {
+            Some(document) => Ok(Some(mongodb::bson::from_document(document)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn find(&self, query: &MessageQuery) -> Result<MessagePage, StorageError> {
+        let filter = Self::filter(query)?;
+        let total = self.collection.count_documents(filter.clone(), CountOptions::default()).await?;
+
+        let options = FindOptions::builder()
+            .sort(doc! { "created_unix_ms": -1, "_id": -1 })
+            .skip(query.offset)
+            .limit(query.limit as i64)
+            .build();
+        let mut cursor = self.collection.find(filter, options).await?;
+        let mut messages = Vec::new();
+        while let Some(document) = cursor.try_next().await? {
+            messages.push(mongodb::bson::from_document(document)?);
+        }
+        Ok(MessagePage { messages, total })
+    }
+}
+
+/// Stores workflows in the `Workflow` collection the processor loads them from.
+pub struct MongoWorkflowStore {
+    collection: Collection<Document>,
+}
+
+impl MongoWorkflowStore {
+    pub fn new(database: &mongodb::Database) -> Self {
+        Self {
+            collection: database.collection::<Document>("Workflow"),
+        }
+    }
+
+    fn key(workflow_id: &str, version: u16) -> Document {
+        doc! { "id": workflow_id, "version": version as i32 }
+    }
+}
+
+#[async_trait]
+impl WorkflowStore for MongoWorkflowStore {
+    async fn create(&self, workflow: &Workflow) -> Result<(), StorageError> {
+        let conflict = || StorageError::Conflict(format!(
+            "Workflow {} version {} already exists", workflow.id, workflow.version
+        ));
+        // Definitions written by hand have generated ids, so check for them explicitly
+        if self.collection.find_one(Self::key(&workflow.id, workflow.version), None).await?.is_some() {
+            return Err(conflict());
+        }
+        if self.collection.find_one(doc! { "id": &workflow.id, "tenant": { "$ne": &workflow.tenant } }, None).await?.is_some() {
+            return Err(StorageError::Conflict(format!(
+                "Workflow {} belongs to another tenant", workflow.id
+            )));
+        }
+
+        let mut document = mongodb::bson::to_document(workflow)?;
+        // Deterministic id so concurrent creates of the same version collide
+        document.insert("_id", format!("{}:{}", workflow.id, workflow.version));
+        match self.collection.insert_one(document, None).await {
+            Ok(_) => Ok(()),
+            Err(e) if is_duplicate_key(&e) => Err(conflict()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get(&self, workflow_id: &str, version: u16) -> Result<Option<Workflow>, StorageError> {
+        match self.collection.find_one(Self::key(workflow_id, version), None).await? {
+            Some(document) => Ok(Some(mongodb::bson::from_document(document)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn versions(&self, workflow_id: &str) -> Result<Vec<Workflow>, StorageError> {
+        let options = FindOptions::builder().sort(doc! { "version": 1 }).build();
+        let mut cursor = self.collection.find(doc! { "id": workflow_id }, options).await?;
+        let mut workflows = Vec::new();
+        while let Some(document) = cursor.try_next().await? {
+            workflows.push(mongodb::bson::from_document(document)?);
+        }
+        Ok(workflows)
+    }
+
+    async fn set_status(&self, workflow_id: &str, version: u16, from: &WorkflowStatus, to: &WorkflowStatus) -> Result<(), StorageError> {
+        let mut filter = Self::key(workflow_id, version);
+        filter.insert("status", mongodb::bson::to_bson(from)?);
+        let update = doc! { "$set": { "status": mongodb::bson::to_bson(to)? } };
+        let result = self.collection.update_one(filter, update, None).await?;
+        if result.matched_count == 0 {
+            return Err(StorageError::Conflict(format!(
+                "Workflow {} version {} is not {:?}", workflow_id, version, from
+            )));
+        }
+        Ok(())
+    }
+
+    /// Without a transaction the two writes can be seen apart, so the other versions are
+    /// deprecated first: a reader may briefly find no active version, but never two. They are
+    /// restored when the version turns out not to be in `from`.
+    async fn activate(&self, workflow_id: &str, version: u16, from: &WorkflowStatus) -> Result<Vec<u16>, StorageError> {
+        match self.get(workflow_id, version).await? {
+            Some(workflow) if &workflow.status == from => {}
+            _ => return Err(StorageError::Conflict(format!(
+                "Workflow {} version {} is not {:?}", workflow_id, version, from
+            ))),
+        }
+
+        let active = mongodb::bson::to_bson(&WorkflowStatus::Active)?;
+        let retired = mongodb::bson::to_bson(&WorkflowStatus::Deprecated)?;
+        let filter = doc! { "id": workflow_id, "version": { "$ne": version as i32 }, "status": active.clone() };
+        let mut deprecated = Vec::new();
+        let mut cursor = self.collection.find(filter, None).await?;
+        while let Some(document) = cursor.try_next().await? {
+            let workflow: Workflow = mongodb::bson::from_document(document)?;
+            deprecated.push(workflow.version);
+        }
+        let versions: Vec<i32> = deprecated.iter().map(|v| *v as i32).collect();
+        self.collection.update_many(
+            doc! { "id": workflow_id, "version": { "$in": versions.clone() }, "status": active.clone() },
+            doc! { "$set": { "status": retired.clone() } },
+            None,
+        ).await?;
+
+        if let Err(e) = self.set_status(workflow_id, version, from, &WorkflowStatus::Active).await {
+            self.collection.update_many(
+                doc! { "id": workflow_id, "version": { "$in": versions }, "status": retired },
+                doc! { "$set": { "status": active } },
+                None,
+            ).await?;
+            return Err(e);
+        }
+        Ok(deprecated)
+    }
+}
+
+/// Stores idempotency keys in the `IdempotencyKey` collection, keyed by tenant and key.
+pub struct MongoIdempotencyStore {
+    collection: Collection<Document>,
+}
+
+impl MongoIdempotencyStore {
+    pub fn new(database: &mongodb::Database) -> Self {
+        Self {
+            collection: database.collection::<Document>("IdempotencyKey"),
+        }
+    }
+
+    fn id(tenant: &str, key: &str) -> String {
+        format!("{}:{}", tenant, key)
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for MongoIdempotencyStore {
+    async fn reserve(&self, record: &IdempotencyRecord) -> Result<Option<IdempotencyRecord>, StorageError> {
+        let id = Self::id(&record.tenant, &record.key);
+        let mut document = mongodb::bson::to_document(record)?;
+        document.insert("_id", &id);
+        // Numeric copy of `expires_at` for taking over expired keys
+        document.insert("expires_unix_ms", MongoTimerStore::unix_ms(record.expires_at));
+
+        match self.collection.insert_one(document.clone(), None).await {
+            Ok(_) => return Ok(None),
+            Err(e) if !is_duplicate_key(&e) => return Err(e.into()),
+            Err(_) => {}
+        }
+
+        // The key is in use; an expired record is replaced atomically, otherwise it is returned
+        let now = MongoTimerStore::unix_ms(OffsetDateTime::now_utc());
+        let expired = doc! { "_id": &id, "expires_unix_ms": { "$lte": now } };
+        if self.collection.replace_one(expired, document, None).await?.matched_count == 1 {
+            return Ok(None);
+        }
+        match self.collection.find_one(doc! { "_id": &id }, None).await? {
+            Some(existing) => Ok(Some(mongodb::bson::from_document(existing)?)),
+            // Released in the meantime
+            None => Box::pin(self.reserve(record)).await,
+        }
+    }
+
+    async fn complete(&self, tenant: &str, key: &str, status: u16, response: serde_json::Value, window: time::Duration) -> Result<(), StorageError> {
+        // Formatted as `IdempotencyRecord` serializes it
+        let expires_at = OffsetDateTime::now_utc() + window;
+        let expires_iso = expires_at
+            .format(&time::format_description::well_known::Iso8601::DEFAULT)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        let update = doc! { "$set": {
+            "response": mongodb::bson::to_bson(&response)?,
+            "status": status as i32,
+            "expires_at": expires_iso,
+            "expires_unix_ms": MongoTimerStore::unix_ms(expires_at),
+        } };
+        self.collection.update_one(doc! { "_id": Self::id(tenant, key) }, update, None).await?;
+        Ok(())
+    }
+
+    async fn release(&self, tenant: &str, key: &str) -> Result<(), StorageError> {
+        self.collection.delete_one(doc! { "_id": Self::id(tenant, key) }, None).await?;
+        Ok(())
+    }
+}
+
+/// Stores daily usage counters in the `Usage` collection, keyed by tenant, route and day.
+pub struct MongoUsageStore {
+    collection: Collection<Document>,
+}
+
+impl MongoUsageStore {
+    pub fn new(database: &mongodb::Database) -> Self {
+        Self {
+            collection: database.collection::<Document>("Usage"),
+        }
+    }
+
+    fn id(tenant: &str, route: &str, day: &str) -> String {
+        format!("{}:{}:{}", tenant, route, day)
+    }
+}
+
+#[async_trait]
+impl UsageStore for MongoUsageStore {
+    async fn consume(&self, tenant: &str, route: &str, day: &str, amount: u64, limit: Option<u64>) -> Result<Option<u64>, StorageError> {
+        if limit.is_some_and(|limit| amount > limit) {
+            return Ok(None);
+        }
+        let mut filter = doc! { "_id": Self::id(tenant, route, day) };
+        if let Some(limit) = limit {
+            filter.insert("count", doc! { "$lte": (limit - amount) as i64 });
+        }
+        let update = doc! {
+            "$inc": { "count": amount as i64 },
+            "$setOnInsert": { "tenant": tenant, "route": route, "day": day },
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .build();
+
+        // A counter over the limit fails the filter, so the upsert collides with it. The first
+        // collision may instead be a concurrent insert of a new counter, hence one retry.
+        for _ in 0..2 {
+            match self.collection.find_one_and_update(filter.clone(), update.clone(), options.clone()).await {
+                Ok(Some(document)) => {
+                    let count = document.get_i64("count").map_err(|e| StorageError::Serialization(e.to_string()))?;
+                    return Ok(Some(count as u64));
+                }
+                Ok(None) => return Ok(None),
+                Err(e) if is_duplicate_key(&e) => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(None)
+    }
+
+    async fn refund(&self, tenant: &str, route: &str, day: &str, amount: u64) -> Result<(), StorageError> {
+        let update = vec![doc! {
+            "$set": { "count": { "$max": [0_i64, { "$subtract": ["$count", amount as i64] }] } },
+        }];
+        self.collection.update_one(doc! { "_id": Self::id(tenant, route, day) }, update, None).await?;
+        Ok(())
+    }
+
+    async fn usage(&self, day: &str, tenant: Option<&str>) -> Result<Vec<UsageRecord>, StorageError> {
+        let mut filter = doc! { "day": day };
+        if let Some(tenant) = tenant {
+            filter.insert("tenant", tenant);
+        }
+        let options = FindOptions::builder().sort(doc! { "tenant": 1, "route": 1 }).build();
+        let documents: Vec<Document> = self.collection.find(filter, options).await?.try_collect().await?;
+        documents.into_iter()
+            .map(|document| mongodb::bson::from_document(document).map_err(StorageError::from))
+            .collect()
+    }
+}
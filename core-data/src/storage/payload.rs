@@ -0,0 +1,85 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::models::message::{Encoding, Payload, PayloadFormat, PayloadSchema};
+use super::StorageError;
+
+/// Payload files in a directory shared by the API and the processor. Uploads are written to a
+/// staging file first and only moved into place once complete, so `Parse` never reads a
+/// partial file through the payload's url.
+#[derive(Debug, Clone)]
+pub struct FilePayloadStore {
+    root: PathBuf,
+}
+
+impl FilePayloadStore {
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self, StorageError> {
+        let store = Self { root: root.into() };
+        fs::create_dir_all(store.staging_dir())
+            .map_err(|e| StorageError::Backend(format!("Cannot create {}: {}", store.root.display(), e)))?;
+        Ok(store)
+    }
+
+    pub fn new_file_id() -> String {
+        uuid::Uuid::new_v4().to_string()
+    }
+
+    fn staging_dir(&self) -> PathBuf {
+        self.root.join(".staging")
+    }
+
+    /// Where an upload is written until it is committed.
+    pub fn staging_path(&self, file_id: &str) -> PathBuf {
+        self.staging_dir().join(format!("{}.part", file_id))
+    }
+
+    pub fn path(&self, file_id: &str) -> PathBuf {
+        self.root.join(file_id)
+    }
+
+    /// Moves a complete upload into place and returns the `File` payload referring to it.
+    pub fn commit(&self, file_id: &str, format: PayloadFormat, schema: PayloadSchema, encoding: Encoding) -> Result<Payload, StorageError> {
+        let path = self.path(file_id);
+        fs::rename(self.staging_path(file_id), &path)
+            .map_err(|e| StorageError::Backend(format!("Cannot store file {}: {}", file_id, e)))?;
+        let size = fs::metadata(&path)
+            .map_err(|e| StorageError::Backend(format!("Cannot read file {}: {}", file_id, e)))?
+            .len();
+        Ok(Payload::new_file(Some(path.to_string_lossy()), format, schema, encoding, size as i64))
+    }
+
+    /// Removes the upload, staged or committed.
+    pub fn discard(&self, file_id: &str) {
+        let _ = fs::remove_file(self.staging_path(file_id));
+        let _ = fs::remove_file(self.path(file_id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::models::message::Message;
+    use super::*;
+
+    #[test]
+    fn test_file_payload_store() {
+        let store = FilePayloadStore::new(std::env::temp_dir().join(FilePayloadStore::new_file_id())).unwrap();
+        let file_id = FilePayloadStore::new_file_id();
+        fs::copy("examples/pacs008_001_07_cct_outgoing.xml", store.staging_path(&file_id)).unwrap();
+
+        let payload = store.commit(&file_id, PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8).unwrap();
+        assert!(!store.staging_path(&file_id).exists());
+        assert_eq!(payload.url(), Some(store.path(&file_id).to_str().unwrap()));
+        assert_eq!(payload.info()["storage"], json!("File"));
+        assert!(payload.info()["size"].as_i64().unwrap() > 0);
+
+        // The processor parses the committed file through the payload url
+        let mut message = Message::new(payload, String::from("tenant1"), String::from("files"), String::from("payment_processing"), 1, String::from("initiate"), None);
+        message.parse(None, String::from("payment_processing"), 1, String::from("parse")).unwrap();
+        assert!(!message.data().is_null());
+
+        store.discard(&file_id);
+        assert!(!store.path(&file_id).exists());
+        assert!(store.commit(&file_id, PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8).is_err());
+    }
+}
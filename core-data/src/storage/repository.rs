@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use time::OffsetDateTime;
+
+use crate::models::message::{Message, MessageStatus};
+use super::StorageError;
+
+/// Filters and page of a message search. Results are ordered newest first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageQuery {
+    pub tenant: Option<String>,
+
+    pub status: Option<MessageStatus>,
+
+    /// Correlation id shared by the messages of a batch
+    pub parent_id: Option<String>,
+
+    /// Created at or after
+    pub from: Option<OffsetDateTime>,
+
+    /// Created before
+    pub to: Option<OffsetDateTime>,
+
+    pub offset: u64,
+
+    pub limit: u64,
+}
+
+impl Default for MessageQuery {
+    fn default() -> Self {
+        MessageQuery {
+            tenant: None,
+            status: None,
+            parent_id: None,
+            from: None,
+            to: None,
+            offset: 0,
+            limit: 50,
+        }
+    }
+}
+
+impl MessageQuery {
+    pub fn matches(&self, message: &Message) -> bool {
+        let created_at = message.created_at();
+        self.tenant.as_ref().is_none_or(|tenant| message.tenant() == tenant)
+            && self.status.as_ref().is_none_or(|status| &message.progress().status == status)
+            && self.parent_id.as_ref().is_none_or(|parent_id| message.parent_id().as_ref() == Some(parent_id))
+            && self.from.is_none_or(|from| created_at >= from)
+            && self.to.is_none_or(|to| created_at < to)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessagePage {
+    pub messages: Vec<Message>,
+
+    /// Messages matching the filters across all pages
+    pub total: u64,
+}
+
+/// Durable copies of messages, including their audit trail.
+#[async_trait]
+pub trait MessageRepository: Send + Sync {
+    /// Stores the message, replacing the stored copy only when it has a lower `version`.
+    /// Fails with `StorageError::Conflict` when an equal or newer copy is already stored,
+    /// so a stale writer cannot overwrite a later one.
+    async fn upsert(&self, message: &Message) -> Result<(), StorageError>;
+
+    async fn get(&self, message_id: &str) -> Result<Option<Message>, StorageError>;
+
+    async fn find(&self, query: &MessageQuery) -> Result<MessagePage, StorageError>;
+}
+
+/// Keeps messages in process memory; for tests and single-process setups.
+#[derive(Default)]
+pub struct InMemoryMessageRepository {
+    messages: Mutex<HashMap<String, Message>>,
+}
+
+impl InMemoryMessageRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl MessageRepository for InMemoryMessageRepository {
+    async fn upsert(&self, message: &Message) -> Result<(), StorageError> {
+        let mut messages = self.messages.lock().unwrap();
+        let message_id = message.id().to_string();
+        if let Some(stored) = messages.get(&message_id) {
+            if stored.version() >= message.version() {
+                return Err(StorageError::Conflict(format!(
+                    "Message {} is already stored at version {}, not replacing it with version {}",
+                    message_id, stored.version(), message.version()
+                )));
+            }
+        }
+        messages.insert(message_id, message.clone());
+        Ok(())
+    }
+
+    async fn get(&self, message_id: &str) -> Result<Option<Message>, StorageError> {
+        Ok(self.messages.lock().unwrap().get(message_id).cloned())
+    }
+
+    async fn find(&self, query: &MessageQuery) -> Result<MessagePage, StorageError> {
+        let mut matching: Vec<Message> = self.messages.lock().unwrap().values()
+            .filter(|m| query.matches(m))
+            .cloned()
+            .collect();
+        matching.sort_by_key(|m| std::cmp::Reverse((m.created_at(), m.id())));
+
+        let total = matching.len() as u64;
+        let messages = matching.into_iter()
+            .skip(query.offset as usize)
+            .take(query.limit as usize)
+            .collect();
+        Ok(MessagePage { messages, total })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn processed() -> Message {
+        let mut message = super::super::test_message();
+        message.transition(MessageStatus::Processing, "parse", "Parsed".to_string(), vec![]).unwrap();
+        message
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_message_repository() {
+        let repository = InMemoryMessageRepository::new();
+        let mut message = super::super::test_message();
+        let initiated = message.clone();
+        repository.upsert(&message).await.unwrap();
+
+        message.transition(MessageStatus::Processing, "parse", "Parsed".to_string(), vec![]).unwrap();
+        repository.upsert(&message).await.unwrap();
+
+        // A writer holding the older copy cannot overwrite the newer one
+        assert!(matches!(repository.upsert(&initiated).await, Err(StorageError::Conflict(_))));
+        assert!(matches!(repository.upsert(&message).await, Err(StorageError::Conflict(_))));
+        let stored = repository.get(&message.id().to_string()).await.unwrap().unwrap();
+        assert_eq!(stored.version(), message.version());
+        assert_eq!(stored.audit().len(), message.audit().len());
+        assert!(repository.get("0").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_message_repository_query() {
+        let repository = InMemoryMessageRepository::new();
+        let processed = processed();
+        repository.upsert(&processed).await.unwrap();
+        for _ in 0..2 {
+            repository.upsert(&super::super::test_message().with_parent_id(String::from("batch-1"))).await.unwrap();
+        }
+
+        let page = repository.find(&MessageQuery { limit: 2, ..Default::default() }).await.unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(page.messages.len(), 2);
+        assert!(page.messages[0].created_at() >= page.messages[1].created_at());
+        let rest = repository.find(&MessageQuery { offset: 2, limit: 2, ..Default::default() }).await.unwrap();
+        assert_eq!(rest.total, 3);
+        assert_eq!(rest.messages.len(), 1);
+
+        let by_status = MessageQuery { status: Some(MessageStatus::Processing), ..Default::default() };
+        let page = repository.find(&by_status).await.unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.messages[0].id(), processed.id());
+
+        let batch = MessageQuery { parent_id: Some(String::from("batch-1")), ..Default::default() };
+        let page = repository.find(&batch).await.unwrap();
+        assert_eq!(page.total, 2);
+        assert!(page.messages.iter().all(|m| m.parent_id().as_deref() == Some("batch-1")));
+
+        let other_tenant = MessageQuery { tenant: Some(String::from("tenant2")), ..Default::default() };
+        assert_eq!(repository.find(&other_tenant).await.unwrap().total, 0);
+        let future = MessageQuery { from: Some(OffsetDateTime::now_utc() + time::Duration::hours(1)), ..Default::default() };
+        assert!(repository.find(&future).await.unwrap().messages.is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::models::message::Message;
+use super::StorageError;
+
+/// A message warehoused by a `Schedule` task, or waiting to retry a task, until `due_at`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ScheduledMessage {
+    pub message_id: String,
+
+    pub workflow_id: String,
+
+    pub workflow_version: u16,
+
+    pub task_id: String,
+
+    /// Topic the message is re-injected into once due
+    pub topic: String,
+
+    #[serde(with = "time::serde::iso8601")]
+    pub due_at: OffsetDateTime,
+
+    pub message: Message,
+}
+
+impl ScheduledMessage {
+    pub fn new(message: Message, topic: String, due_at: OffsetDateTime) -> Self {
+        let progress = message.progress();
+        ScheduledMessage {
+            message_id: message.id().to_string(),
+            workflow_id: progress.workflow_id.clone(),
+            workflow_version: progress.workflow_version,
+            task_id: progress.prev_task.clone(),
+            topic,
+            due_at,
+            message,
+        }
+    }
+}
+
+#[async_trait]
+pub trait TimerStore: Send + Sync {
+    /// Stores the message, replacing an earlier timer for the same id.
+    async fn schedule(&self, scheduled: ScheduledMessage) -> Result<(), StorageError>;
+
+    /// Removes and returns up to `limit` messages due at `now`, earliest first. Each due
+    /// message is returned to exactly one caller.
+    async fn take_due(&self, now: OffsetDateTime, limit: usize) -> Result<Vec<ScheduledMessage>, StorageError>;
+}
+
+/// Keeps timers in process memory; for tests and single-process setups.
+#[derive(Default)]
+pub struct InMemoryTimerStore {
+    timers: Mutex<Vec<ScheduledMessage>>,
+}
+
+impl InMemoryTimerStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl TimerStore for InMemoryTimerStore {
+    async fn schedule(&self, scheduled: ScheduledMessage) -> Result<(), StorageError> {
+        let mut timers = self.timers.lock().unwrap();
+        timers.retain(|t| t.message_id != scheduled.message_id);
+        timers.push(scheduled);
+        timers.sort_by_key(|t| t.due_at);
+        Ok(())
+    }
+
+    async fn take_due(&self, now: OffsetDateTime, limit: usize) -> Result<Vec<ScheduledMessage>, StorageError> {
+        let mut timers = self.timers.lock().unwrap();
+        let due = timers.iter().take(limit).take_while(|t| t.due_at <= now).count();
+        Ok(timers.drain(..due).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_in_memory_timer_store() {
+        let store = InMemoryTimerStore::new();
+        let now = OffsetDateTime::now_utc();
+        let later = super::super::test_message();
+        let sooner = super::super::test_message();
+        store.schedule(ScheduledMessage::new(later.clone(), "payment_incoming".to_string(), now + Duration::hours(2))).await.unwrap();
+        store.schedule(ScheduledMessage::new(sooner.clone(), "payment_incoming".to_string(), now + Duration::hours(1))).await.unwrap();
+        // Scheduling again replaces the earlier timer
+        store.schedule(ScheduledMessage::new(later.clone(), "payment_incoming".to_string(), now + Duration::hours(3))).await.unwrap();
+
+        assert!(store.take_due(now, 10).await.unwrap().is_empty());
+        let due = store.take_due(now + Duration::hours(3), 1).await.unwrap();
+        assert_eq!(due.iter().map(|t| t.message_id.clone()).collect::<Vec<_>>(), vec![sooner.id().to_string()]);
+        assert_eq!(due[0].task_id, "initiate");
+
+        // Each due message is handed out once
+        let due = store.take_due(now + Duration::hours(3), 10).await.unwrap();
+        assert_eq!(due.iter().map(|t| t.message_id.clone()).collect::<Vec<_>>(), vec![later.id().to_string()]);
+        assert!(store.take_due(now + Duration::hours(3), 10).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::StorageError;
+
+/// How much of a route a tenant used on one UTC day.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UsageRecord {
+    pub tenant: String,
+
+    pub route: String,
+
+    /// UTC day as `YYYY-MM-DD`
+    pub day: String,
+
+    pub count: u64,
+}
+
+/// Daily usage counters per tenant and route, backing the API's quotas.
+#[async_trait]
+pub trait UsageStore: Send + Sync {
+    /// Adds `amount` to the day's count unless that would take it over `limit`. Returns the
+    /// new count, or `None` when the amount was refused and nothing was added.
+    async fn consume(&self, tenant: &str, route: &str, day: &str, amount: u64, limit: Option<u64>) -> Result<Option<u64>, StorageError>;
+
+    /// Takes `amount` back off the day's count, e.g. for messages that were charged but never
+    /// published. The count does not go below zero.
+    async fn refund(&self, tenant: &str, route: &str, day: &str, amount: u64) -> Result<(), StorageError>;
+
+    /// The day's counters, optionally for one tenant, ordered by tenant and route.
+    async fn usage(&self, day: &str, tenant: Option<&str>) -> Result<Vec<UsageRecord>, StorageError>;
+}
+
+/// Keeps usage counters in process memory; for tests and single-process setups.
+#[derive(Default)]
+pub struct InMemoryUsageStore {
+    counters: Mutex<BTreeMap<(String, String, String), u64>>,
+}
+
+impl InMemoryUsageStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl UsageStore for InMemoryUsageStore {
+    async fn consume(&self, tenant: &str, route: &str, day: &str, amount: u64, limit: Option<u64>) -> Result<Option<u64>, StorageError> {
+        let mut counters = self.counters.lock().unwrap();
+        // Earlier days are no longer needed
+        counters.retain(|(d, _, _), _| d.as_str() >= day);
+
+        let count = counters.entry((day.to_string(), tenant.to_string(), route.to_string())).or_insert(0);
+        if limit.is_some_and(|limit| *count + amount > limit) {
+            return Ok(None);
+        }
+        *count += amount;
+        Ok(Some(*count))
+    }
+
+    async fn refund(&self, tenant: &str, route: &str, day: &str, amount: u64) -> Result<(), StorageError> {
+        if let Some(count) = self.counters.lock().unwrap().get_mut(&(day.to_string(), tenant.to_string(), route.to_string())) {
+            *count = count.saturating_sub(amount);
+        }
+        Ok(())
+    }
+
+    async fn usage(&self, day: &str, tenant: Option<&str>) -> Result<Vec<UsageRecord>, StorageError> {
+        Ok(self.counters.lock().unwrap().iter()
+            .filter(|((d, t, _), _)| d == day && tenant.is_none_or(|tenant| t == tenant))
+            .map(|((d, t, r), count)| UsageRecord {
+                tenant: t.clone(),
+                route: r.clone(),
+                day: d.clone(),
+                count: *count,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_in_memory_usage_store() {
+        let store = InMemoryUsageStore::new();
+        assert_eq!(store.consume("tenant1", "initiate", "2026-01-01", 3, Some(5)).await.unwrap(), Some(3));
+        assert_eq!(store.consume("tenant1", "initiate", "2026-01-01", 2, Some(5)).await.unwrap(), Some(5));
+
+        // A refused amount is not counted, and counters are per tenant and route
+        assert_eq!(store.consume("tenant1", "initiate", "2026-01-01", 1, Some(5)).await.unwrap(), None);
+        assert_eq!(store.consume("tenant1", "files", "2026-01-01", 1, None).await.unwrap(), Some(1));
+        assert_eq!(store.consume("tenant2", "initiate", "2026-01-01", 4, None).await.unwrap(), Some(4));
+        let usage = store.usage("2026-01-01", Some("tenant1")).await.unwrap();
+        assert_eq!(usage.iter().map(|u| (u.route.as_str(), u.count)).collect::<Vec<_>>(), vec![("files", 1), ("initiate", 5)]);
+
+        // Refunds free up the quota again, but never below zero
+        store.refund("tenant1", "initiate", "2026-01-01", 2).await.unwrap();
+        assert_eq!(store.consume("tenant1", "initiate", "2026-01-01", 2, Some(5)).await.unwrap(), Some(5));
+        store.refund("tenant1", "files", "2026-01-01", 3).await.unwrap();
+        assert_eq!(store.consume("tenant1", "files", "2026-01-01", 1, None).await.unwrap(), Some(1));
+
+        // A new day starts from zero
+        assert_eq!(store.consume("tenant1", "initiate", "2026-01-02", 5, Some(5)).await.unwrap(), Some(5));
+        assert!(store.usage("2026-01-01", None).await.unwrap().is_empty());
+        assert_eq!(store.usage("2026-01-02", None).await.unwrap().len(), 1);
+    }
+}
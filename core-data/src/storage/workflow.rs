@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::models::workflow::{Workflow, WorkflowStatus};
+use super::StorageError;
+
+/// Workflow definitions, one entry per `(id, version)`.
+#[async_trait]
+pub trait WorkflowStore: Send + Sync {
+    /// Stores a new version. Fails with `StorageError::Conflict` when the version already exists
+    /// or the workflow id belongs to another tenant, as messages refer to workflows by id alone.
+    async fn create(&self, workflow: &Workflow) -> Result<(), StorageError>;
+
+    async fn get(&self, workflow_id: &str, version: u16) -> Result<Option<Workflow>, StorageError>;
+
+    /// Every version of the workflow, oldest first.
+    async fn versions(&self, workflow_id: &str) -> Result<Vec<Workflow>, StorageError>;
+
+    /// Moves the version from `from` to `to`. Fails with `StorageError::Conflict` when it is
+    /// no longer in `from`, so concurrent status changes cannot both apply.
+    async fn set_status(&self, workflow_id: &str, version: u16, from: &WorkflowStatus, to: &WorkflowStatus) -> Result<(), StorageError>;
+
+    /// Moves the version from `from` to `Active` and deprecates the other active versions, so
+    /// new messages only start on one. Returns the deprecated versions; fails like `set_status`.
+    async fn activate(&self, workflow_id: &str, version: u16, from: &WorkflowStatus) -> Result<Vec<u16>, StorageError>;
+}
+
+/// Keeps workflows in process memory; for tests and single-process setups.
+#[derive(Default)]
+pub struct InMemoryWorkflowStore {
+    workflows: Mutex<BTreeMap<(String, u16), Workflow>>,
+}
+
+impl InMemoryWorkflowStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl WorkflowStore for InMemoryWorkflowStore {
+    async fn create(&self, workflow: &Workflow) -> Result<(), StorageError> {
+        let mut workflows = self.workflows.lock().unwrap();
+        let key = (workflow.id.clone(), workflow.version);
+        if workflows.contains_key(&key) {
+            return Err(StorageError::Conflict(format!(
+                "Workflow {} version {} already exists", workflow.id, workflow.version
+            )));
+        }
+        if workflows.values().any(|w| w.id == workflow.id && w.tenant != workflow.tenant) {
+            return Err(StorageError::Conflict(format!(
+                "Workflow {} belongs to another tenant", workflow.id
+            )));
+        }
+        workflows.insert(key, workflow.clone());
+        Ok(())
+    }
+
+    async fn get(&self, workflow_id: &str, version: u16) -> Result<Option<Workflow>, StorageError> {
+        Ok(self.workflows.lock().unwrap().get(&(workflow_id.to_string(), version)).cloned())
+    }
+
+    async fn versions(&self, workflow_id: &str) -> Result<Vec<Workflow>, StorageError> {
+        Ok(self.workflows.lock().unwrap().values()
+            .filter(|w| w.id == workflow_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn set_status(&self, workflow_id: &str, version: u16, from: &WorkflowStatus, to: &WorkflowStatus) -> Result<(), StorageError> {
+        let mut workflows = self.workflows.lock().unwrap();
+        match workflows.get_mut(&(workflow_id.to_string(), version)) {
+            Some(workflow) if &workflow.status == from => {
+                workflow.status = to.clone();
+                Ok(())
+            }
+            _ => Err(StorageError::Conflict(format!(
+                "Workflow {} version {} is not {:?}", workflow_id, version, from
+            ))),
+        }
+    }
+
+    async fn activate(&self, workflow_id: &str, version: u16, from: &WorkflowStatus) -> Result<Vec<u16>, StorageError> {
+        let mut workflows = self.workflows.lock().unwrap();
+        match workflows.get_mut(&(workflow_id.to_string(), version)) {
+            Some(workflow) if &workflow.status == from => workflow.status = WorkflowStatus::Active,
+            _ => return Err(StorageError::Conflict(format!(
+                "Workflow {} version {} is not {:?}", workflow_id, version, from
+            ))),
+        }
+        let mut deprecated = Vec::new();
+        for workflow in workflows.values_mut()
+            .filter(|w| w.id == workflow_id && w.version != version && w.status == WorkflowStatus::Active)
+        {
+            workflow.status = WorkflowStatus::Deprecated;
+            deprecated.push(workflow.version);
+        }
+        Ok(deprecated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(version: u16, status: WorkflowStatus) -> Workflow {
+        let mut workflow: Workflow = serde_json::from_str(include_str!("../../../sample-workflow.json")).unwrap();
+        workflow.version = version;
+        workflow.status = status;
+        workflow
+    }
+
+    #[tokio::test]
+    async fn test_workflow_management() {
+        let store = InMemoryWorkflowStore::new();
+        let draft = sample(1, WorkflowStatus::Draft);
+        store.create(&draft).await.unwrap();
+        assert!(matches!(store.create(&draft).await, Err(StorageError::Conflict(_))));
+        store.create(&sample(2, WorkflowStatus::Draft)).await.unwrap();
+        let versions: Vec<u16> = store.versions("payment_processing").await.unwrap().iter().map(|w| w.version).collect();
+        assert_eq!(versions, vec![1, 2]);
+        assert!(store.get("payment_processing", 3).await.unwrap().is_none());
+
+        store.set_status("payment_processing", 1, &WorkflowStatus::Draft, &WorkflowStatus::Active).await.unwrap();
+        // A concurrent activation that read the draft loses
+        assert!(matches!(
+            store.set_status("payment_processing", 1, &WorkflowStatus::Draft, &WorkflowStatus::Active).await,
+            Err(StorageError::Conflict(_))
+        ));
+        assert_eq!(store.get("payment_processing", 1).await.unwrap().unwrap().status, WorkflowStatus::Active);
+
+        // Activating a version retires the one running before it
+        assert_eq!(store.activate("payment_processing", 2, &WorkflowStatus::Draft).await.unwrap(), vec![1]);
+        assert_eq!(store.get("payment_processing", 1).await.unwrap().unwrap().status, WorkflowStatus::Deprecated);
+        assert_eq!(store.get("payment_processing", 2).await.unwrap().unwrap().status, WorkflowStatus::Active);
+        assert!(matches!(
+            store.activate("payment_processing", 2, &WorkflowStatus::Draft).await,
+            Err(StorageError::Conflict(_))
+        ));
+
+        // Ids are owned by the tenant that created them
+        let mut foreign = sample(3, WorkflowStatus::Draft);
+        foreign.tenant = "tenant2".to_string();
+        assert!(matches!(store.create(&foreign).await, Err(StorageError::Conflict(_))));
+    }
+}
@@ -48,12 +48,12 @@ fn test_message_lifecycle() {
     let enrichment_config = vec![
         EnrichmentRules {
             field: "data.metadata.processing_date".to_string(),
-            logic: json!({"var": ["processing_date"]}),
+            logic: json!({"var": ["processing_date"]}).into(),
             description: Some("Add processing timestamp".to_string()),
         },
         EnrichmentRules {
             field: "data.metadata.message_type".to_string(),
-            logic: json!({"var": ["message_type"]}),
+            logic: json!({"var": ["message_type"]}).into(),
             description: Some("Add message classification".to_string()),
         }
     ];
@@ -88,4 +88,47 @@ fn test_message_lifecycle() {
     assert_eq!(audit_trail[0].description(), "Payment created");
     assert_eq!(audit_trail[1].description(), "Parsed payment message");
     assert_eq!(audit_trail[2].description(), "Applied metadata enrichment");
-}
\ No newline at end of file
+}
+#[test]
+fn test_validate_payload() {
+    let xml = fs::read_to_string("examples/pacs008_001_07_cct_outgoing.xml")
+        .expect("Failed to read test XML file");
+    let message_with = |xml: &str| Message::new(
+        Payload::new_inline(Some(xml.as_bytes().to_vec()), PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8),
+        "banking".to_string(),
+        "api".to_string(),
+        "test_validate_payload".to_string(),
+        1,
+        "initiate".to_string(),
+        None,
+    );
+
+    let valid = message_with(&xml);
+    assert!(valid.validate_payload().is_ok());
+    // Nothing is recorded on the message
+    assert_eq!(valid.audit().len(), 1);
+    assert!(valid.data().is_null());
+
+    let too_long = xml.replace("VOLCUSTMSGID0001", &"X".repeat(36));
+    let errors = message_with(&too_long).validate_payload().unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, "FIToFICstmrCdtTrf.GrpHdr.MsgId");
+    assert!(errors[0].code.is_some());
+
+    // Every violation is reported, each at the element it was found in
+    let several = too_long
+        .replace("<Cd>ACCEPT</Cd>", &format!("<Cd>{}</Cd>", "X".repeat(36)))
+        .replacen("<Ctry>US</Ctry>", "<Ctry>us</Ctry>", 1);
+    let errors = message_with(&several).validate_payload().unwrap_err();
+    let paths: Vec<&str> = errors.iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(paths, vec![
+        "FIToFICstmrCdtTrf.GrpHdr.MsgId",
+        "FIToFICstmrCdtTrf.CdtTrfTxInf.PmtTpInf.LclInstrm.Cd",
+        "FIToFICstmrCdtTrf.CdtTrfTxInf.Dbtr.PstlAdr.Ctry",
+    ], "{:?}", errors);
+
+    let malformed = xml.replace("<MsgId>VOLCUSTMSGID0001</MsgId>", "");
+    let errors = message_with(&malformed).validate_payload().unwrap_err();
+    assert_eq!(errors[0].code, None);
+    assert!(errors[0].path.contains("GrpHdr"), "{:?}", errors);
+}
@@ -1,7 +1,8 @@
 use serde_json::json;
 use core_data::models::workflow::*;
 use core_data::models::task::*;
-use core_data::models::message::MessageStatus;
+use core_data::models::message::*;
+use core_data::rules::*;
 
 #[cfg(test)]
 mod tests {
@@ -16,9 +17,13 @@ mod tests {
             message_status: MessageStatus::Recieved,
             prev_task: String::from("prev_task"),
             prev_status_code: None,
-            condition: json!({"condition": "value"}),
+            condition: json!({"condition": "value"}).into(),
             function: FunctionType::Validate,
             input: json!({"input": "value"}),
+            retry: None,
+            timeout_ms: None,
+            join: None,
+            compensation: None,
         };
         let workflow = Workflow {
             id: String::from("workflow_1"),
@@ -28,10 +33,13 @@ mod tests {
             tenant: String::from("tenant"),
             origin: String::from("origin"),
             status: WorkflowStatus::Active,
-            condition: json!({"condition": "value"}),
+            condition: json!({"condition": "value"}).into(),
             tasks: vec![task.clone()],
             input_topic: String::from("input_topic"),
             persist_on_complete: false,
+            timeout_ms: None,
+            condition_context: ConditionContext::Metadata,
+            sub_workflow: false,
         };
         assert_eq!(workflow.name, String::from("Workflow 1"));
         assert_eq!(workflow.description, String::from("Test workflow"));
@@ -51,10 +59,13 @@ mod tests {
             tenant: String::from("tenant"),
             origin: String::from("origin"),
             status: WorkflowStatus::Draft,
-            condition: json!({"condition": "value"}),
+            condition: json!({"condition": "value"}).into(),
             tasks: vec![],
             input_topic: String::from("input_topic"),
             persist_on_complete: false,
+            timeout_ms: None,
+            condition_context: ConditionContext::Metadata,
+            sub_workflow: false,
         };
         assert_eq!(workflow.name, String::from("Empty Workflow"));
         assert_eq!(workflow.description, String::from("Workflow with no tasks"));
@@ -70,9 +81,13 @@ mod tests {
             message_status: MessageStatus::Recieved,
             prev_task: String::from("prev_task"),
             prev_status_code: None,
-            condition: json!({"condition": "value"}),
+            condition: json!({"condition": "value"}).into(),
             function: FunctionType::Validate,
             input: json!({"input": "value"}),
+            retry: None,
+            timeout_ms: None,
+            join: None,
+            compensation: None,
         };
         let task2 = Task {
             id: String::from("task_2"),
@@ -81,9 +96,13 @@ mod tests {
             message_status: MessageStatus::Recieved,
             prev_task: String::from("prev_task"),
             prev_status_code: None,
-            condition: json!({"condition": "value"}),
+            condition: json!({"condition": "value"}).into(),
             function: FunctionType::Enrich,
             input: json!({"input": "value"}),
+            retry: None,
+            timeout_ms: None,
+            join: None,
+            compensation: None,
         };
         let workflow = Workflow {
             id: String::from("workflow_3"),
@@ -93,10 +112,13 @@ mod tests {
             tenant: String::from("tenant"),
             origin: String::from("origin"),
             status: WorkflowStatus::Draft,
-            condition: json!({"condition": "value"}),
+            condition: json!({"condition": "value"}).into(),
             tasks: vec![task1.clone(), task2.clone()],
             input_topic: String::from("input_topic"),
             persist_on_complete: false,
+            timeout_ms: None,
+            condition_context: ConditionContext::Metadata,
+            sub_workflow: false,
         };
         assert_eq!(workflow.name, String::from("Workflow with Multiple Tasks"));
         assert_eq!(workflow.description, String::from("Workflow containing multiple tasks"));
@@ -104,4 +126,640 @@ mod tests {
         assert_eq!(workflow.tasks[0], task1);
         assert_eq!(workflow.tasks[1], task2);
     }
-}
\ No newline at end of file
+
+    /// Version 1 of an active workflow running `steps` one after the other from `initiate`, the
+    /// first on a `Recieved` message and the rest on `Processing` ones. Tests change what they
+    /// need on the result.
+    fn workflow_of(steps: &[(&str, FunctionType, serde_json::Value)]) -> Workflow {
+        let mut prev_task = "initiate";
+        let mut tasks = Vec::new();
+        for (id, function, input) in steps {
+            let message_status = if tasks.is_empty() { MessageStatus::Recieved } else { MessageStatus::Processing };
+            tasks.push(branch_task(id, prev_task, message_status, function.clone(), input.clone()));
+            prev_task = id;
+        }
+        Workflow {
+            id: String::from("payment_processing"),
+            name: String::from("Payment Processing"),
+            description: String::from("Versioned workflow"),
+            version: 1,
+            tenant: String::from("tenant1"),
+            origin: String::from("api"),
+            status: WorkflowStatus::Active,
+            condition: Rule::default(),
+            tasks,
+            input_topic: String::from("payment_incoming"),
+            persist_on_complete: false,
+            timeout_ms: None,
+            condition_context: ConditionContext::Metadata,
+            sub_workflow: false,
+        }
+    }
+
+    /// A single fetch of reference data tagged with the workflow version.
+    fn fetch_workflow(version: u16, status: WorkflowStatus) -> Workflow {
+        let mut workflow = workflow_of(&[("fetch_reference_data", FunctionType::Fetch, json!({"version": version}))]);
+        workflow.version = version;
+        workflow.status = status;
+        workflow
+    }
+
+    fn new_message() -> Message {
+        let payload = Payload::new_inline(None, PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+        Message::new(
+            payload,
+            String::from("tenant1"),
+            String::from("api"),
+            String::from("payment_processing"),
+            1,
+            String::from("initiate"),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_workflow_version_pinning() {
+        let v1 = fetch_workflow(1, WorkflowStatus::Active);
+        let mut message = new_message();
+        assert!(!message.is_pinned());
+
+        let selected = message.workflow_select(std::slice::from_ref(&v1)).unwrap();
+        assert_eq!(selected.version, 1);
+        assert!(message.execute_workflow(&v1).is_ok());
+        assert!(message.is_pinned());
+        assert_eq!(message.progress().workflow_version, 1);
+
+        // A newer version is activated while the message is in flight
+        let workflows = vec![fetch_workflow(1, WorkflowStatus::Deprecated), fetch_workflow(2, WorkflowStatus::Active)];
+        let resumed = message.workflow_select(&workflows).unwrap();
+        assert_eq!(resumed.version, 1);
+
+        // New messages pick up the latest active version
+        let mut fresh = new_message();
+        let selected = fresh.workflow_select(&workflows).unwrap();
+        assert_eq!(selected.version, 2);
+        assert!(fresh.execute_workflow(selected).is_ok());
+        assert_eq!(fresh.progress().workflow_version, 2);
+        assert_eq!(fresh.audit().last().unwrap().workflow_version(), 2);
+    }
+
+    #[test]
+    fn test_workflow_validate() {
+        let sample = std::fs::read_to_string("../sample-workflow.json").unwrap();
+        let workflow: Workflow = serde_json::from_str(&sample).unwrap();
+        assert!(workflow.validate().is_ok());
+
+        let mut invalid = fetch_workflow(1, WorkflowStatus::Active);
+        let mut duplicate = invalid.tasks[0].clone();
+        duplicate.prev_task = duplicate.id.clone();
+        invalid.tasks.push(duplicate);
+        let errors = invalid.validate().unwrap_err();
+        assert!(errors.iter().any(|e| e.contains("Duplicate task id")));
+        assert!(errors.iter().any(|e| e.contains("cyclic")));
+
+        // Rules that cannot run are reported with where they are
+        let mut invalid = fetch_workflow(1, WorkflowStatus::Active);
+        invalid.condition = json!({"==": [{"var": "metadata.channel"}]}).into();
+        invalid.tasks.push(branch_task("enrich", "fetch_reference_data", MessageStatus::Processing, FunctionType::Enrich,
+            json!([{"field": "metadata.rate", "logic": {"fx_rate": ["EUR", "USD"]}, "description": null}])));
+        assert_eq!(invalid.validate().unwrap_err(), vec![
+            "Workflow condition: Operator '==' takes exactly 2 arguments, got 1",
+            "Task 'enrich' rule for 'metadata.rate': Unknown operator 'fx_rate'",
+        ]);
+    }
+
+    #[test]
+    fn test_workflow_diff() {
+        let sample = std::fs::read_to_string("../sample-workflow.json").unwrap();
+        let workflow: Workflow = serde_json::from_str(&sample).unwrap();
+        let round_trip: Workflow = serde_json::from_value(serde_json::to_value(&workflow).unwrap()).unwrap();
+        assert_eq!(round_trip, workflow);
+
+        let draft = fetch_workflow(1, WorkflowStatus::Draft);
+        let mut next = fetch_workflow(2, WorkflowStatus::Draft);
+        next.tasks[0].input = json!({"version": 3});
+        next.tasks.push(branch_task("publish", "fetch_reference_data", MessageStatus::Processing, FunctionType::Publish, json!({})));
+
+        let changes = draft.diff(&next);
+        let paths: Vec<&str> = changes.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, vec!["tasks.fetch_reference_data.input.version", "tasks.publish", "version"]);
+        assert!(changes[1].from.is_none());
+        assert!(draft.diff(&draft).is_empty());
+
+        assert!(WorkflowStatus::Draft.can_transition_to(&WorkflowStatus::Active));
+        assert!(!WorkflowStatus::Draft.can_transition_to(&WorkflowStatus::Deprecated));
+        assert!(!WorkflowStatus::Active.can_transition_to(&WorkflowStatus::Draft));
+    }
+
+    #[test]
+    fn test_task_retry_policy() {
+        let mut workflow = fetch_workflow(1, WorkflowStatus::Active);
+        workflow.tasks[0].function = FunctionType::Parse;
+        workflow.tasks[0].retry = Some(RetryPolicy {
+            max_attempts: 3,
+            backoff: BackoffStrategy::Exponential,
+            delay_ms: 1000,
+            max_delay_ms: None,
+            retryable_codes: vec![400],
+        });
+
+        // The payload has no content, so parsing fails with a 400 every time
+        let mut message = new_message();
+        for attempt in 1..3 {
+            let outcome = message.execute_workflow(&workflow).ok().unwrap();
+            assert!(matches!(outcome, WorkflowOutcome::RetryScheduled { .. }));
+            assert_eq!(message.progress().attempt, attempt);
+            assert_eq!(message.progress().status, MessageStatus::Recieved);
+            assert!(message.progress().retry_at.is_some());
+            assert!(message.is_pinned());
+        }
+        assert_eq!(message.audit().len(), 3);
+
+        assert!(message.execute_workflow(&workflow).is_err());
+        assert_eq!(message.progress().status, MessageStatus::Failed);
+        assert_eq!(message.progress().retry_at, None);
+
+        let policy = workflow.tasks[0].retry.as_ref().unwrap();
+        assert_eq!(policy.delay_ms(1), 1000);
+        assert_eq!(policy.delay_ms(3), 4000);
+        assert!(!policy.is_retryable(500));
+    }
+
+    #[test]
+    fn test_repair_and_cancel() {
+        let mut workflow = fetch_workflow(1, WorkflowStatus::Active);
+        workflow.tasks[0].function = FunctionType::Parse;
+
+        // Only a failed message can be repaired
+        let mut message = new_message();
+        assert_eq!(message.repair(String::from("Nothing to repair")).unwrap_err().code, 409);
+        assert!(message.execute_workflow(&workflow).is_err());
+        assert_eq!(message.progress().status, MessageStatus::Failed);
+        assert_eq!(message.progress().prev_task, "fetch_reference_data");
+
+        // Repairing runs the failed task again
+        assert!(message.repair(String::from("Parser fixed")).is_ok());
+        assert_eq!(message.progress().status, MessageStatus::Repaired);
+        assert!(message.execute_workflow(&fetch_workflow(1, WorkflowStatus::Active)).is_ok());
+        assert_eq!(message.progress().status, MessageStatus::Processing);
+        assert_eq!(message.progress().prev_status_code, Some(StatusCode::Success));
+        assert!(message.progress().tokens.iter().any(|t| t.task == "fetch_reference_data"));
+
+        assert!(message.cancel(String::from("Cancelled by customer")).is_ok());
+        assert_eq!(message.progress().status, MessageStatus::Cancelled);
+        assert_eq!(message.repair(String::from("Too late")).unwrap_err().code, 409);
+    }
+
+    #[test]
+    fn test_workflow_timeout() {
+        // Fetches a long list of amounts, then sums it once for each of `rules` enrichment rules
+        let amounts: Vec<u64> = (0..50_000).collect();
+        let sum = json!({"reduce": [{"var": "amounts"}, {"+": [{"var": "current"}, {"var": "accumulator"}]}, 0]});
+        let slow_workflow = |rules: usize| {
+            let rules: Vec<_> = (0..rules)
+                .map(|i| json!({"field": format!("metadata.sum_{}", i), "logic": sum, "description": null}))
+                .collect();
+            workflow_of(&[
+                ("fetch_amounts", FunctionType::Fetch, json!({"amounts": amounts})),
+                ("sum_amounts", FunctionType::Enrich, json!(rules)),
+            ])
+        };
+
+        let mut workflow = fetch_workflow(1, WorkflowStatus::Active);
+        workflow.timeout_ms = Some(60_000);
+        workflow.tasks[0].timeout_ms = Some(60_000);
+
+        // Generous limits do not affect normal execution
+        let mut message = new_message();
+        assert!(message.execute_workflow(&workflow).is_ok());
+        assert_eq!(message.progress().prev_status_code, Some(StatusCode::Success));
+
+        // How long the slow task takes with a single rule
+        let mut message = new_message();
+        let started = std::time::Instant::now();
+        assert!(message.execute_workflow(&slow_workflow(1)).is_ok());
+        let one_rule = started.elapsed();
+
+        // A task that runs out of time stops evaluating its remaining rules
+        let mut workflow = slow_workflow(20);
+        workflow.tasks[1].timeout_ms = Some(1);
+        let mut message = new_message();
+        let started = std::time::Instant::now();
+        let error = message.execute_workflow(&workflow).unwrap_err();
+        assert!(started.elapsed() < one_rule * 5);
+        assert_eq!(error.code, TIMEOUT_CODE as u16);
+        assert_eq!(message.progress().status, MessageStatus::Failed);
+        assert_eq!(message.progress().prev_status_code, Some(StatusCode::Timeout));
+        assert_eq!(message.progress().prev_task, "sum_amounts");
+        assert!(message.metadata().get("sum_0").is_none());
+        let audit = message.audit().last().unwrap();
+        assert_eq!(audit.changes()[0].field(), "progress.status");
+        assert_eq!(audit.changes()[1].new_value(), Some(&json!("Timeout")));
+
+        // So does a workflow
+        let mut workflow = slow_workflow(20);
+        workflow.timeout_ms = Some(1);
+        let mut message = new_message();
+        let started = std::time::Instant::now();
+        let error = message.execute_workflow(&workflow).unwrap_err();
+        assert!(started.elapsed() < one_rule * 5);
+        assert_eq!(error.code, TIMEOUT_CODE as u16);
+        assert_eq!(message.progress().status, MessageStatus::Failed);
+        assert_eq!(message.progress().prev_status_code, Some(StatusCode::Timeout));
+    }
+
+    fn branch_task(id: &str, prev_task: &str, message_status: MessageStatus, function: FunctionType, input: serde_json::Value) -> Task {
+        Task {
+            id: String::from(id),
+            name: String::from(id),
+            description: format!("Run {}", id),
+            message_status,
+            prev_task: String::from(prev_task),
+            prev_status_code: Some(StatusCode::Success),
+            condition: Rule::default(),
+            function,
+            input,
+            retry: None,
+            timeout_ms: None,
+            join: None,
+            compensation: None,
+        }
+    }
+
+    #[test]
+    fn test_workflow_fan_out_and_join() {
+        let mut workflow = workflow_of(&[]);
+        let mut combine = branch_task("combine", "", MessageStatus::Processing, FunctionType::Enrich, json!([
+            {"field": "metadata.fx_rate", "logic": {"var": "fx_rate"}, "description": null},
+            {"field": "metadata.sanctions_hit", "logic": {"var": "sanctions_hit"}, "description": null}
+        ]));
+        combine.join = Some(Join {
+            tasks: vec![String::from("fetch_fx"), String::from("screen_sanctions")],
+            mode: JoinMode::All,
+        });
+        workflow.tasks = vec![
+            combine,
+            branch_task("fetch_fx", "initiate", MessageStatus::Recieved, FunctionType::Fetch, json!({"fx_rate": 1.1})),
+            branch_task("screen_sanctions", "initiate", MessageStatus::Recieved, FunctionType::Fetch, json!({"sanctions_hit": false})),
+        ];
+        assert!(workflow.validate().is_ok());
+        assert!(workflow.is_ancestor("fetch_fx", "combine"));
+        assert!(!workflow.is_ancestor("fetch_fx", "screen_sanctions"));
+
+        // Both branches run before the join, which sees both results
+        let mut message = new_message();
+        assert!(message.execute_workflow(&workflow).is_ok());
+        assert_eq!(message.progress().prev_task, "combine");
+        assert_eq!(message.metadata()["fx_rate"], json!(1.1));
+        assert_eq!(message.metadata()["sanctions_hit"], json!(false));
+        let tasks: Vec<&str> = message.progress().tokens.iter().map(|t| t.task.as_str()).collect();
+        assert_eq!(tasks, vec!["initiate", "fetch_fx", "screen_sanctions", "combine"]);
+
+        // Branches writing the same field conflict and the later one is undone
+        workflow.tasks[2].input = json!({"fx_rate": 1.2});
+        let mut message = new_message();
+        assert!(message.execute_workflow(&workflow).is_err());
+        assert_eq!(message.progress().status, MessageStatus::Failed);
+        assert_eq!(message.progress().prev_task, "screen_sanctions");
+        assert_eq!(message.audit().len(), 4);
+        assert_eq!(message.audit()[1].description(), "Run fetch_fx");
+        let failure = message.audit().last().unwrap();
+        assert_eq!((failure.task(), failure.changes()[0].field()), ("screen_sanctions", "progress.status"));
+
+        // Outside parallel branches a fetch replaces what was fetched before
+        workflow.tasks = vec![
+            branch_task("fetch_fx", "initiate", MessageStatus::Recieved, FunctionType::Fetch, json!({"fx_rate": 1.1})),
+            branch_task("fetch_limits", "fetch_fx", MessageStatus::Processing, FunctionType::Fetch, json!({"limit": 10000})),
+        ];
+        let mut message = new_message();
+        assert!(message.execute_workflow(&workflow).is_ok());
+        assert_eq!(message.condition_context()["fetched"], json!({"limit": 10000}));
+    }
+
+    #[test]
+    fn test_sub_workflow() {
+        let mut screening = fetch_workflow(1, WorkflowStatus::Active);
+        screening.id = String::from("screening");
+        screening.tasks[0].input = json!({"sanctions_hit": false});
+        screening.sub_workflow = true;
+
+        let parent = workflow_of(&[
+            ("screen", FunctionType::SubWorkflow, json!({"workflow_id": "screening", "version": 1})),
+            ("fetch_fx", FunctionType::Fetch, json!({"fx_rate": 1.1})),
+        ]);
+        assert!(parent.validate().is_ok());
+        let workflows = vec![parent.clone(), screening];
+
+        // Only the parent is picked for new messages, wherever the sub-workflow is listed
+        let reversed = [workflows[1].clone(), parent.clone()];
+        assert_eq!(new_message().workflow_select(&reversed).map(|w| w.id.as_str()), Some("payment_processing"));
+        assert!(new_message().workflow_select(&reversed[..1]).is_none());
+
+        let mut message = new_message();
+        assert_eq!(message.execute_workflow_with(&parent, &workflows).ok(), Some(WorkflowOutcome::Finished));
+        assert_eq!(message.progress().workflow_id, "payment_processing");
+        assert_eq!(message.progress().prev_task, "fetch_fx");
+
+        // The sub-workflow's entries sit under the invoking task
+        let screen = &message.audit()[1];
+        assert_eq!(screen.task(), "screen");
+        assert_eq!(screen.children().len(), 2);
+        assert_eq!(screen.children()[0].workflow(), "screening");
+        assert_eq!(screen.children()[0].changes()[0].field(), "ephemeral_data.sanctions_hit");
+        assert_eq!(screen.children()[1].changes()[0].field(), "progress.status");
+
+        // Unknown versions fail the parent task
+        let mut message = new_message();
+        assert!(message.execute_workflow_with(&parent, &workflows[..1]).is_err());
+        assert_eq!(message.progress().prev_task, "screen");
+        assert_eq!(message.progress().prev_status_code, Some(StatusCode::Failure));
+
+        // Without the workflows to resolve them against, nothing runs
+        let mut message = new_message();
+        assert_eq!(message.execute_workflow(&parent).err().map(|e| e.code), Some(400));
+        assert_eq!(message.progress().status, MessageStatus::Recieved);
+        assert_eq!(message.audit().len(), new_message().audit().len());
+
+        // A failed sub-workflow completes the task with a failure the parent can route on
+        let mut failing = workflows[1].clone();
+        failing.tasks[0].function = FunctionType::Enrich;
+        failing.tasks[0].input = json!([{"field": "payload.hit", "logic": true, "description": null}]);
+        let mut parent = parent.clone();
+        parent.tasks.push(branch_task("manual_screening", "screen", MessageStatus::Processing, FunctionType::Fetch, json!({"manual": true})));
+        parent.tasks[2].prev_status_code = Some(StatusCode::Failure);
+        let workflows = vec![parent.clone(), failing];
+        let mut message = new_message();
+        assert_eq!(message.execute_workflow_with(&parent, &workflows).ok(), Some(WorkflowOutcome::Finished));
+        assert_eq!(message.progress().prev_task, "manual_screening");
+        assert_eq!(message.progress().tokens[1].status_code, Some(StatusCode::Failure));
+        assert_eq!(message.audit()[1].children().last().unwrap().changes()[0].field(), "progress.status");
+
+        let mut recursive = parent.clone();
+        recursive.tasks[0].input = json!({"workflow_id": "payment_processing", "version": 1});
+        assert!(recursive.validate().unwrap_err().iter().any(|e| e.contains("invokes its own workflow")));
+    }
+
+    #[test]
+    fn test_approval_suspend_and_resume() {
+        let workflow = workflow_of(&[
+            ("fetch_limits", FunctionType::Fetch, json!({"limit": 10000})),
+            ("manual_review", FunctionType::Approval, serde_json::Value::Null),
+            ("fetch_fx", FunctionType::Fetch, json!({"fx_rate": 1.1})),
+        ]);
+        let mut message = new_message();
+        assert_eq!(
+            message.execute_workflow(&workflow).ok(),
+            Some(WorkflowOutcome::Suspended { task_id: String::from("manual_review") })
+        );
+        assert_eq!(message.progress().status, MessageStatus::Suspended);
+
+        // Nothing runs while suspended
+        assert_eq!(message.execute_workflow(&workflow).ok(), Some(WorkflowOutcome::Finished));
+        assert_eq!(message.progress().prev_task, "manual_review");
+
+        let decision = ApprovalDecision {
+            approved: true,
+            approver: String::from("ops@bank.example"),
+            reason: String::from("Verified with customer"),
+        };
+        assert!(message.resume(&fetch_workflow(2, WorkflowStatus::Active), &decision).is_err());
+        assert!(message.resume(&workflow, &decision).is_ok());
+        assert!(message.resume(&workflow, &decision).is_err());
+        let audit = message.audit().last().unwrap();
+        assert_eq!(audit.description(), "Approved by ops@bank.example: Verified with customer");
+        assert_eq!(audit.changes()[1].new_value(), serde_json::to_value(&decision).ok().as_ref());
+
+        assert!(message.execute_workflow(&workflow).is_ok());
+        assert_eq!(message.progress().prev_task, "fetch_fx");
+        assert_eq!(message.progress().status, MessageStatus::Processing);
+
+        // Rejections end the message and compensate what ran before the review
+        let mut workflow = workflow;
+        workflow.tasks[0].compensation = Some(Compensation {
+            description: String::from("Release limit"),
+            function: FunctionType::Fetch,
+            input: json!({"limit": null}),
+        });
+        let mut message = new_message();
+        assert!(message.execute_workflow(&workflow).is_ok());
+        assert!(message.resume(&workflow, &ApprovalDecision { approved: false, ..decision }).is_ok());
+        assert_eq!(message.progress().status, MessageStatus::Rejected);
+        assert_eq!(message.progress().prev_status_code, Some(StatusCode::Failure));
+        let compensation = message.audit().last().unwrap();
+        assert_eq!((compensation.task(), compensation.description()), ("fetch_limits.compensation", "Release limit"));
+    }
+
+    #[test]
+    fn test_scheduled_execution() {
+        let schedule_workflow = |schedule: serde_json::Value| workflow_of(&[
+            ("warehouse", FunctionType::Schedule, schedule),
+            ("fetch_fx", FunctionType::Fetch, json!({"fx_rate": 1.1})),
+        ]);
+        let workflow = schedule_workflow(json!({"at": "2999-01-01"}));
+        assert!(workflow.validate().is_ok());
+        let mut message = new_message();
+        let due_at = match message.execute_workflow(&workflow).ok() {
+            Some(WorkflowOutcome::Scheduled { task_id, due_at }) if task_id == "warehouse" => due_at,
+            other => panic!("Unexpected outcome {:?}", other),
+        };
+        assert_eq!(due_at.year(), 2999);
+        assert_eq!(message.progress().status, MessageStatus::Scheduled);
+
+        // Released messages continue after the schedule task
+        assert!(message.wake().is_ok());
+        assert!(message.execute_workflow(&workflow).is_ok());
+        assert_eq!(message.progress().prev_task, "fetch_fx");
+
+        // Past instants run straight through
+        let mut message = new_message();
+        assert_eq!(message.execute_workflow(&schedule_workflow(json!({"at": "2000-01-01T09:00:00Z"}))).ok(), Some(WorkflowOutcome::Finished));
+        assert_eq!(message.progress().prev_task, "fetch_fx");
+
+        let message = new_message();
+        let cutoff = schedule_workflow(json!({"cutoff": "17:00"}));
+        let due_at = message.scheduled_for(&cutoff.tasks[0]).ok().unwrap();
+        assert!(due_at > time::OffsetDateTime::now_utc());
+        assert_eq!((due_at.hour(), due_at.minute()), (17, 0));
+
+        // Expressions that do not yield an instant fail the task
+        let mut message = new_message();
+        assert!(message.execute_workflow(&schedule_workflow(json!({"at": {"var": "data.missing"}}))).is_err());
+        assert_eq!(message.progress().status, MessageStatus::Failed);
+        assert!(schedule_workflow(json!({"every": "day"})).validate().is_err());
+    }
+
+    #[test]
+    fn test_compensation_on_failure() {
+        let compensation = |description: &str, input: serde_json::Value| Some(Compensation {
+            description: String::from(description),
+            function: FunctionType::Fetch,
+            input,
+        });
+        let mut workflow = workflow_of(&[
+            ("reserve_funds", FunctionType::Fetch, json!({"reservation": "R-1"})),
+            ("post_ledger", FunctionType::Fetch, json!({"posting": "P-1"})),
+            ("fetch_fx", FunctionType::Fetch, json!({"fx_rate": 1.1})),
+            // The payload has no content, so parsing fails
+            ("parse", FunctionType::Parse, serde_json::Value::Null),
+        ]);
+        workflow.tasks[0].compensation = compensation("Release reservation", json!({"reservation": null}));
+        workflow.tasks[1].compensation = compensation("Reverse posting", json!({"posting": null}));
+        assert!(workflow.validate().is_ok());
+
+        let mut message = new_message();
+        assert!(message.execute_workflow(&workflow).is_err());
+        assert_eq!(message.progress().status, MessageStatus::Failed);
+        assert_eq!(message.progress().prev_task, "parse");
+
+        let compensations: Vec<(&str, &str)> = message.audit().iter()
+            .filter(|a| a.task().ends_with(".compensation"))
+            .map(|a| (a.task(), a.description()))
+            .collect();
+        assert_eq!(compensations, vec![
+            ("post_ledger.compensation", "Reverse posting"),
+            ("reserve_funds.compensation", "Release reservation"),
+        ]);
+
+        workflow.tasks[0].compensation.as_mut().unwrap().function = FunctionType::Approval;
+        assert!(workflow.validate().is_err());
+        workflow.tasks[0].compensation.as_mut().unwrap().function = FunctionType::Publish;
+        assert!(workflow.validate().unwrap_err().iter().any(|e| e.contains("cannot be a Publish function")));
+    }
+
+    #[test]
+    fn test_combined_condition_context() {
+        let mut workflow = workflow_of(&[
+            ("fetch_fx", FunctionType::Fetch, json!({"fx_rate": 1.1})),
+            ("convert", FunctionType::Fetch, json!({"converted": true})),
+        ]);
+        workflow.condition = json!({"==": [{"var": "payload_info.format"}, "Xml"]}).into();
+        workflow.tasks[1].condition = json!({">": [{"var": "fetched.fx_rate"}, 1]}).into();
+
+        // Existing workflows keep evaluating against metadata only
+        let message = new_message();
+        assert!(message.workflow_select(std::slice::from_ref(&workflow)).is_none());
+
+        workflow.condition_context = ConditionContext::Combined;
+        let mut message = new_message();
+        let selected = message.workflow_select(std::slice::from_ref(&workflow)).cloned().unwrap();
+        assert!(message.execute_workflow(&selected).is_ok());
+        assert_eq!(message.progress().prev_task, "convert");
+
+        let context = message.condition_context();
+        assert_eq!(context["progress"]["prev_task"], json!("convert"));
+        assert_eq!(context["fetched"]["converted"], json!(true));
+        assert_eq!(context["payload_info"]["schema"], json!("ISO20022"));
+    }
+
+    #[test]
+    fn test_rule_operators() {
+        let engine = RuleEngine::new();
+        let apply = |rule: serde_json::Value| engine.apply(&rule, &json!({"iban": "GB82 WEST 1234 5698 7654 32"})).unwrap();
+
+        assert_eq!(apply(json!({"iban_valid": [{"var": "iban"}]})), json!(true));
+        assert_eq!(apply(json!({"iban_valid": "GB82WEST12345698765433"})), json!(false));
+        assert_eq!(apply(json!({"bic_valid": "DEUTDEFF500"})), json!(true));
+        assert_eq!(apply(json!({"bic_valid": "deutdeff"})), json!(false));
+        assert_eq!(apply(json!({"lei_valid": "5493001KJTIIGC8Y1R12"})), json!(true));
+        assert_eq!(apply(json!({"lei_valid": "5493001KJTIIGC8Y1R13"})), json!(false));
+        assert_eq!(apply(json!({"country_of_bic": "DEUTDEFF"})), json!("DE"));
+        assert_eq!(apply(json!({"decimal_add": ["0.1", 0.2]})), json!("0.3"));
+        assert_eq!(apply(json!({"decimal_mul": ["10.005", "1", "EUR"]})), json!("10.01"));
+        assert_eq!(apply(json!({"decimal_mul": ["1234.5", "1", "JPY"]})), json!("1235"));
+        assert_eq!(apply(json!({"decimal_add": ["1.2", "0", "KWD"]})), json!("1.200"));
+        assert_eq!(apply(json!({"date_add_business_days": ["2024-05-31", 1]})), json!("2024-06-03"));
+        assert_eq!(apply(json!({"date_add_business_days": ["2024-06-03", -1]})), json!("2024-05-31"));
+        assert_eq!(apply(json!({"regex_match": ["INV-001", "^INV-[0-9]+$"]})), json!(true));
+        assert_eq!(apply(json!({"regex_match": ["PO-001", "^INV-[0-9]+$"]})), json!(false));
+        assert_eq!(apply(json!({"uuid": []})).as_str().map(str::len), Some(36));
+        assert!(apply(json!({"now": []})).as_str().is_some());
+
+        // Custom operators compose with the stock ones
+        assert_eq!(apply(json!({"and": [{"iban_valid": {"var": "iban"}}, {"==": [{"country_of_bic": "BNPAFRPP"}, "FR"]}]})), json!(true));
+        assert!(engine.apply(&json!({"decimal_add": ["abc", 1]}), &json!({})).is_err());
+        assert!(engine.apply(&json!({"decimal_add": [1, "abc"]}), &json!({})).is_err());
+        assert!(engine.apply(&json!({"decimal_mul": ["10.005", "NaN"]}), &json!({})).is_err());
+        assert!(engine.apply(&json!({"decimal_mul": ["10.005", "1", "eur"]}), &json!({})).is_err());
+        assert!(engine.apply(&json!({"regex_match": ["INV-001", "(INV"]}), &json!({})).is_err());
+
+        // Rules compiled at load time evaluate on the shared engine and serialize as plain JSONLogic
+        let rule: Rule = serde_json::from_value(json!({"country_of_bic": {"var": "bic"}})).unwrap();
+        assert_eq!(RuleEngine::shared().evaluate(&rule, &json!({"bic": "BNPAFRPP"})).unwrap(), json!("FR"));
+        assert_eq!(serde_json::to_value(&rule).unwrap(), json!({"country_of_bic": {"var": "bic"}}));
+        assert!(rule.errors().is_empty());
+
+        // Unknown operators and wrong argument counts are caught when compiling and never run
+        let rule = Rule::new(json!({"and": [{"iban_vaild": {"var": "iban"}}, {"==": [1]}, {"regex_match": ["INV-001"]}]}));
+        assert_eq!(rule.errors(), [
+            "Unknown operator 'iban_vaild'",
+            "Operator '==' takes exactly 2 arguments, got 1",
+            "Operator 'regex_match' takes exactly 2 arguments, got 1",
+        ]);
+        assert!(RuleEngine::shared().evaluate(&rule, &json!({})).is_err());
+        assert!(Rule::new(json!({"if": [true, {"preserve": {"a": 1, "b": 2}}, {"now": null}]})).errors().is_empty());
+    }
+
+    #[test]
+    fn test_validate_task() {
+        let rules = |bic: &str| json!([
+            {"rule": {"bic_valid": bic}, "message": "Creditor agent BIC is invalid"},
+            {"rule": {"==": [{"var": "fetched.fx_rate"}, 1.1]}, "message": "FX rate missing"},
+        ]);
+        let mut workflow = fetch_workflow(1, WorkflowStatus::Active);
+        workflow.tasks.push(branch_task("validate", "fetch_reference_data", MessageStatus::Processing, FunctionType::Validate, rules("DEUTDEFF")));
+        workflow.tasks[0].input = json!({"fx_rate": 1.1});
+        assert!(workflow.validate().is_ok());
+
+        let mut message = new_message();
+        assert_eq!(message.execute_workflow(&workflow).ok(), Some(WorkflowOutcome::Finished));
+        assert_eq!(message.progress().prev_task, "validate");
+        assert_eq!(message.audit().last().map(|a| a.task()), Some("validate"));
+
+        workflow.tasks[1].input = rules("NOT A BIC");
+        let mut message = new_message();
+        assert!(message.execute_workflow(&workflow).is_err());
+        assert_eq!(message.progress().status, MessageStatus::Failed);
+
+        workflow.tasks[1].input = json!({"rule": true});
+        assert!(workflow.validate().is_err());
+
+        // Malformed rules that reach the engine anyway fail the task instead of the worker
+        let mut message = new_message();
+        let error = message.execute_workflow(&workflow).unwrap_err();
+        assert!(error.desciption.contains("Invalid validation rules"), "{}", error.desciption);
+        assert_eq!(message.progress().status, MessageStatus::Failed);
+    }
+
+    #[test]
+    fn test_status_transitions() {
+        assert!(MessageStatus::Recieved.can_transition_to(&MessageStatus::Processing));
+        assert!(MessageStatus::Failed.can_transition_to(&MessageStatus::Repaired));
+        assert!(!MessageStatus::Completed.can_transition_to(&MessageStatus::Recieved));
+        assert!(!MessageStatus::Scheduled.can_transition_to(&MessageStatus::Rejected));
+
+        let mut message = new_message();
+        let audit_len = message.audit().len();
+        assert!(message.transition(MessageStatus::Processing, "screen", String::from("Screening started"), vec![]).is_ok());
+        assert!(message.transition(MessageStatus::Failed, "screen", String::from("Screening failed"), vec![]).is_ok());
+        let error = message.transition(MessageStatus::Completed, "screen", String::from("Done"), vec![]).unwrap_err();
+        assert_eq!(error.code, 409);
+        assert_eq!(message.progress().status, MessageStatus::Failed);
+
+        // Each transition is audited, staying put is not
+        assert!(message.transition(MessageStatus::Failed, "screen", String::from("Still failed"), vec![]).is_ok());
+        assert!(message.transition(MessageStatus::Repaired, "repair", String::from("Beneficiary corrected"), vec![]).is_ok());
+        let transitions: Vec<(Option<&serde_json::Value>, Option<&serde_json::Value>)> = message.audit()[audit_len..].iter()
+            .map(|a| (a.changes()[0].old_value(), a.changes()[0].new_value()))
+            .collect();
+        assert_eq!(transitions, vec![
+            (Some(&json!("Recieved")), Some(&json!("Processing"))),
+            (Some(&json!("Processing")), Some(&json!("Failed"))),
+            (Some(&json!("Failed")), Some(&json!("Repaired"))),
+        ]);
+
+        // Nothing runs once a message reaches a terminal status
+        assert!(message.transition(MessageStatus::Cancelled, "cancel", String::from("Cancelled by customer"), vec![]).is_ok());
+        assert!(message.execute_workflow(&fetch_workflow(1, WorkflowStatus::Active)).is_ok());
+        assert_eq!(message.progress().prev_task, "cancel");
+    }
+}
@@ -0,0 +1,215 @@
+use std::fmt;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Header carrying the request id; a caller's own id is kept when it is short and printable.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the request being handled, or empty outside of a request.
+pub fn request_id() -> String {
+    REQUEST_ID.try_with(Clone::clone).unwrap_or_default()
+}
+
+/// Body of every error response.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ApiError {
+    #[serde(skip)]
+    status: StatusCode,
+
+    /// Stable, machine-readable error code, e.g. `not_found` or `invalid_payload`
+    pub code: String,
+
+    pub message: String,
+
+    /// Individual problems, such as each failed payload rule
+    #[schema(value_type = Vec<Object>)]
+    pub details: Vec<Value>,
+
+    /// Matches the `X-Request-Id` response header and the server logs
+    pub request_id: String,
+}
+
+impl ApiError {
+    /// An error whose code is derived from the status, e.g. `bad_request`.
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        let code = status.canonical_reason()
+            .unwrap_or("error")
+            .to_lowercase()
+            .replace([' ', '-'], "_");
+        ApiError {
+            status,
+            code,
+            message: message.into(),
+            details: Vec::new(),
+            request_id: request_id(),
+        }
+    }
+
+    pub fn with_code(mut self, code: &str) -> Self {
+        self.code = code.to_string();
+        self
+    }
+
+    pub fn with_details(mut self, details: Vec<Value>) -> Self {
+        self.details = details;
+        self
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, message)
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(self)
+    }
+}
+
+/// Renders errors raised by the Json, Query and Path extractors in the error envelope.
+pub fn extractor_error(err: impl fmt::Display, _req: &HttpRequest) -> actix_web::Error {
+    ApiError::bad_request(err.to_string()).into()
+}
+
+/// Fallback for routes that do not exist.
+pub async fn not_found(req: HttpRequest) -> HttpResponse {
+    ApiError::not_found(format!("No route for {} {}", req.method(), req.path())).error_response()
+}
+
+/// Assigns the request id, makes it available to `request_id()` while the request is
+/// handled, and echoes it in the response. Errors are rendered inside the scope so their
+/// envelope carries the id.
+pub async fn assign_request_id(req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let id = req.headers().get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH)
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let header = HeaderValue::from_str(&id).ok();
+
+    match REQUEST_ID.scope(id, async move {
+        next.call(req).await.map_err(|e| {
+            let response = e.error_response();
+            (e, response)
+        })
+    }).await {
+        Ok(res) => {
+            let mut res = res.map_into_boxed_body();
+            if let Some(header) = header {
+                res.headers_mut().insert(HeaderName::from_static("x-request-id"), header);
+            }
+            Ok(res)
+        }
+        Err((e, mut response)) => {
+            if let Some(header) = header {
+                response.headers_mut().insert(HeaderName::from_static("x-request-id"), header);
+            }
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web, App};
+    use serde::Deserialize;
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Page {
+        limit: u64,
+    }
+
+    async fn page(query: web::Query<Page>) -> HttpResponse {
+        HttpResponse::Ok().json(query.limit)
+    }
+
+    async fn failing() -> Result<HttpResponse, ApiError> {
+        Err(ApiError::conflict("Already decided"))
+    }
+
+    #[actix_web::test]
+    async fn test_error_envelope_carries_the_request_id() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::QueryConfig::default().error_handler(extractor_error))
+                .service(web::resource("/page").route(web::get().to(page)))
+                .service(web::resource("/failing").route(web::get().to(failing)))
+                .default_service(web::to(not_found))
+                .wrap(actix_web::middleware::from_fn(assign_request_id))
+        ).await;
+
+        // A caller's own id is kept
+        let request = test::TestRequest::get().uri("/missing").insert_header((REQUEST_ID_HEADER, "req-42")).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers().get(REQUEST_ID_HEADER).unwrap(), "req-42");
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["code"], "not_found");
+        assert_eq!(body["request_id"], "req-42");
+
+        // Overlong ids are replaced, and extractor errors use the envelope
+        let request = test::TestRequest::get().uri("/page?limit=many")
+            .insert_header((REQUEST_ID_HEADER, "x".repeat(MAX_REQUEST_ID_LENGTH + 1)))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let id = response.headers().get(REQUEST_ID_HEADER).unwrap().to_str().unwrap().to_string();
+        assert!(Uuid::parse_str(&id).is_ok());
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["code"], "bad_request");
+        assert_eq!(body["request_id"], id.as_str());
+
+        // Errors returned by handlers are rendered inside the request scope
+        let response = test::call_service(&app, test::TestRequest::get().uri("/failing").to_request()).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let id = response.headers().get(REQUEST_ID_HEADER).unwrap().to_str().unwrap().to_string();
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["message"], "Already decided");
+        assert_eq!(body["request_id"], id.as_str());
+        assert!(!id.is_empty());
+    }
+}
@@ -0,0 +1,312 @@
+use core_data::models::message::{ApprovalDecision, MessageStatus};
+use core_data::models::workflow::Workflow;
+use core_data::storage::{ApprovalStore, ParkedMessage, StorageError, WorkflowStore};
+use serde::{Deserialize, Serialize};
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use utoipa::ToSchema;
+use tracing::{error, info, instrument, warn};
+use crate::config::config::*;
+use crate::initiate::publish_to_kafka;
+use crate::api::ApiError;
+use crate::auth::{can_access, Principal};
+
+#[derive(Deserialize, ToSchema)]
+pub struct DecisionRequest {
+    /// Only needed when authentication is disabled; the approver is otherwise the subject of
+    /// the credentials and any other name is refused
+    #[serde(default)]
+    pub approver: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PendingApproval {
+    pub message_id: String,
+    pub workflow_id: String,
+    pub workflow_version: u16,
+    /// Approval task the message is parked at
+    pub task_id: String,
+    pub tenant: String,
+    /// Unix time in seconds
+    pub parked_at: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DecisionResponse {
+    pub message_id: String,
+    /// Message status after the decision
+    #[schema(value_type = String)]
+    pub status: MessageStatus,
+}
+
+#[utoipa::path(
+    get,
+    path = "/approvals",
+    tag = "approvals",
+    responses(
+        (status = 200, description = "Messages awaiting approval", body = Vec<PendingApproval>),
+    ),
+)]
+#[instrument(skip(store, principal))]
+pub async fn list_approvals(store: web::Data<dyn ApprovalStore>, principal: Option<web::ReqData<Principal>>) -> impl Responder {
+    match store.list().await {
+        Ok(parked) => {
+            let pending: Vec<PendingApproval> = parked.iter()
+                .filter(|p| can_access(&principal, p.message.tenant()))
+                .map(|p| PendingApproval {
+                    message_id: p.message_id.clone(),
+                    workflow_id: p.workflow_id.clone(),
+                    workflow_version: p.workflow_version,
+                    task_id: p.task_id.clone(),
+                    tenant: p.message.tenant().clone(),
+                    parked_at: p.parked_at.unix_timestamp(),
+                })
+                .collect();
+            HttpResponse::Ok().json(pending)
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to list messages awaiting approval");
+            ApiError::internal(format!("Storage error: {}", e)).error_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/approvals/{message_id}/approve",
+    tag = "approvals",
+    params(("message_id" = String, Path)),
+    request_body = DecisionRequest,
+    responses(
+        (status = 200, description = "Decision published; the workflow continues", body = DecisionResponse),
+        (status = 400, description = "Approver missing, or not the subject of the credentials", body = ApiError),
+        (status = 404, description = "Message not awaiting approval", body = ApiError),
+        (status = 409, description = "Decision could not be applied, or the workflow version is unknown", body = ApiError),
+    ),
+)]
+pub async fn approve_message(
+    config: web::Data<AppConfig>,
+    store: web::Data<dyn ApprovalStore>,
+    workflows: web::Data<dyn WorkflowStore>,
+    principal: Option<web::ReqData<Principal>>,
+    message_id: web::Path<String>,
+    body: web::Json<DecisionRequest>,
+) -> impl Responder {
+    decide(&config, store.get_ref(), workflows.get_ref(), &principal, &message_id, body.into_inner(), true).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/approvals/{message_id}/reject",
+    tag = "approvals",
+    params(("message_id" = String, Path)),
+    request_body = DecisionRequest,
+    responses(
+        (status = 200, description = "Decision published; the workflow continues", body = DecisionResponse),
+        (status = 400, description = "Approver missing, or not the subject of the credentials", body = ApiError),
+        (status = 404, description = "Message not awaiting approval", body = ApiError),
+        (status = 409, description = "Decision could not be applied, or the workflow version is unknown", body = ApiError),
+    ),
+)]
+pub async fn reject_message(
+    config: web::Data<AppConfig>,
+    store: web::Data<dyn ApprovalStore>,
+    workflows: web::Data<dyn WorkflowStore>,
+    principal: Option<web::ReqData<Principal>>,
+    message_id: web::Path<String>,
+    body: web::Json<DecisionRequest>,
+) -> impl Responder {
+    decide(&config, store.get_ref(), workflows.get_ref(), &principal, &message_id, body.into_inner(), false).await
+}
+
+/// Applies the decision against the workflow version the message is suspended in, which
+/// compensates a rejected message, and republishes the message to the topic it was consumed
+/// from, where the processor continues the workflow. The message is parked again if that fails.
+#[instrument(skip(config, store, workflows, principal, request), fields(approver))]
+async fn decide(
+    config: &AppConfig,
+    store: &dyn ApprovalStore,
+    workflows: &dyn WorkflowStore,
+    principal: &Option<web::ReqData<Principal>>,
+    message_id: &str,
+    request: DecisionRequest,
+    approved: bool,
+) -> HttpResponse {
+    let approver = match approver(principal, request.approver) {
+        Ok(approver) => approver,
+        Err(e) => return e.error_response(),
+    };
+    tracing::Span::current().record("approver", approver.as_str());
+
+    // Another tenant's message is reported as unknown before anything is taken off the store
+    let not_awaiting = || ApiError::not_found(format!("Message {} is not awaiting approval", message_id)).error_response();
+    match store.get(message_id).await {
+        Ok(Some(parked)) if can_access(principal, parked.message.tenant()) => {}
+        Ok(_) => {
+            warn!("Message not awaiting approval");
+            return not_awaiting();
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to load message awaiting approval");
+            return ApiError::internal(format!("Storage error: {}", e)).error_response();
+        }
+    }
+    let parked = match store.take(message_id).await {
+        Ok(Some(parked)) => parked,
+        Ok(None) => {
+            warn!("Message decided concurrently");
+            return not_awaiting();
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to take message awaiting approval");
+            return ApiError::internal(format!("Storage error: {}", e)).error_response();
+        }
+    };
+
+    let workflow = match parked_workflow(&parked, workflows).await {
+        Ok(Some(workflow)) => workflow,
+        Ok(None) => {
+            let description = format!("Workflow {} version {} not found", parked.workflow_id, parked.workflow_version);
+            repark(store, parked).await;
+            return ApiError::conflict(description).error_response();
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to load the workflow of the message awaiting approval");
+            repark(store, parked).await;
+            return ApiError::internal(format!("Storage error: {}", e)).error_response();
+        }
+    };
+
+    let decision = ApprovalDecision {
+        approved,
+        approver,
+        reason: request.reason,
+    };
+    let mut message = parked.message.clone();
+    if let Err(e) = message.resume(&workflow, &decision) {
+        repark(store, parked).await;
+        return ApiError::conflict(e.to_string()).error_response();
+    }
+
+    if let Err(e) = publish_to_kafka(&message, &parked.topic, config).await {
+        repark(store, parked).await;
+        return ApiError::internal(format!("Kafka error: {}", e)).with_code("publish_failed").error_response();
+    }
+
+    info!(status = ?message.progress().status, "Approval decision published");
+    HttpResponse::Ok().json(DecisionResponse {
+        message_id: message_id.to_string(),
+        status: message.progress().status.clone(),
+    })
+}
+
+/// The subject of the credentials, or the approver named in the body when authentication
+/// is disabled.
+fn approver(principal: &Option<web::ReqData<Principal>>, requested: Option<String>) -> Result<String, ApiError> {
+    match (principal, requested) {
+        (Some(principal), Some(requested)) if requested != principal.subject => {
+            warn!(subject = %principal.subject, requested = %requested, "Approver does not match the credentials");
+            Err(ApiError::bad_request(format!("Approver {} does not match the credentials", requested)))
+        }
+        (Some(principal), _) => Ok(principal.subject.clone()),
+        (None, Some(requested)) => Ok(requested),
+        (None, None) => Err(ApiError::bad_request("Missing approver")),
+    }
+}
+
+/// The workflow kept with the parked message. Messages parked before it was kept fall back
+/// to the workflow store.
+async fn parked_workflow(parked: &ParkedMessage, workflows: &dyn WorkflowStore) -> Result<Option<Workflow>, StorageError> {
+    match &parked.workflow {
+        Some(workflow) => Ok(Some(workflow.clone())),
+        None => workflows.get(&parked.workflow_id, parked.workflow_version).await,
+    }
+}
+
+async fn repark(store: &dyn ApprovalStore, parked: ParkedMessage) {
+    if let Err(e) = store.park(parked).await {
+        error!(error = %e, "Failed to park message again after an unsuccessful decision");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use actix_web::dev::Service;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{App, HttpMessage};
+    use core_data::models::message::{Encoding, Message, Payload, PayloadFormat, PayloadSchema};
+    use core_data::storage::{InMemoryApprovalStore, InMemoryWorkflowStore};
+    use serde_json::json;
+    use crate::auth::Scope;
+    use super::*;
+
+    fn parked(tenant: &str) -> ParkedMessage {
+        let payload = Payload::new_inline(None, PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+        let message = Message::new(payload, tenant.to_string(), "api".to_string(), "payment_processing".to_string(), 1, "initiate".to_string(), None);
+        ParkedMessage::new(message, "payment_incoming".to_string())
+    }
+
+    #[actix_web::test]
+    async fn test_approver_is_taken_from_the_credentials() {
+        let approvals: web::Data<dyn ApprovalStore> = web::Data::from(Arc::new(InMemoryApprovalStore::new()) as Arc<dyn ApprovalStore>);
+        let workflows: web::Data<dyn WorkflowStore> = web::Data::from(Arc::new(InMemoryWorkflowStore::new()) as Arc<dyn WorkflowStore>);
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(AppConfig::default()))
+                .app_data(approvals)
+                .app_data(workflows)
+                .wrap_fn(|req, srv| {
+                    req.extensions_mut().insert(Principal { subject: "alice".to_string(), tenant: "tenant1".to_string(), scopes: vec![Scope::Admin] });
+                    srv.call(req)
+                })
+                .service(web::resource("/approvals/{message_id}/approve").route(web::post().to(approve_message)))
+        ).await;
+
+        let request = |body| TestRequest::post().uri("/approvals/42/approve").set_json(body).to_request();
+        let response = call_service(&app, request(json!({"approver": "bob", "reason": "Looks fine"}))).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // Naming no one, or the subject itself, gets as far as the parked message
+        for body in [json!({"reason": "Looks fine"}), json!({"approver": "alice", "reason": "Looks fine"})] {
+            let response = call_service(&app, request(body)).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_other_tenants_messages_stay_parked() {
+        let store = Arc::new(InMemoryApprovalStore::new());
+        let other = parked("tenant2");
+        store.park(other.clone()).await.unwrap();
+        let workflows: web::Data<dyn WorkflowStore> = web::Data::from(Arc::new(InMemoryWorkflowStore::new()) as Arc<dyn WorkflowStore>);
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(AppConfig::default()))
+                .app_data(web::Data::from(store.clone() as Arc<dyn ApprovalStore>))
+                .app_data(workflows)
+                .wrap_fn(|req, srv| {
+                    req.extensions_mut().insert(Principal { subject: "alice".to_string(), tenant: "tenant1".to_string(), scopes: vec![Scope::Admin] });
+                    srv.call(req)
+                })
+                .service(web::resource("/approvals/{message_id}/reject").route(web::post().to(reject_message)))
+        ).await;
+
+        let uri = format!("/approvals/{}/reject", other.message_id);
+        let response = call_service(&app, TestRequest::post().uri(&uri).set_json(json!({"reason": "Not mine"})).to_request()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(store.get(&other.message_id).await.unwrap(), Some(other));
+    }
+
+    #[actix_web::test]
+    async fn test_parked_workflow_needs_no_workflow_store() {
+        let workflows = InMemoryWorkflowStore::new();
+        let workflow: Workflow = serde_json::from_str(include_str!("../../sample-workflow.json")).unwrap();
+        let kept = parked("tenant1").with_workflow(workflow.clone());
+        assert_eq!(parked_workflow(&kept, &workflows).await.unwrap(), Some(workflow));
+
+        // Older records without a workflow are looked up instead
+        assert_eq!(parked_workflow(&parked("tenant1"), &workflows).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,281 @@
+use std::fmt;
+
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder, ResponseError};
+use core_data::models::message::*;
+use core_data::storage::IdempotencyStore;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::{debug, error, info, instrument, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::api::{request_id, ApiError};
+use crate::config::config::AppConfig;
+use crate::idempotency;
+use crate::limits::{route_of, RateLimiter};
+use crate::initiate::{publish_batch, InitiateParams};
+use crate::tenant::{resolve_origin, resolve_tenant};
+
+/// Content types read as one JSON string payload per line; anything else is a JSON array.
+const NDJSON_TYPES: [&str; 2] = ["application/x-ndjson", "application/ndjson"];
+
+#[derive(Debug, PartialEq)]
+enum BatchError {
+    Invalid(String),
+    Empty,
+    TooMany(usize),
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchError::Invalid(msg) => write!(f, "Invalid batch: {}", msg),
+            BatchError::Empty => write!(f, "Batch contains no payloads"),
+            BatchError::TooMany(limit) => write!(f, "Batch exceeds the limit of {} payloads", limit),
+        }
+    }
+}
+
+impl ResponseError for BatchError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            BatchError::Invalid(_) | BatchError::Empty => StatusCode::BAD_REQUEST,
+            BatchError::TooMany(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ApiError::new(self.status_code(), self.to_string()).error_response()
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemStatus {
+    /// Published to Kafka
+    Accepted,
+    /// Not a string payload, or failed pre-validation; never published
+    Invalid,
+    /// Could not be published
+    Failed,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ItemResult {
+    /// Position of the payload in the batch
+    pub index: usize,
+    pub status: ItemStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    /// Why the item was not accepted; validation problems or a delivery error
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[schema(value_type = Vec<Object>)]
+    pub errors: Vec<Value>,
+}
+
+impl ItemResult {
+    fn rejected(index: usize, status: ItemStatus, errors: Vec<Value>) -> Self {
+        ItemResult { index, status, message_id: None, errors }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchResponse {
+    /// Parent id of every message in the batch
+    pub batch_id: String,
+    pub accepted: usize,
+    pub failed: usize,
+    /// One result per payload, in batch order
+    pub results: Vec<ItemResult>,
+}
+
+/// Splits the body into payloads. A malformed NDJSON line only fails its own item, while a
+/// body that is not a JSON array fails the whole batch.
+fn parse_items(req: &HttpRequest, body: &[u8], limit: usize) -> Result<Vec<Result<String, String>>, BatchError> {
+    let items: Vec<Result<String, String>> = if NDJSON_TYPES.contains(&req.content_type()) {
+        let text = std::str::from_utf8(body).map_err(|e| BatchError::Invalid(e.to_string()))?;
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str::<String>(line).map_err(|e| format!("Invalid line: {}", e)))
+            .collect()
+    } else {
+        serde_json::from_slice::<Vec<Value>>(body)
+            .map_err(|e| BatchError::Invalid(e.to_string()))?
+            .into_iter()
+            .map(|item| match item {
+                Value::String(payload) => Ok(payload),
+                _ => Err("Item must be a string payload".to_string()),
+            })
+            .collect()
+    };
+
+    match items.len() {
+        0 => Err(BatchError::Empty),
+        n if n > limit => Err(BatchError::TooMany(limit)),
+        _ => Ok(items),
+    }
+}
+
+/// Initiates one message per payload, all sharing the batch id as their parent id. Every item
+/// gets its own result; the response is 207 when any of them was not accepted.
+#[utoipa::path(
+    post,
+    path = "/initiate/batch/{origin}",
+    tag = "initiate",
+    params(
+        ("origin" = String, Path, description = "Channel the payments arrived through; `/initiate/batch` uses `api`"),
+        InitiateParams,
+    ),
+    request_body(
+        content(
+            (Vec<String> = "application/json"),
+            (String = "application/x-ndjson"),
+        ),
+        description = "ISO 20022 XML payloads, as a JSON array of strings or one JSON string per line",
+    ),
+    responses(
+        (status = 200, description = "Every payload was published", body = BatchResponse),
+        (status = 207, description = "Some payloads were not published; see each result", body = BatchResponse),
+        (status = 400, description = "Unreadable or empty batch", body = ApiError),
+        (status = 409, description = "Idempotency key in use", body = ApiError),
+        (status = 413, description = "Too many payloads", body = ApiError),
+        (status = 429, description = "Rate limit exceeded, or the valid payloads exceed the daily quota; see `Retry-After`", body = ApiError),
+    ),
+)]
+#[instrument(skip(config, idempotency_store, limiter, req, body), fields(request_id = %request_id(), tenant, origin, batch_id, items))]
+pub async fn initiate_batch(
+    config: web::Data<AppConfig>,
+    idempotency_store: web::Data<dyn IdempotencyStore>,
+    limiter: web::Data<RateLimiter>,
+    params: web::Query<InitiateParams>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> impl Responder {
+    debug!(body_size = body.len(), "Received batch initiation request");
+
+    let tenant = match resolve_tenant(&req, &config) {
+        Ok(tenant) => tenant,
+        Err(e) => return e.error_response(),
+    };
+    let origin = resolve_origin(&req);
+    let items = match parse_items(&req, &body, config.maxbatchitems) {
+        Ok(items) => items,
+        Err(e) => {
+            warn!(error = %e, "Rejected batch");
+            return e.error_response();
+        }
+    };
+    let batch_id = Uuid::new_v4().to_string();
+    tracing::Span::current()
+        .record("tenant", tenant.as_str())
+        .record("origin", origin.as_str())
+        .record("batch_id", batch_id.as_str())
+        .record("items", items.len());
+
+    let mut results = Vec::with_capacity(items.len());
+    let mut messages = Vec::new();
+    for (index, item) in items.into_iter().enumerate() {
+        match item {
+            Ok(body) => {
+                let payload = Payload::new_inline(
+                    Some(body.into_bytes()),
+                    PayloadFormat::Xml,
+                    PayloadSchema::ISO20022,
+                    Encoding::Utf8,
+                );
+                let message = Message::new(
+                    payload,
+                    tenant.clone(),
+                    origin.clone(),
+                    config.initialworkflowid.clone(),
+                    config.initialworkflowversion,
+                    config.initialtaskid.clone(),
+                    Some("Payment".to_string()),
+                ).with_parent_id(batch_id.clone());
+                messages.push((index, message));
+            }
+            Err(e) => results.push(ItemResult::rejected(index, ItemStatus::Invalid, vec![json!(e)])),
+        }
+    }
+
+    if params.validate.unwrap_or(config.prevalidate) {
+        let validated = match web::block(move || {
+            messages.into_iter()
+                .map(|(index, message)| {
+                    let validation = message.validate_payload();
+                    (index, message, validation)
+                })
+                .collect::<Vec<_>>()
+        }).await {
+            Ok(validated) => validated,
+            Err(e) => {
+                error!(error = %e, "Payload validation failed to run");
+                return ApiError::internal(format!("Task error: {}", e)).error_response();
+            }
+        };
+        messages = Vec::with_capacity(validated.len());
+        for (index, message, validation) in validated {
+            match validation {
+                Ok(()) => messages.push((index, message)),
+                Err(errors) => {
+                    debug!(index, errors = ?errors, "Batch item failed pre-validation");
+                    results.push(ItemResult::rejected(index, ItemStatus::Invalid, errors.iter().map(|e| json!(e)).collect()));
+                }
+            }
+        }
+    }
+
+    let idempotency_key = match idempotency::reserve(idempotency_store.get_ref(), &config, &req, &tenant, &origin, &body).await {
+        Ok(key) => key,
+        Err(response) => return response,
+    };
+
+    // Every valid item is charged up front, and the ones that fail to publish refunded, so
+    // only accepted items count against the quota
+    if let Err(e) = limiter.consume_quota(&tenant, route_of(req.path()), messages.len() as u64).await {
+        if let Some(key) = &idempotency_key {
+            idempotency::finish(idempotency_store.get_ref(), &config, &tenant, key, None).await;
+        }
+        return e.error_response();
+    }
+
+    let (indices, messages): (Vec<usize>, Vec<Message>) = messages.into_iter().unzip();
+    let deliveries = publish_batch(&messages, &config.kafkatopic, &config).await;
+    for ((index, message), delivery) in indices.into_iter().zip(&messages).zip(deliveries) {
+        results.push(match delivery {
+            Ok(()) => ItemResult {
+                index,
+                status: ItemStatus::Accepted,
+                message_id: Some(message.id().to_string()),
+                errors: Vec::new(),
+            },
+            Err(e) => ItemResult::rejected(index, ItemStatus::Failed, vec![json!(e)]),
+        });
+    }
+    results.sort_by_key(|r| r.index);
+
+    let accepted = results.iter().filter(|r| r.status == ItemStatus::Accepted).count();
+    limiter.refund_quota(&tenant, route_of(req.path()), (messages.len() - accepted) as u64).await;
+    let response = BatchResponse {
+        batch_id,
+        accepted,
+        failed: results.len() - accepted,
+        results,
+    };
+
+    let status = if response.failed == 0 {
+        info!(accepted, "Batch initiated successfully");
+        StatusCode::OK
+    } else {
+        warn!(accepted, failed = response.failed, "Batch partially initiated");
+        StatusCode::MULTI_STATUS
+    };
+
+    // A batch that published nothing can be retried with the same key
+    if let Some(key) = &idempotency_key {
+        let stored = (accepted > 0).then(|| json!(response));
+        idempotency::finish(idempotency_store.get_ref(), &config, &tenant, key, stored.as_ref().map(|r| (status, r))).await;
+    }
+
+    HttpResponse::build(status).json(response)
+}
@@ -10,6 +10,99 @@ pub struct AppConfig {
     pub kafkabootstrapservers: String,
     pub kafkamessagetimeoutms: String,
     pub kafkatopic: String,
+    pub mongodburi: String,
+    pub mongodbdatabase: String,
+    /// Tenants allowed to initiate messages
+    pub tenants: Vec<String>,
+    /// Tenant used when the request does not name one; empty requires a tenant on every request
+    pub defaulttenant: String,
+    pub initialworkflowid: String,
+    pub initialworkflowversion: u16,
+    pub initialtaskid: String,
+    /// JSON file of API keys, HMAC keys and JWT settings; empty disables authentication
+    pub authfile: String,
+    /// Origins browsers may call the API from
+    pub corsallowedorigins: Vec<String>,
+    /// How long an idempotency key replays its first response
+    pub idempotencywindowsecs: u64,
+    /// How long an in-progress idempotency key holds before another request may take it over
+    pub idempotencyleasesecs: u64,
+    /// Parse and validate payloads before publishing unless the request says otherwise
+    pub prevalidate: bool,
+    /// Directory of uploaded payload files, shared with the processor
+    pub payloaddir: String,
+    pub maxuploadbytes: u64,
+    /// Most payloads one /initiate/batch request may carry
+    pub maxbatchitems: usize,
+    pub maxbatchbytes: usize,
+    /// Token buckets per route, from `RATELIMITS`
+    pub ratelimits: Vec<RateLimit>,
+    /// Messages a tenant may initiate per UTC day through a route, from `DAILYQUOTAS`
+    pub dailyquotas: Vec<DailyQuota>,
+}
+
+/// Token bucket for a route. Limits without a tenant apply to every tenant that has no
+/// limit of its own; each tenant gets its own bucket either way.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RateLimit {
+    pub tenant: Option<String>,
+    /// First path segment, e.g. `initiate`
+    pub route: String,
+    /// Requests added to the bucket per minute
+    pub per_minute: u32,
+    /// Bucket size, the most requests allowed in a burst
+    pub burst: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DailyQuota {
+    pub tenant: Option<String>,
+    pub route: String,
+    pub messages: u64,
+}
+
+/// Splits `[tenant:]route=value` entries of a comma separated list.
+fn limit_entries(value: &str) -> Result<Vec<(Option<String>, String, String)>, String> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (key, value) = entry.split_once('=').ok_or_else(|| format!("'{}' is not [tenant:]route=value", entry))?;
+            let (tenant, route) = match key.split_once(':') {
+                Some((tenant, route)) => (Some(tenant.trim().to_string()), route.trim()),
+                None => (None, key.trim()),
+            };
+            if route.is_empty() {
+                return Err(format!("'{}' names no route", entry));
+            }
+            Ok((tenant, route.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Parses `RATELIMITS`, e.g. `initiate=600/100,tenant2:initiate=60/10` for 600 requests a
+/// minute in bursts of up to 100.
+fn parse_rate_limits(value: &str) -> Result<Vec<RateLimit>, String> {
+    limit_entries(value)?.into_iter()
+        .map(|(tenant, route, value)| {
+            let (per_minute, burst) = value.split_once('/')
+                .and_then(|(rate, burst)| Some((rate.trim().parse::<u32>().ok()?, burst.trim().parse::<u32>().ok()?)))
+                .filter(|(rate, burst)| *rate > 0 && *burst > 0)
+                .ok_or_else(|| format!("'{}' for route {} is not a positive per-minute/burst pair", value, route))?;
+            Ok(RateLimit { tenant, route, per_minute, burst })
+        })
+        .collect()
+}
+
+/// Parses `DAILYQUOTAS`, e.g. `initiate=100000,tenant2:initiate=5000`.
+fn parse_daily_quotas(value: &str) -> Result<Vec<DailyQuota>, String> {
+    limit_entries(value)?.into_iter()
+        .map(|(tenant, route, value)| {
+            let messages = value.parse::<u64>()
+                .map_err(|e| format!("'{}' for route {} is not a message count: {}", value, route, e))?;
+            Ok(DailyQuota { tenant, route, messages })
+        })
+        .collect()
 }
 
 #[derive(Debug)]
@@ -93,12 +186,191 @@ pub async fn load_config() -> Result<AppConfig, ConfigError> {
         }
     }
 
+    // Optional; without MongoDB the approval endpoints only see this process's memory
+    {
+        let _mongodb_span = info_span!("mongodb_config").entered();
+        config.mongodburi = env::var("MONGODBURI").unwrap_or_default();
+        config.mongodbdatabase = env::var("MONGODBDATABASE")
+            .unwrap_or_else(|_| {
+                debug!(default = "PaymentProcessor", "Using default MongoDB database");
+                String::from("PaymentProcessor")
+            });
+    }
+
+    // Tenants and the workflow new messages start on
+    {
+        let _initiation_span = info_span!("initiation_config").entered();
+        config.tenants = env::var("TENANTS")
+            .unwrap_or_else(|_| {
+                debug!(default = "tenant1", "Using default tenants");
+                String::from("tenant1")
+            })
+            .split(',')
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect();
+        config.defaulttenant = env::var("DEFAULTTENANT").unwrap_or_default();
+        if !config.defaulttenant.is_empty() && !config.tenants.contains(&config.defaulttenant) {
+            error!(tenant = %config.defaulttenant, "Default tenant is not a configured tenant");
+            return Err(ConfigError::ParseError(format!("Unknown default tenant: {}", config.defaulttenant)));
+        }
+
+        config.initialworkflowid = env::var("INITIALWORKFLOWID")
+            .unwrap_or_else(|_| {
+                debug!(default = "payment_processing", "Using default initial workflow id");
+                String::from("payment_processing")
+            });
+        config.initialworkflowversion = match env::var("INITIALWORKFLOWVERSION")
+            .unwrap_or_else(|_| String::from("1"))
+            .parse() {
+                Ok(version) => version,
+                Err(e) => {
+                    error!(error = %e, env_var = "INITIALWORKFLOWVERSION", "Invalid initial workflow version");
+                    return Err(ConfigError::ParseError(format!("Invalid initial workflow version: {}", e)));
+                }
+            };
+        config.initialtaskid = env::var("INITIALTASKID")
+            .unwrap_or_else(|_| {
+                debug!(default = "initiate", "Using default initial task id");
+                String::from("initiate")
+            });
+    }
+
+    // Credentials and browser access
+    {
+        let _auth_span = info_span!("auth_config").entered();
+        config.authfile = env::var("AUTHFILE").unwrap_or_default();
+        config.corsallowedorigins = env::var("CORSALLOWEDORIGINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|o| o.trim().to_string())
+            .filter(|o| !o.is_empty())
+            .collect();
+    }
+
+    {
+        let _idempotency_span = info_span!("idempotency_config").entered();
+        config.idempotencywindowsecs = match env::var("IDEMPOTENCYWINDOWSECS")
+            .unwrap_or_else(|_| {
+                debug!(default = "86400", "Using default idempotency window");
+                String::from("86400")
+            })
+            .parse() {
+                Ok(secs) => secs,
+                Err(e) => {
+                    error!(error = %e, env_var = "IDEMPOTENCYWINDOWSECS", "Invalid idempotency window");
+                    return Err(ConfigError::ParseError(format!("Invalid idempotency window: {}", e)));
+                }
+            };
+        config.idempotencyleasesecs = match env::var("IDEMPOTENCYLEASESECS")
+            .unwrap_or_else(|_| {
+                debug!(default = "60", "Using default idempotency lease");
+                String::from("60")
+            })
+            .parse() {
+                Ok(secs) => secs,
+                Err(e) => {
+                    error!(error = %e, env_var = "IDEMPOTENCYLEASESECS", "Invalid idempotency lease");
+                    return Err(ConfigError::ParseError(format!("Invalid idempotency lease: {}", e)));
+                }
+            };
+    }
+
+    {
+        let _validation_span = info_span!("validation_config").entered();
+        config.prevalidate = match env::var("PREVALIDATE").unwrap_or_else(|_| String::from("false")).parse() {
+            Ok(prevalidate) => prevalidate,
+            Err(e) => {
+                error!(error = %e, env_var = "PREVALIDATE", "Invalid pre-validation flag");
+                return Err(ConfigError::ParseError(format!("Invalid PREVALIDATE: {}", e)));
+            }
+        };
+    }
+
+    {
+        let _files_span = info_span!("files_config").entered();
+        config.payloaddir = env::var("PAYLOADDIR")
+            .unwrap_or_else(|_| {
+                debug!(default = "payloads", "Using default payload directory");
+                String::from("payloads")
+            });
+        config.maxuploadbytes = match env::var("MAXUPLOADBYTES")
+            .unwrap_or_else(|_| {
+                debug!(default = "524288000", "Using default upload limit");
+                String::from("524288000")
+            })
+            .parse() {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    error!(error = %e, env_var = "MAXUPLOADBYTES", "Invalid upload limit");
+                    return Err(ConfigError::ParseError(format!("Invalid upload limit: {}", e)));
+                }
+            };
+    }
+
+    {
+        let _batch_span = info_span!("batch_config").entered();
+        config.maxbatchitems = match env::var("MAXBATCHITEMS")
+            .unwrap_or_else(|_| {
+                debug!(default = "1000", "Using default batch item limit");
+                String::from("1000")
+            })
+            .parse() {
+                Ok(items) if items > 0 => items,
+                Ok(_) => {
+                    error!(env_var = "MAXBATCHITEMS", "Batch item limit must be positive");
+                    return Err(ConfigError::ParseError("Batch item limit must be positive".to_string()));
+                }
+                Err(e) => {
+                    error!(error = %e, env_var = "MAXBATCHITEMS", "Invalid batch item limit");
+                    return Err(ConfigError::ParseError(format!("Invalid batch item limit: {}", e)));
+                }
+            };
+        config.maxbatchbytes = match env::var("MAXBATCHBYTES")
+            .unwrap_or_else(|_| {
+                debug!(default = "10485760", "Using default batch size limit");
+                String::from("10485760")
+            })
+            .parse() {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    error!(error = %e, env_var = "MAXBATCHBYTES", "Invalid batch size limit");
+                    return Err(ConfigError::ParseError(format!("Invalid batch size limit: {}", e)));
+                }
+            };
+    }
+
+    {
+        let _limits_span = info_span!("limits_config").entered();
+        config.ratelimits = parse_rate_limits(&env::var("RATELIMITS").unwrap_or_default())
+            .map_err(|e| {
+                error!(error = %e, env_var = "RATELIMITS", "Invalid rate limits");
+                ConfigError::ParseError(format!("Invalid rate limits: {}", e))
+            })?;
+        config.dailyquotas = parse_daily_quotas(&env::var("DAILYQUOTAS").unwrap_or_default())
+            .map_err(|e| {
+                error!(error = %e, env_var = "DAILYQUOTAS", "Invalid daily quotas");
+                ConfigError::ParseError(format!("Invalid daily quotas: {}", e))
+            })?;
+
+        let tenants = config.ratelimits.iter().map(|l| &l.tenant)
+            .chain(config.dailyquotas.iter().map(|q| &q.tenant))
+            .flatten();
+        for tenant in tenants {
+            if !config.tenants.contains(tenant) {
+                error!(tenant = %tenant, "Limit configured for an unknown tenant");
+                return Err(ConfigError::ParseError(format!("Limit for unknown tenant: {}", tenant)));
+            }
+        }
+    }
+
     info!(
         duration_ms = start.elapsed().as_millis(),
         host = %config.serverhostname,
         port = %config.serverport,
         kafka_servers = %config.kafkabootstrapservers,
         kafka_topic = %config.kafkatopic,
+        tenants = ?config.tenants,
         "Configuration loaded successfully"
     );
     
@@ -0,0 +1,198 @@
+use std::fmt;
+use std::path::Path;
+
+use actix_multipart::Multipart;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
+use core_data::models::message::*;
+use core_data::storage::FilePayloadStore;
+use futures_util::{Stream, StreamExt};
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, error, info, instrument, warn};
+use utoipa::ToSchema;
+use crate::api::{request_id, ApiError};
+use crate::config::config::AppConfig;
+use crate::initiate::publish_to_kafka;
+use crate::limits::{route_of, RateLimiter};
+use crate::tenant::{resolve_origin, resolve_tenant};
+
+/// Multipart field carrying the file; other fields are ignored.
+const FILE_FIELD: &str = "file";
+
+#[derive(Debug)]
+enum UploadError {
+    TooLarge(u64),
+    Invalid(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadError::TooLarge(limit) => write!(f, "File exceeds the limit of {} bytes", limit),
+            UploadError::Invalid(msg) => write!(f, "Invalid upload: {}", msg),
+            UploadError::Io(e) => write!(f, "Failed to write file: {}", e),
+        }
+    }
+}
+
+impl From<UploadError> for ApiError {
+    fn from(e: UploadError) -> Self {
+        let status = match e {
+            UploadError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            UploadError::Invalid(_) => StatusCode::BAD_REQUEST,
+            UploadError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError::new(status, e.to_string())
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UploadResponse {
+    /// Name of the stored file in the payload directory
+    pub file_id: String,
+    pub message_id: String,
+    /// Bytes received
+    pub size: u64,
+}
+
+/// Streams the chunks to `path`, failing as soon as more than `limit` bytes arrive.
+async fn write_chunks<S, E>(path: &Path, mut chunks: S, limit: u64) -> Result<u64, UploadError>
+where
+    S: Stream<Item = Result<web::Bytes, E>> + Unpin,
+    E: fmt::Display,
+{
+    let mut file = tokio::fs::File::create(path).await.map_err(UploadError::Io)?;
+    let mut size = 0u64;
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(|e| UploadError::Invalid(e.to_string()))?;
+        size += chunk.len() as u64;
+        if size > limit {
+            return Err(UploadError::TooLarge(limit));
+        }
+        file.write_all(&chunk).await.map_err(UploadError::Io)?;
+    }
+    file.flush().await.map_err(UploadError::Io)?;
+    Ok(size)
+}
+
+async fn write_multipart(path: &Path, mut multipart: Multipart, limit: u64) -> Result<u64, UploadError> {
+    while let Some(field) = multipart.next().await {
+        let mut field = field.map_err(|e| UploadError::Invalid(e.to_string()))?;
+        if field.name() == Some(FILE_FIELD) {
+            return write_chunks(path, field, limit).await;
+        }
+        while let Some(chunk) = field.next().await {
+            chunk.map_err(|e| UploadError::Invalid(e.to_string()))?;
+        }
+    }
+    Err(UploadError::Invalid(format!("No '{}' field", FILE_FIELD)))
+}
+
+/// Accepts a `multipart/form-data` upload with a `file` field, or the file as the raw body,
+/// stores it as a `File` payload and initiates a message for it.
+#[utoipa::path(
+    post,
+    path = "/files/{origin}",
+    tag = "files",
+    params(("origin" = String, Path, description = "Channel the file arrived through; `/files` uses `api`")),
+    request_body(
+        content(
+            (String = "multipart/form-data"),
+            (String = "application/octet-stream"),
+        ),
+        description = "The payload file, in a `file` form field or as the raw body",
+    ),
+    responses(
+        (status = 200, description = "File stored and message published", body = UploadResponse),
+        (status = 400, description = "Unreadable upload", body = ApiError),
+        (status = 413, description = "File exceeds MAXUPLOADBYTES", body = ApiError),
+        (status = 429, description = "Rate limit or daily quota exceeded; see `Retry-After`", body = ApiError),
+        (status = 500, description = "Storing or publishing failed", body = ApiError),
+    ),
+)]
+#[instrument(skip(config, store, limiter, req, body), fields(request_id = %request_id(), tenant, origin, file_id))]
+pub async fn upload_file(
+    config: web::Data<AppConfig>,
+    store: web::Data<FilePayloadStore>,
+    limiter: web::Data<RateLimiter>,
+    req: HttpRequest,
+    body: web::Payload,
+) -> impl Responder {
+    let tenant = match resolve_tenant(&req, &config) {
+        Ok(tenant) => tenant,
+        Err(e) => return e.error_response(),
+    };
+    let origin = resolve_origin(&req);
+    let file_id = FilePayloadStore::new_file_id();
+    tracing::Span::current()
+        .record("tenant", tenant.as_str())
+        .record("origin", origin.as_str())
+        .record("file_id", file_id.as_str());
+
+    let limit = config.maxuploadbytes;
+    let declared = req.headers().get("Content-Length")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if declared.is_some_and(|length| length > limit) {
+        return ApiError::from(UploadError::TooLarge(limit)).error_response();
+    }
+
+    let staging = store.staging_path(&file_id);
+    let is_multipart = req.headers().get("Content-Type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("multipart/form-data"));
+    let written = if is_multipart {
+        write_multipart(&staging, Multipart::new(req.headers(), body), limit).await
+    } else {
+        write_chunks(&staging, body, limit).await
+    };
+
+    let size = match written {
+        Ok(size) => size,
+        Err(e) => {
+            warn!(error = %e, "Upload failed");
+            store.discard(&file_id);
+            return ApiError::from(e).error_response();
+        }
+    };
+    debug!(size = size, "File received");
+
+    let payload = match store.commit(&file_id, PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8) {
+        Ok(payload) => payload,
+        Err(e) => {
+            error!(error = %e, "Failed to store file");
+            store.discard(&file_id);
+            return ApiError::internal(format!("Storage error: {}", e)).error_response();
+        }
+    };
+
+    // Charged once the file is stored, so unreadable or oversized uploads cost nothing
+    if let Err(e) = limiter.consume_quota(&tenant, route_of(req.path()), 1).await {
+        store.discard(&file_id);
+        return e.error_response();
+    }
+
+    let message = Message::new(
+        payload,
+        tenant,
+        origin,
+        config.initialworkflowid.clone(),
+        config.initialworkflowversion,
+        config.initialtaskid.clone(),
+        Some("Payment".to_string()),
+    );
+    if let Err(e) = publish_to_kafka(&message, &config.kafkatopic, &config).await {
+        store.discard(&file_id);
+        limiter.refund_quota(message.tenant(), route_of(req.path()), 1).await;
+        return ApiError::internal(format!("Kafka error: {}", e)).with_code("publish_failed").error_response();
+    }
+
+    info!(message_id = %message.id(), size = size, "File initiated successfully");
+    HttpResponse::Ok().json(UploadResponse {
+        file_id,
+        message_id: message.id().to_string(),
+        size,
+    })
+}
@@ -0,0 +1,125 @@
+use core_data::storage::{IdempotencyRecord, IdempotencyStore};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::{error, info, warn};
+use crate::api::ApiError;
+use crate::config::config::AppConfig;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+pub const REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Identifies a request by its path, so a key cannot replay one route's response on
+/// another, and by its origin and body.
+fn request_hash(path: &str, origin: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(path.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(origin.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+/// Reserves the request's idempotency key, if it has one, and returns it. A replay of a
+/// completed request gets the original response and status, and a key that is in progress
+/// or was used with a different request gets a 409. A reservation that is never finished
+/// lapses after the lease.
+pub async fn reserve(store: &dyn IdempotencyStore, config: &AppConfig, req: &HttpRequest, tenant: &str, origin: &str, body: &[u8]) -> Result<Option<String>, HttpResponse> {
+    let key = match req.headers().get(IDEMPOTENCY_KEY_HEADER).map(|v| v.to_str()) {
+        None => return Ok(None),
+        Some(Ok(key)) if !key.trim().is_empty() && key.len() <= MAX_KEY_LENGTH => key.trim().to_string(),
+        Some(_) => {
+            return Err(ApiError::bad_request(format!(
+                "{} must be 1 to {} visible characters", IDEMPOTENCY_KEY_HEADER, MAX_KEY_LENGTH
+            )).error_response());
+        }
+    };
+
+    let lease = time::Duration::seconds(config.idempotencyleasesecs as i64);
+    let record = IdempotencyRecord::new(tenant.to_string(), key.clone(), request_hash(req.path(), origin, body), lease);
+    match store.reserve(&record).await {
+        Ok(None) => Ok(Some(key)),
+        Ok(Some(existing)) if existing.request_hash != record.request_hash => {
+            warn!(idempotency_key = %key, "Idempotency key reused with a different request");
+            Err(ApiError::conflict(format!("Idempotency key {} was used with a different request", key))
+                .with_code("idempotency_key_reused")
+                .error_response())
+        }
+        Ok(Some(IdempotencyRecord { response: Some(response), status, .. })) => {
+            info!(idempotency_key = %key, "Replaying response of an earlier request");
+            // Records stored before statuses were kept were all 200s
+            let status = status.and_then(|s| StatusCode::from_u16(s).ok()).unwrap_or(StatusCode::OK);
+            Err(HttpResponse::build(status).insert_header((REPLAYED_HEADER, "true")).json(response))
+        }
+        Ok(Some(_)) => {
+            warn!(idempotency_key = %key, "Request with the same idempotency key still in progress");
+            Err(ApiError::conflict(format!("A request with idempotency key {} is still in progress", key))
+                .with_code("idempotency_key_in_progress")
+                .error_response())
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to reserve idempotency key");
+            Err(ApiError::internal(format!("Storage error: {}", e)).error_response())
+        }
+    }
+}
+
+/// Records the response and its status for replays, or frees the key when the request failed.
+pub async fn finish(store: &dyn IdempotencyStore, config: &AppConfig, tenant: &str, key: &str, response: Option<(StatusCode, &Value)>) {
+    let window = time::Duration::seconds(config.idempotencywindowsecs as i64);
+    let result = match response {
+        Some((status, response)) => store.complete(tenant, key, status.as_u16(), response.clone(), window).await,
+        None => store.release(tenant, key).await,
+    };
+    if let Err(e) = result {
+        error!(error = %e, idempotency_key = %key, "Failed to update idempotency key");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use core_data::storage::InMemoryIdempotencyStore;
+    use serde_json::json;
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_replay_keeps_the_original_status() {
+        let store = InMemoryIdempotencyStore::new();
+        let config = AppConfig { idempotencywindowsecs: 60, idempotencyleasesecs: 60, ..Default::default() };
+        let req = TestRequest::default().uri("/initiate").insert_header((IDEMPOTENCY_KEY_HEADER, "key-1")).to_http_request();
+
+        let key = reserve(&store, &config, &req, "tenant1", "bank", b"<xml/>").await.unwrap().unwrap();
+        let in_progress = reserve(&store, &config, &req, "tenant1", "bank", b"<xml/>").await.unwrap_err();
+        assert_eq!(in_progress.status(), StatusCode::CONFLICT);
+
+        finish(&store, &config, "tenant1", &key, Some((StatusCode::MULTI_STATUS, &json!({"accepted": 1})))).await;
+        let replay = reserve(&store, &config, &req, "tenant1", "bank", b"<xml/>").await.unwrap_err();
+        assert_eq!(replay.status(), StatusCode::MULTI_STATUS);
+        assert_eq!(replay.headers().get(REPLAYED_HEADER).unwrap(), "true");
+
+        // The same key and body on another route is a different request
+        let batch = TestRequest::default().uri("/initiate/batch").insert_header((IDEMPOTENCY_KEY_HEADER, "key-1")).to_http_request();
+        let reused = reserve(&store, &config, &batch, "tenant1", "bank", b"<xml/>").await.unwrap_err();
+        assert_eq!(reused.status(), StatusCode::CONFLICT);
+    }
+
+    #[actix_web::test]
+    async fn test_unfinished_reservation_lapses_after_the_lease() {
+        let store = InMemoryIdempotencyStore::new();
+        let config = AppConfig { idempotencywindowsecs: 60, idempotencyleasesecs: 0, ..Default::default() };
+        let req = TestRequest::default().uri("/initiate").insert_header((IDEMPOTENCY_KEY_HEADER, "key-1")).to_http_request();
+
+        assert!(reserve(&store, &config, &req, "tenant1", "bank", b"<xml/>").await.unwrap().is_some());
+        let key = reserve(&store, &config, &req, "tenant1", "bank", b"<xml/>").await.unwrap().unwrap();
+
+        // Completing holds the key for the window, not the lease
+        finish(&store, &config, "tenant1", &key, Some((StatusCode::OK, &json!({"message_id": "42"})))).await;
+        let replay = reserve(&store, &config, &req, "tenant1", "bank", b"<xml/>").await.unwrap_err();
+        assert_eq!(replay.status(), StatusCode::OK);
+    }
+}
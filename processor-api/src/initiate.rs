@@ -1,13 +1,18 @@
 use core_data::models::message::*;
-use serde::Serialize;
-use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
 use serde_json::json;
 use rdkafka::producer::{FutureProducer, FutureRecord};
 use rdkafka::ClientConfig;
-use tokio;
 use tracing::{debug, error, info, instrument, warn};
 use crate::config::config::*;
-use uuid::Uuid;
+use crate::tenant::{resolve_origin, resolve_tenant};
+use crate::idempotency;
+use crate::limits::{route_of, RateLimiter};
+use crate::api::{request_id, ApiError};
+use core_data::storage::IdempotencyStore;
+use utoipa::{IntoParams, ToSchema};
 use lazy_static::lazy_static;
 use std::sync::Mutex;
 
@@ -34,24 +39,23 @@ fn get_or_init_producer(config: &AppConfig) -> Result<FutureProducer, String> {
     Ok(producer.as_ref().unwrap().clone())
 }
 
-#[derive(Serialize)]
-#[serde(untagged)] 
-pub enum InitiationResponse {
-    Success(serde_json::Value),
-    Error(Vec<String>),
+#[derive(Debug, Serialize, ToSchema)]
+pub struct InitiateResponse {
+    /// Id of the created message, as a decimal string
+    pub message_id: String,
 }
 
 #[instrument(skip(message, config), fields(message_id = %message.id()))]
-async fn publish_to_kafka(message: &Message, config: &AppConfig) -> Result<(), String> {
+pub async fn publish_to_kafka(message: &Message, topic: &str, config: &AppConfig) -> Result<(), String> {
     let start = std::time::Instant::now();
     
     debug!(
         bootstrap_servers = %config.kafkabootstrapservers,
-        topic = %config.kafkatopic,
+        topic = %topic,
         "Creating Kafka producer"
     );
 
-    let producer = get_or_init_producer(&config)?;
+    let producer = get_or_init_producer(config)?;
 
     let json_string = serde_json::to_string(&message)
         .map_err(|e| {
@@ -66,7 +70,7 @@ async fn publish_to_kafka(message: &Message, config: &AppConfig) -> Result<(), S
 
     producer
         .send(
-            FutureRecord::to(&config.kafkatopic)
+            FutureRecord::to(topic)
                 .payload(json_string.as_bytes())
                 .key(&message.id().to_string()),
             std::time::Duration::from_secs(5),
@@ -75,7 +79,7 @@ async fn publish_to_kafka(message: &Message, config: &AppConfig) -> Result<(), S
         .map_err(|(e, _)| {
             error!(
                 error = %e,
-                topic = %config.kafkatopic,
+                topic = %topic,
                 message_id = %message.id(),
                 "Failed to deliver message to Kafka"
             );
@@ -85,64 +89,198 @@ async fn publish_to_kafka(message: &Message, config: &AppConfig) -> Result<(), S
     info!(
         duration_ms = start.elapsed().as_millis(),
         message_id = %message.id(),
-        topic = %config.kafkatopic,
+        topic = %topic,
         "Successfully published message to Kafka"
     );
 
     Ok(())
 }
 
-#[instrument(skip(config, _req, body), fields(request_id = %Uuid::new_v4()))]
+/// Hands every message to the producer before awaiting any delivery, so they go out in
+/// shared Kafka batches. Results are in the order of `messages`.
+#[instrument(skip(messages, config), fields(messages = messages.len()))]
+pub async fn publish_batch(messages: &[Message], topic: &str, config: &AppConfig) -> Vec<Result<(), String>> {
+    let start = std::time::Instant::now();
+
+    let producer = match get_or_init_producer(config) {
+        Ok(producer) => producer,
+        Err(e) => return messages.iter().map(|_| Err(e.clone())).collect(),
+    };
+
+    let deliveries = messages.iter().map(|message| {
+        let producer = &producer;
+        async move {
+            let json_string = serde_json::to_string(message)
+                .map_err(|e| {
+                    error!(error = %e, message_id = %message.id(), "Failed to serialize message");
+                    format!("Message serialization error: {}", e)
+                })?;
+            let key = message.id().to_string();
+            producer
+                .send(
+                    FutureRecord::to(topic)
+                        .payload(json_string.as_bytes())
+                        .key(&key),
+                    std::time::Duration::from_secs(5),
+                )
+                .await
+                .map(|_| ())
+                .map_err(|(e, _)| {
+                    error!(
+                        error = %e,
+                        topic = %topic,
+                        message_id = %message.id(),
+                        "Failed to deliver message to Kafka"
+                    );
+                    format!("Kafka delivery error: {}", e)
+                })
+        }
+    });
+    let results = futures_util::future::join_all(deliveries).await;
+
+    info!(
+        duration_ms = start.elapsed().as_millis(),
+        delivered = results.iter().filter(|r| r.is_ok()).count(),
+        topic = %topic,
+        "Published message batch to Kafka"
+    );
+
+    results
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct InitiateParams {
+    /// Parses and validates the payload before publishing; defaults to `PREVALIDATE`
+    pub validate: Option<bool>,
+}
+
+/// Initiates a message for an ISO 20022 XML payload.
+#[utoipa::path(
+    post,
+    path = "/initiate/{origin}",
+    tag = "initiate",
+    params(
+        ("origin" = String, Path, description = "Channel the payment arrived through; `/initiate` uses `api`"),
+        InitiateParams,
+    ),
+    request_body(content = String, content_type = "application/xml"),
+    responses(
+        (status = 200, description = "Message published", body = InitiateResponse),
+        (status = 400, description = "Bad request", body = ApiError),
+        (status = 409, description = "Idempotency key in use", body = ApiError),
+        (status = 422, description = "Payload failed pre-validation; `details` lists each problem", body = ApiError),
+        (status = 429, description = "Rate limit or daily quota exceeded; see `Retry-After`", body = ApiError),
+        (status = 500, description = "Publishing failed", body = ApiError),
+    ),
+)]
+#[instrument(skip(config, idempotency_store, limiter, req, body), fields(request_id = %request_id(), tenant, origin))]
 pub async fn initiate_message(
     config: web::Data<AppConfig>,
-    _req: HttpRequest,
+    idempotency_store: web::Data<dyn IdempotencyStore>,
+    limiter: web::Data<RateLimiter>,
+    params: web::Query<InitiateParams>,
+    req: HttpRequest,
     body: String,
 ) -> impl Responder {
     debug!(body_size = body.len(), "Received initiation request");
 
-    let initiation_result = tokio::spawn(async move {
-        let payload = Payload::new_inline(
-            Some(body.as_bytes().to_vec()),
-            PayloadFormat::Xml,
-            PayloadSchema::ISO20022,
-            Encoding::Utf8,
-        );
-        
-        let message = Message::new(
-            payload,
-            "tenant1".to_string(),
-            "api".to_string(),
-            "payment_processing".to_string(),
-            1,
-            "initiate".to_string(),
-            Some("Payment".to_string()),
-        );
-
-        debug!(message_id = %message.id(), "Created new message");
-
-        if let Err(e) = publish_to_kafka(&message, &config).await {
-            error!(error = %e, "Message initiation failed");
-            return InitiationResponse::Error(vec![format!("Kafka error: {}", e)]);
+    let tenant = match resolve_tenant(&req, &config) {
+        Ok(tenant) => tenant,
+        Err(e) => return e.error_response(),
+    };
+    let origin = resolve_origin(&req);
+    tracing::Span::current()
+        .record("tenant", tenant.as_str())
+        .record("origin", origin.as_str());
+
+    let payload = Payload::new_inline(
+        Some(body.as_bytes().to_vec()),
+        PayloadFormat::Xml,
+        PayloadSchema::ISO20022,
+        Encoding::Utf8,
+    );
+    let message = Message::new(
+        payload,
+        tenant.clone(),
+        origin.clone(),
+        config.initialworkflowid.clone(),
+        config.initialworkflowversion,
+        config.initialtaskid.clone(),
+        Some("Payment".to_string()),
+    );
+    debug!(message_id = %message.id(), "Created new message");
+
+    // Invalid payloads are refused before they can reserve an idempotency key
+    let message = if params.validate.unwrap_or(config.prevalidate) {
+        let (message, validation) = match web::block(move || {
+            let validation = message.validate_payload();
+            (message, validation)
+        }).await {
+            Ok(result) => result,
+            Err(e) => {
+                error!(error = %e, "Payload validation failed to run");
+                return ApiError::internal(format!("Task error: {}", e)).error_response();
+            }
+        };
+        if let Err(errors) = validation {
+            warn!(errors = ?errors, "Payload failed pre-validation");
+            return invalid_payload(&errors).error_response();
+        }
+        message
+    } else {
+        message
+    };
+
+    let idempotency_key = match idempotency::reserve(idempotency_store.get_ref(), &config, &req, &tenant, &origin, body.as_bytes()).await {
+        Ok(key) => key,
+        Err(response) => return response,
+    };
+
+    if let Err(e) = limiter.consume_quota(&tenant, route_of(req.path()), 1).await {
+        if let Some(key) = &idempotency_key {
+            idempotency::finish(idempotency_store.get_ref(), &config, &tenant, key, None).await;
         }
+        return e.error_response();
+    }
 
-        let response = json!({ "message_id": message.id().to_string() });
-        info!(message_id = %message.id(), "Message initiated successfully");
-        InitiationResponse::Success(response)
+    let message_id = message.id().to_string();
+    let publish_config = config.clone();
+    let published = tokio::spawn(async move {
+        publish_to_kafka(&message, &publish_config.kafkatopic, &publish_config).await
     })
     .await
     .unwrap_or_else(|e| {
         error!(error = %e, "Task execution failed");
-        InitiationResponse::Error(vec![format!("Task error: {:?}", e)])
+        Err(format!("Task error: {:?}", e))
     });
 
-    match &initiation_result {
-        InitiationResponse::Success(data) => {
-            info!(message_id = %data["message_id"], "Request completed successfully");
-            HttpResponse::Ok().json(data)
-        },
-        InitiationResponse::Error(errors) => {
-            warn!(errors = ?errors, "Request failed");
-            HttpResponse::BadRequest().json(errors)
-        },
+    let response = match published {
+        Ok(()) => Ok(InitiateResponse { message_id }),
+        Err(e) => {
+            error!(error = %e, "Message initiation failed");
+            limiter.refund_quota(&tenant, route_of(req.path()), 1).await;
+            Err(ApiError::internal(format!("Kafka error: {}", e)).with_code("publish_failed"))
+        }
+    };
+
+    if let Some(key) = &idempotency_key {
+        let stored = response.as_ref().ok().map(|r| json!(r));
+        idempotency::finish(idempotency_store.get_ref(), &config, &tenant, key, stored.as_ref().map(|r| (StatusCode::OK, r))).await;
+    }
+
+    match response {
+        Ok(response) => {
+            info!(message_id = %response.message_id, "Message initiated successfully");
+            HttpResponse::Ok().json(response)
+        }
+        Err(e) => e.error_response(),
     }
-}
\ No newline at end of file
+}
+
+/// The 422 for a payload that failed pre-validation, one detail per problem.
+pub fn invalid_payload(errors: &[PayloadError]) -> ApiError {
+    ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "Payload failed validation")
+        .with_code("invalid_payload")
+        .with_details(errors.iter().map(|e| json!(e)).collect())
+}
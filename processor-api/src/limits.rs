@@ -0,0 +1,410 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use core_data::storage::{StorageError, UsageStore};
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Iso8601;
+use time::OffsetDateTime;
+use tracing::{debug, error, instrument, warn};
+use utoipa::{IntoParams, ToSchema};
+use crate::api::ApiError;
+use crate::auth::{tenant_of, Principal};
+use crate::config::config::{AppConfig, DailyQuota, RateLimit};
+use crate::tenant::resolve_tenant;
+
+/// Route a request counts against: the first path segment, e.g. `initiate` for
+/// `/initiate/batch/swift`.
+pub fn route_of(path: &str) -> &str {
+    path.trim_start_matches('/').split('/').next().unwrap_or_default()
+}
+
+/// Current UTC day as `YYYY-MM-DD`, the period of the daily quotas.
+fn today() -> String {
+    OffsetDateTime::now_utc().date().to_string()
+}
+
+/// Seconds until the next UTC day starts and the quotas reset.
+fn secs_until_tomorrow() -> u64 {
+    let now = OffsetDateTime::now_utc();
+    let tomorrow = now.date().next_day().map(|d| d.midnight().assume_utc()).unwrap_or(now);
+    (tomorrow - now).whole_seconds().max(1) as u64
+}
+
+#[derive(Debug)]
+pub enum LimitError {
+    RateLimited { route: String, retry_after: u64 },
+    QuotaExceeded { route: String, messages: u64, retry_after: u64 },
+    Storage(StorageError),
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::RateLimited { route, .. } => write!(f, "Rate limit for {} exceeded", route),
+            LimitError::QuotaExceeded { route, messages, .. } => write!(f, "Daily quota of {} messages for {} exceeded", messages, route),
+            LimitError::Storage(e) => write!(f, "Storage error: {}", e),
+        }
+    }
+}
+
+impl ResponseError for LimitError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            LimitError::RateLimited { .. } | LimitError::QuotaExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+            LimitError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let (code, retry_after) = match self {
+            LimitError::RateLimited { retry_after, .. } => ("rate_limited", *retry_after),
+            LimitError::QuotaExceeded { retry_after, .. } => ("quota_exceeded", *retry_after),
+            LimitError::Storage(_) => return ApiError::internal(self.to_string()).error_response(),
+        };
+        let mut response = ApiError::new(self.status_code(), self.to_string()).with_code(code).error_response();
+        response.headers_mut().insert(
+            actix_web::http::header::RETRY_AFTER,
+            retry_after.into(),
+        );
+        response
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Adds the tokens earned since the last update, up to the burst size.
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let earned = now.duration_since(self.updated).as_secs_f64() * limit.per_minute as f64 / 60.0;
+        self.tokens = (self.tokens + earned).min(limit.burst as f64);
+        self.updated = now;
+    }
+}
+
+/// Token buckets and daily quotas per tenant and route. Buckets live in this process, so
+/// each API instance enforces its rate limits on its own; quotas are counted in the usage
+/// store and shared.
+pub struct RateLimiter {
+    limits: Vec<RateLimit>,
+    quotas: Vec<DailyQuota>,
+    buckets: Mutex<HashMap<(String, String), Bucket>>,
+    usage: Arc<dyn UsageStore>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &AppConfig, usage: Arc<dyn UsageStore>) -> Self {
+        RateLimiter {
+            limits: config.ratelimits.clone(),
+            quotas: config.dailyquotas.clone(),
+            buckets: Mutex::new(HashMap::new()),
+            usage,
+        }
+    }
+
+    /// The tenant's own limit for the route, or else the one for every tenant.
+    fn limit(&self, tenant: &str, route: &str) -> Option<&RateLimit> {
+        let matching = |l: &&RateLimit| l.route == route;
+        self.limits.iter().filter(matching).find(|l| l.tenant.as_deref() == Some(tenant))
+            .or_else(|| self.limits.iter().filter(matching).find(|l| l.tenant.is_none()))
+    }
+
+    fn quota(&self, tenant: &str, route: &str) -> Option<u64> {
+        let matching = |q: &&DailyQuota| q.route == route;
+        self.quotas.iter().filter(matching).find(|q| q.tenant.as_deref() == Some(tenant))
+            .or_else(|| self.quotas.iter().filter(matching).find(|q| q.tenant.is_none()))
+            .map(|q| q.messages)
+    }
+
+    /// Takes a token from the tenant's bucket for the route.
+    pub fn acquire(&self, tenant: &str, route: &str) -> Result<(), LimitError> {
+        let Some(limit) = self.limit(tenant, route) else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry((tenant.to_string(), route.to_string()))
+            .or_insert_with(|| Bucket { tokens: limit.burst as f64, updated: now });
+        bucket.refill(limit, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        let retry_after = ((1.0 - bucket.tokens) * 60.0 / limit.per_minute as f64).ceil().max(1.0) as u64;
+        Err(LimitError::RateLimited { route: route.to_string(), retry_after })
+    }
+
+    /// Counts `messages` against the tenant's daily quota for the route, refusing all of them
+    /// when they do not fit.
+    #[instrument(skip(self))]
+    pub async fn consume_quota(&self, tenant: &str, route: &str, messages: u64) -> Result<(), LimitError> {
+        let quota = self.quota(tenant, route);
+        match self.usage.consume(tenant, route, &today(), messages, quota).await {
+            Ok(Some(count)) => {
+                debug!(count, quota = ?quota, "Quota consumed");
+                Ok(())
+            }
+            Ok(None) => {
+                warn!(quota = ?quota, "Daily quota exceeded");
+                Err(LimitError::QuotaExceeded {
+                    route: route.to_string(),
+                    messages: quota.unwrap_or_default(),
+                    retry_after: secs_until_tomorrow(),
+                })
+            }
+            Err(e) => {
+                error!(error = %e, "Failed to count quota usage");
+                Err(LimitError::Storage(e))
+            }
+        }
+    }
+
+    /// Gives back `messages` charged by `consume_quota` that were never initiated.
+    #[instrument(skip(self))]
+    pub async fn refund_quota(&self, tenant: &str, route: &str, messages: u64) {
+        if messages == 0 {
+            return;
+        }
+        if let Err(e) = self.usage.refund(tenant, route, &today(), messages).await {
+            error!(error = %e, "Failed to refund quota usage");
+        }
+    }
+
+    /// Tokens left in the tenant's bucket for the route, as of now.
+    fn available(&self, tenant: &str, route: &str) -> Option<u32> {
+        let limit = self.limit(tenant, route)?;
+        let mut buckets = self.buckets.lock().unwrap();
+        Some(match buckets.get_mut(&(tenant.to_string(), route.to_string())) {
+            Some(bucket) => {
+                bucket.refill(limit, Instant::now());
+                bucket.tokens.floor() as u32
+            }
+            None => limit.burst,
+        })
+    }
+
+    fn bucket_keys(&self, tenant: Option<&str>) -> Vec<(String, String)> {
+        self.buckets.lock().unwrap().keys()
+            .filter(|(t, _)| tenant.is_none_or(|tenant| t == tenant))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Applies the rate limit of the request's route to its tenant. Requests whose tenant
+/// cannot be resolved pass through, for the handler to refuse.
+pub async fn rate_limit(req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let limiter = req.app_data::<web::Data<RateLimiter>>().cloned();
+    let config = req.app_data::<web::Data<AppConfig>>().cloned();
+    if let (Some(limiter), Some(config)) = (limiter, config) {
+        if let Ok(tenant) = resolve_tenant(req.request(), &config) {
+            let route = route_of(req.path()).to_string();
+            if let Err(e) = limiter.acquire(&tenant, &route) {
+                warn!(tenant = %tenant, route = %route, "Request rate limited");
+                return Ok(req.error_response(e));
+            }
+        }
+    }
+    next.call(req).await.map(ServiceResponse::map_into_boxed_body)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UsageParams {
+    pub tenant: Option<String>,
+    /// UTC day as `YYYY-MM-DD`; defaults to today
+    pub day: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct RouteUsage {
+    pub tenant: String,
+    pub route: String,
+    /// Messages counted against the daily quota
+    pub messages: u64,
+    /// Daily quota; absent when unlimited
+    pub quota: Option<u64>,
+    /// Requests the rate limit allows right now; absent when unlimited
+    pub available: Option<u32>,
+    pub per_minute: Option<u32>,
+    pub burst: Option<u32>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UsageReport {
+    pub day: String,
+    pub routes: Vec<RouteUsage>,
+}
+
+/// Quota usage for the day and, for today, the state of the rate limit buckets.
+#[utoipa::path(
+    get,
+    path = "/usage",
+    tag = "usage",
+    params(UsageParams),
+    responses(
+        (status = 200, description = "Usage per tenant and route", body = UsageReport),
+        (status = 400, description = "Invalid day", body = ApiError),
+        (status = 403, description = "Tenant not accessible with these credentials", body = ApiError),
+    ),
+)]
+#[instrument(skip(limiter, principal))]
+pub async fn get_usage(
+    limiter: web::Data<RateLimiter>,
+    principal: Option<web::ReqData<Principal>>,
+    params: web::Query<UsageParams>,
+) -> impl Responder {
+    let UsageParams { mut tenant, day } = params.into_inner();
+    if let Some(own) = tenant_of(&principal) {
+        if tenant.as_deref().is_some_and(|requested| requested != own) {
+            return ApiError::forbidden(format!("Not authorized for tenant {}", tenant.unwrap_or_default())).error_response();
+        }
+        tenant = Some(own.to_string());
+    }
+    let today = today();
+    let day = day.unwrap_or_else(|| today.clone());
+    if time::Date::parse(&day, &Iso8601::DATE).is_err() {
+        return ApiError::bad_request(format!("Invalid day '{}', expected YYYY-MM-DD", day)).error_response();
+    }
+
+    let records = match limiter.usage.usage(&day, tenant.as_deref()).await {
+        Ok(records) => records,
+        Err(e) => {
+            error!(error = %e, "Failed to load usage");
+            return ApiError::internal(format!("Storage error: {}", e)).error_response();
+        }
+    };
+    let mut routes: BTreeMap<(String, String), RouteUsage> = records.into_iter()
+        .map(|r| ((r.tenant.clone(), r.route.clone()), RouteUsage {
+            tenant: r.tenant,
+            route: r.route,
+            messages: r.count,
+            ..Default::default()
+        }))
+        .collect();
+    // Buckets only describe the present
+    if day == today {
+        for key in limiter.bucket_keys(tenant.as_deref()) {
+            routes.entry(key.clone()).or_insert_with(|| RouteUsage {
+                tenant: key.0,
+                route: key.1,
+                ..Default::default()
+            });
+        }
+    }
+
+    for usage in routes.values_mut() {
+        usage.quota = limiter.quota(&usage.tenant, &usage.route);
+        if let Some(limit) = limiter.limit(&usage.tenant, &usage.route) {
+            usage.per_minute = Some(limit.per_minute);
+            usage.burst = Some(limit.burst);
+            if day == today {
+                usage.available = limiter.available(&usage.tenant, &usage.route);
+            }
+        }
+    }
+
+    HttpResponse::Ok().json(UsageReport {
+        day,
+        routes: routes.into_values().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use actix_web::dev::Service;
+    use actix_web::test::{call_and_read_body_json, call_service, init_service, TestRequest};
+    use actix_web::{App, HttpMessage};
+    use core_data::storage::InMemoryUsageStore;
+    use serde_json::Value;
+    use crate::auth::Scope;
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        let config = AppConfig {
+            ratelimits: vec![
+                RateLimit { tenant: None, route: "initiate".to_string(), per_minute: 30, burst: 2 },
+                RateLimit { tenant: Some("tenant2".to_string()), route: "initiate".to_string(), per_minute: 60, burst: 5 },
+            ],
+            dailyquotas: vec![DailyQuota { tenant: None, route: "initiate".to_string(), messages: 10 }],
+            ..Default::default()
+        };
+        RateLimiter::new(&config, Arc::new(InMemoryUsageStore::new()))
+    }
+
+    #[test]
+    fn test_bucket_refills_up_to_the_burst() {
+        let limit = RateLimit { tenant: None, route: "initiate".to_string(), per_minute: 60, burst: 5 };
+        let start = Instant::now();
+        let mut bucket = Bucket { tokens: 0.0, updated: start };
+        bucket.refill(&limit, start + Duration::from_secs(2));
+        assert_eq!(bucket.tokens, 2.0);
+        bucket.refill(&limit, start + Duration::from_secs(60));
+        assert_eq!(bucket.tokens, 5.0);
+    }
+
+    #[test]
+    fn test_acquire_limits_each_tenant() {
+        let limiter = limiter();
+        assert!(limiter.acquire("tenant1", "initiate").is_ok());
+        assert!(limiter.acquire("tenant1", "initiate").is_ok());
+
+        // An empty bucket waits for the next token, two seconds at 30 per minute
+        let error = limiter.acquire("tenant1", "initiate").unwrap_err();
+        assert!(matches!(error, LimitError::RateLimited { retry_after: 2, .. }));
+        let response = error.error_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get(actix_web::http::header::RETRY_AFTER).unwrap(), "2");
+
+        // Other tenants and routes have buckets of their own
+        for _ in 0..5 {
+            assert!(limiter.acquire("tenant2", "initiate").is_ok());
+        }
+        assert!(limiter.acquire("tenant2", "initiate").is_err());
+        assert!(limiter.acquire("tenant1", "messages").is_ok());
+        assert_eq!(limiter.available("tenant1", "initiate"), Some(0));
+        assert_eq!(limiter.available("tenant3", "initiate"), Some(2));
+    }
+
+    #[actix_web::test]
+    async fn test_usage_is_confined_to_the_tenant() {
+        let limiter = web::Data::new(limiter());
+        limiter.consume_quota("tenant1", "initiate", 4).await.unwrap();
+        limiter.consume_quota("tenant2", "initiate", 3).await.unwrap();
+        assert!(matches!(
+            limiter.consume_quota("tenant1", "initiate", 7).await,
+            Err(LimitError::QuotaExceeded { messages: 10, .. })
+        ));
+
+        let app = init_service(
+            App::new()
+                .app_data(limiter)
+                .wrap_fn(|req, srv| {
+                    req.extensions_mut().insert(Principal { subject: "ops".to_string(), tenant: "tenant1".to_string(), scopes: vec![Scope::Admin] });
+                    srv.call(req)
+                })
+                .service(web::resource("/usage").route(web::get().to(get_usage)))
+        ).await;
+
+        let report: Value = call_and_read_body_json(&app, TestRequest::get().uri("/usage").to_request()).await;
+        let routes = report["routes"].as_array().unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0]["tenant"], "tenant1");
+        assert_eq!(routes[0]["messages"], 4);
+        assert_eq!(routes[0]["quota"], 10);
+
+        let response = call_service(&app, TestRequest::get().uri("/usage?tenant=tenant2").to_request()).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
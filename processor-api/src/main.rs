@@ -1,19 +1,54 @@
+mod api;
+mod openapi;
 mod initiate;
+mod batch;
+mod auth;
+mod approval;
+mod messages;
+mod workflows;
+mod tenant;
+mod idempotency;
+mod limits;
+mod files;
 mod config;
 
+use std::sync::Arc;
+
+use actix_cors::Cors;
 use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
-use tracing::{debug, error, info, instrument};
+use tracing::{debug, error, info, instrument, warn};
+use crate::api::{assign_request_id, extractor_error, not_found};
+use crate::openapi::openapi_json;
 use crate::initiate::initiate_message;
+use crate::batch::initiate_batch;
+use crate::files::upload_file;
+use crate::auth::{authorize, Authenticator};
+use crate::limits::{get_usage, rate_limit, RateLimiter};
+use crate::approval::{approve_message, list_approvals, reject_message};
+use crate::messages::{get_message, get_message_audit, list_messages};
+use crate::workflows::{activate_workflow, create_workflow, deprecate_workflow, diff_workflow, get_workflow, list_versions, validate_workflow};
+use core_data::storage::{
+    ApprovalStore, FilePayloadStore, IdempotencyStore, InMemoryApprovalStore, InMemoryIdempotencyStore, InMemoryMessageRepository,
+    InMemoryUsageStore, InMemoryWorkflowStore, MessageRepository, MongoApprovalStore, MongoIdempotencyStore, MongoMessageRepository,
+    MongoUsageStore, MongoWorkflowStore, UsageStore, WorkflowStore,
+};
 use crate::config::config::load_config;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use serde::Serialize;
 
-#[derive(Serialize)]
+#[derive(Serialize, utoipa::ToSchema)]
 struct HealthResponse {
     status: String,
     timestamp: String,
 }
 
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    security(()),
+    responses((status = 200, description = "The API is up", body = HealthResponse)),
+)]
 #[get("/health")]
 async fn health_check() -> impl Responder {
     let health_status = HealthResponse {
@@ -24,6 +59,14 @@ async fn health_check() -> impl Responder {
     HttpResponse::Ok().json(health_status)
 }
 
+/// Only the configured origins get CORS headers, so preflight requests never reach authentication.
+fn cors(origins: &[String]) -> Cors {
+    origins.iter()
+        .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+        .allowed_methods(vec!["GET", "POST"])
+        .allow_any_header()
+}
+
 #[instrument(name = "startup")]
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -64,7 +107,59 @@ async fn main() -> std::io::Result<()> {
         }
     };
 
+    type Stores = (Arc<dyn ApprovalStore>, Arc<dyn MessageRepository>, Arc<dyn WorkflowStore>, Arc<dyn IdempotencyStore>, Arc<dyn UsageStore>);
+    let (approvals, messages, workflows, idempotency, usage): Stores = if config.mongodburi.is_empty() {
+        warn!("No MongoDB configured, approvals, messages, workflows, idempotency keys and usage are kept in memory and not shared");
+        (
+            Arc::new(InMemoryApprovalStore::new()),
+            Arc::new(InMemoryMessageRepository::new()),
+            Arc::new(InMemoryWorkflowStore::new()),
+            Arc::new(InMemoryIdempotencyStore::new()),
+            Arc::new(InMemoryUsageStore::new()),
+        )
+    } else {
+        let client = mongodb::Client::with_uri_str(&config.mongodburi).await
+            .map_err(|e| {
+                error!(error = %e, database = %config.mongodbdatabase, "Failed to connect to MongoDB stores");
+                std::io::Error::other(e.to_string())
+            })?;
+        let database = client.database(&config.mongodbdatabase);
+        (
+            Arc::new(MongoApprovalStore::new(&database)),
+            Arc::new(MongoMessageRepository::new(&database)),
+            Arc::new(MongoWorkflowStore::new(&database)),
+            Arc::new(MongoIdempotencyStore::new(&database)),
+            Arc::new(MongoUsageStore::new(&database)),
+        )
+    };
+
+    let authenticator = if config.authfile.is_empty() {
+        warn!("No AUTHFILE configured, authentication is disabled");
+        None
+    } else {
+        match Authenticator::load(&config.authfile) {
+            Ok(authenticator) => Some(web::Data::new(authenticator)),
+            Err(e) => {
+                error!(error = %e, "Failed to load credentials. Exiting application");
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let payload_store = match FilePayloadStore::new(&config.payloaddir) {
+        Ok(store) => web::Data::new(store),
+        Err(e) => {
+            error!(error = %e, "Failed to open payload directory. Exiting application");
+            std::process::exit(1);
+        }
+    };
+
+    let limiter = web::Data::new(RateLimiter::new(&config, usage));
     let web_config = web::Data::new(config.clone());
+    let web_approvals: web::Data<dyn ApprovalStore> = web::Data::from(approvals);
+    let web_messages: web::Data<dyn MessageRepository> = web::Data::from(messages);
+    let web_workflows: web::Data<dyn WorkflowStore> = web::Data::from(workflows);
+    let web_idempotency: web::Data<dyn IdempotencyStore> = web::Data::from(idempotency);
     let bind_address = format!("{}:{}", &config.serverhostname, &config.serverport);
     
     info!(
@@ -76,14 +171,54 @@ async fn main() -> std::io::Result<()> {
         debug!("Initializing new worker");
         App::new()
             .app_data(web_config.clone())
+            .app_data(web_approvals.clone())
+            .app_data(web_messages.clone())
+            .app_data(web_workflows.clone())
+            .app_data(web_idempotency.clone())
+            .app_data(payload_store.clone())
+            .app_data(limiter.clone())
+            .configure(|cfg| {
+                if let Some(authenticator) = &authenticator {
+                    cfg.app_data(authenticator.clone());
+                }
+            })
+            .app_data(web::JsonConfig::default().error_handler(extractor_error))
+            .app_data(web::QueryConfig::default().error_handler(extractor_error))
+            .app_data(web::PathConfig::default().error_handler(extractor_error))
             .service(health_check)
+            .service(web::resource("/openapi.json").route(web::get().to(openapi_json)))
             .service(web::resource("/initiate").to(initiate_message))
+            // Registered ahead of /initiate/{origin}, which would otherwise take "batch" as an origin
+            .service(web::resource("/initiate/batch")
+                .app_data(web::PayloadConfig::new(config.maxbatchbytes))
+                .route(web::post().to(initiate_batch)))
+            .service(web::resource("/initiate/batch/{origin}")
+                .app_data(web::PayloadConfig::new(config.maxbatchbytes))
+                .route(web::post().to(initiate_batch)))
+            .service(web::resource("/initiate/{origin}").to(initiate_message))
+            .service(web::resource("/files").route(web::post().to(upload_file)))
+            .service(web::resource("/files/{origin}").route(web::post().to(upload_file)))
+            .service(web::resource("/approvals").route(web::get().to(list_approvals)))
+            .service(web::resource("/approvals/{message_id}/approve").route(web::post().to(approve_message)))
+            .service(web::resource("/approvals/{message_id}/reject").route(web::post().to(reject_message)))
+            .service(web::resource("/messages").route(web::get().to(list_messages)))
+            .service(web::resource("/messages/{message_id}").route(web::get().to(get_message)))
+            .service(web::resource("/messages/{message_id}/audit").route(web::get().to(get_message_audit)))
+            .service(web::resource("/workflows").route(web::post().to(create_workflow)))
+            .service(web::resource("/workflows/{workflow_id}/versions").route(web::get().to(list_versions)))
+            .service(web::resource("/workflows/{workflow_id}/versions/{version}").route(web::get().to(get_workflow)))
+            .service(web::resource("/workflows/{workflow_id}/versions/{version}/validate").route(web::post().to(validate_workflow)))
+            .service(web::resource("/workflows/{workflow_id}/versions/{version}/activate").route(web::post().to(activate_workflow)))
+            .service(web::resource("/workflows/{workflow_id}/versions/{version}/deprecate").route(web::post().to(deprecate_workflow)))
+            .service(web::resource("/workflows/{workflow_id}/diff").route(web::get().to(diff_workflow)))
+            .service(web::resource("/usage").route(web::get().to(get_usage)))
+            .default_service(web::to(not_found))
+            .wrap(actix_web::middleware::from_fn(rate_limit))
+            .wrap(actix_web::middleware::from_fn(authorize))
+            .wrap(actix_web::middleware::from_fn(assign_request_id))
             .wrap(actix_web::middleware::Logger::default())
             .wrap(actix_web::middleware::Compress::default())
-            .wrap(actix_web::middleware::DefaultHeaders::new()
-                .add(("Access-Control-Allow-Origin", "*"))
-                .add(("Access-Control-Allow-Methods", "POST"))
-                .add(("Access-Control-Allow-Headers", "Content-Type")))
+            .wrap(cors(&config.corsallowedorigins))
     })
     .bind(&bind_address)
     .map_err(|e| {
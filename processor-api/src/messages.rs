@@ -0,0 +1,265 @@
+use core_data::models::message::{Message, MessageStatus};
+use core_data::storage::{MessageQuery, MessageRepository};
+use serde::{Deserialize, Serialize};
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use serde_json::{json, Value};
+use utoipa::{IntoParams, ToSchema};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::{error, instrument, warn};
+use crate::api::ApiError;
+use crate::auth::{can_access, tenant_of, Principal};
+
+const MAX_PAGE_SIZE: u64 = 500;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListParams {
+    pub tenant: Option<String>,
+    pub status: Option<String>,
+    /// Batch correlation id returned by /initiate/batch
+    pub parent_id: Option<String>,
+    /// RFC 3339 creation time lower bound, inclusive
+    pub from: Option<String>,
+    /// RFC 3339 creation time upper bound, exclusive
+    pub to: Option<String>,
+    pub offset: Option<u64>,
+    pub limit: Option<u64>,
+}
+
+impl ListParams {
+    fn query(self) -> Result<MessageQuery, Vec<String>> {
+        let mut errors = Vec::new();
+        let mut instant = |name: &str, value: Option<String>| value.and_then(|v| {
+            OffsetDateTime::parse(&v, &Rfc3339)
+                .map_err(|e| errors.push(format!("Invalid '{}' time '{}': {}", name, v, e)))
+                .ok()
+        });
+        let from = instant("from", self.from);
+        let to = instant("to", self.to);
+        let status = self.status.and_then(|s| {
+            serde_json::from_value::<MessageStatus>(json!(s))
+                .map_err(|_| errors.push(format!("Invalid status '{}'", s)))
+                .ok()
+        });
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let defaults = MessageQuery::default();
+        Ok(MessageQuery {
+            tenant: self.tenant,
+            status,
+            parent_id: self.parent_id,
+            from,
+            to,
+            offset: self.offset.unwrap_or(defaults.offset),
+            limit: self.limit.unwrap_or(defaults.limit).clamp(1, MAX_PAGE_SIZE),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MessageSummary {
+    pub message_id: String,
+    pub tenant: String,
+    pub origin: String,
+    /// Batch correlation id, for messages initiated through /initiate/batch
+    pub parent_id: Option<String>,
+    #[schema(value_type = String)]
+    pub status: MessageStatus,
+    pub workflow_id: String,
+    pub workflow_version: u16,
+    pub prev_task: String,
+    pub version: u16,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+impl From<&Message> for MessageSummary {
+    fn from(message: &Message) -> Self {
+        let progress = message.progress();
+        MessageSummary {
+            message_id: message.id().to_string(),
+            tenant: message.tenant().clone(),
+            origin: message.origin().clone(),
+            parent_id: message.parent_id().clone(),
+            status: progress.status.clone(),
+            workflow_id: progress.workflow_id.clone(),
+            workflow_version: progress.workflow_version,
+            prev_task: progress.prev_task.clone(),
+            version: message.version(),
+            created_at: message.created_at().format(&Rfc3339).ok(),
+            updated_at: progress.timestamp.format(&Rfc3339).ok(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MessageList {
+    pub messages: Vec<MessageSummary>,
+    /// Matching messages across all pages
+    pub total: u64,
+    pub offset: u64,
+    pub limit: u64,
+}
+
+/// Messages of other tenants are reported as not found.
+async fn load(repository: &dyn MessageRepository, principal: &Option<web::ReqData<Principal>>, message_id: &str) -> Result<Message, ApiError> {
+    match repository.get(message_id).await {
+        Ok(Some(message)) if can_access(principal, message.tenant()) => Ok(message),
+        Ok(_) => {
+            warn!(message_id = %message_id, "Message not found");
+            Err(ApiError::not_found(format!("Message {} not found", message_id)))
+        }
+        Err(e) => {
+            error!(error = %e, message_id = %message_id, "Failed to load message");
+            Err(ApiError::internal(format!("Storage error: {}", e)))
+        }
+    }
+}
+
+/// The full message, including its payload, data and audit trail.
+#[utoipa::path(
+    get,
+    path = "/messages/{message_id}",
+    tag = "messages",
+    params(("message_id" = String, Path)),
+    responses(
+        (status = 200, description = "The stored message", body = Object),
+        (status = 404, description = "Unknown message", body = ApiError),
+    ),
+)]
+#[instrument(skip(repository, principal))]
+pub async fn get_message(
+    repository: web::Data<dyn MessageRepository>,
+    principal: Option<web::ReqData<Principal>>,
+    message_id: web::Path<String>,
+) -> impl Responder {
+    match load(repository.get_ref(), &principal, &message_id).await {
+        Ok(message) => HttpResponse::Ok().json(message),
+        Err(e) => e.error_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/messages/{message_id}/audit",
+    tag = "messages",
+    params(("message_id" = String, Path)),
+    responses(
+        (status = 200, description = "Audit log entries, oldest first", body = Vec<Object>),
+        (status = 404, description = "Unknown message", body = ApiError),
+    ),
+)]
+#[instrument(skip(repository, principal))]
+pub async fn get_message_audit(
+    repository: web::Data<dyn MessageRepository>,
+    principal: Option<web::ReqData<Principal>>,
+    message_id: web::Path<String>,
+) -> impl Responder {
+    match load(repository.get_ref(), &principal, &message_id).await {
+        Ok(message) => HttpResponse::Ok().json(message.audit()),
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Pages through messages, newest first.
+#[utoipa::path(
+    get,
+    path = "/messages",
+    tag = "messages",
+    params(ListParams),
+    responses(
+        (status = 200, description = "One page of messages", body = MessageList),
+        (status = 400, description = "Invalid filter; `details` lists each problem", body = ApiError),
+        (status = 403, description = "Tenant not accessible with these credentials", body = ApiError),
+    ),
+)]
+#[instrument(skip(repository, principal))]
+pub async fn list_messages(
+    repository: web::Data<dyn MessageRepository>,
+    principal: Option<web::ReqData<Principal>>,
+    params: web::Query<ListParams>,
+) -> impl Responder {
+    let mut query = match params.into_inner().query() {
+        Ok(query) => query,
+        Err(errors) => {
+            return ApiError::bad_request("Invalid message query")
+                .with_details(errors.into_iter().map(Value::String).collect())
+                .error_response();
+        }
+    };
+    if let Some(tenant) = tenant_of(&principal) {
+        if query.tenant.as_deref().is_some_and(|requested| requested != tenant) {
+            return ApiError::forbidden(format!("Not authorized for tenant {}", query.tenant.unwrap_or_default())).error_response();
+        }
+        query.tenant = Some(tenant.to_string());
+    }
+
+    match repository.find(&query).await {
+        Ok(page) => HttpResponse::Ok().json(MessageList {
+            messages: page.messages.iter().map(MessageSummary::from).collect(),
+            total: page.total,
+            offset: query.offset,
+            limit: query.limit,
+        }),
+        Err(e) => {
+            error!(error = %e, "Failed to search messages");
+            ApiError::internal(format!("Storage error: {}", e)).error_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use actix_web::dev::Service;
+    use actix_web::{test, App, HttpMessage};
+    use core_data::models::message::{Encoding, Payload, PayloadFormat, PayloadSchema};
+    use core_data::storage::InMemoryMessageRepository;
+    use crate::auth::Scope;
+    use super::*;
+
+    fn message(tenant: &str) -> Message {
+        let payload = Payload::new_inline(None, PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+        Message::new(payload, tenant.to_string(), "api".to_string(), "payment_processing".to_string(), 1, "initiate".to_string(), None)
+    }
+
+    #[actix_web::test]
+    async fn test_messages_are_confined_to_the_tenant() {
+        let repository = InMemoryMessageRepository::new();
+        let own = message("tenant1");
+        let other = message("tenant2");
+        repository.upsert(&own).await.unwrap();
+        repository.upsert(&other).await.unwrap();
+        let repository: web::Data<dyn MessageRepository> = web::Data::from(Arc::new(repository) as Arc<dyn MessageRepository>);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(repository)
+                .wrap_fn(|req, srv| {
+                    req.extensions_mut().insert(Principal { subject: "reader".to_string(), tenant: "tenant1".to_string(), scopes: vec![Scope::Read] });
+                    srv.call(req)
+                })
+                .service(web::resource("/messages").route(web::get().to(list_messages)))
+                .service(web::resource("/messages/{message_id}").route(web::get().to(get_message)))
+                .service(web::resource("/messages/{message_id}/audit").route(web::get().to(get_message_audit)))
+        ).await;
+
+        let list: Value = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/messages").to_request()).await;
+        assert_eq!(list["total"], json!(1));
+        assert_eq!(list["messages"][0]["message_id"], json!(own.id().to_string()));
+
+        let response = test::call_service(&app, test::TestRequest::get().uri("/messages?tenant=tenant2").to_request()).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::FORBIDDEN);
+
+        // Another tenant's message looks like it does not exist
+        for uri in [format!("/messages/{}", other.id()), format!("/messages/{}/audit", other.id())] {
+            let response = test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+            assert_eq!(response.status(), actix_web::http::StatusCode::NOT_FOUND);
+        }
+        let response = test::call_service(&app, test::TestRequest::get().uri(&format!("/messages/{}", own.id())).to_request()).await;
+        assert!(response.status().is_success());
+    }
+}
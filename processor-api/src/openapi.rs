@@ -0,0 +1,68 @@
+use actix_web::{HttpResponse, Responder};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use crate::api::ApiError;
+use crate::auth::API_KEY_HEADER;
+
+/// The API contract, generated from the handlers' `utoipa::path` annotations.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Open Payments Processor API",
+        description = "Initiates ISO 20022 payments and manages their messages, approvals and workflows. \
+            Every error response carries the `ApiError` envelope. Requests may also be signed with an \
+            HMAC key through the `X-Key-Id`, `X-Timestamp` and `X-Signature` headers.",
+    ),
+    paths(
+        crate::health_check,
+        crate::initiate::initiate_message,
+        crate::batch::initiate_batch,
+        crate::files::upload_file,
+        crate::messages::list_messages,
+        crate::messages::get_message,
+        crate::messages::get_message_audit,
+        crate::approval::list_approvals,
+        crate::approval::approve_message,
+        crate::approval::reject_message,
+        crate::workflows::create_workflow,
+        crate::workflows::list_versions,
+        crate::workflows::get_workflow,
+        crate::workflows::validate_workflow,
+        crate::workflows::activate_workflow,
+        crate::workflows::deprecate_workflow,
+        crate::workflows::diff_workflow,
+        crate::limits::get_usage,
+    ),
+    components(schemas(ApiError)),
+    modifiers(&SecuritySchemes),
+    security(("api_key" = []), ("bearer" = [])),
+    tags(
+        (name = "initiate", description = "Start payment messages"),
+        (name = "files", description = "Start payment messages from uploaded files"),
+        (name = "messages", description = "Query messages and their audit trail"),
+        (name = "approvals", description = "Decide messages parked for approval"),
+        (name = "workflows", description = "Manage workflow definitions"),
+        (name = "usage", description = "Rate limit and daily quota usage"),
+    ),
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(API_KEY_HEADER))),
+        );
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).bearer_format("JWT").build()),
+        );
+    }
+}
+
+pub async fn openapi_json() -> impl Responder {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
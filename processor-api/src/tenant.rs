@@ -0,0 +1,83 @@
+use std::fmt;
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use tracing::warn;
+use crate::api::ApiError;
+use crate::config::config::AppConfig;
+
+/// Header naming the tenant when the credentials do not.
+pub const TENANT_HEADER: &str = "X-Tenant-Id";
+
+/// Origin of messages initiated without a channel in the route.
+pub const DEFAULT_ORIGIN: &str = "api";
+
+/// Tenant bound to the request's credentials, stored in the request extensions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tenant(pub String);
+
+#[derive(Debug, PartialEq)]
+pub enum TenantError {
+    Missing,
+    InvalidHeader,
+    /// The header names another tenant than the credentials
+    Forbidden(String),
+    Unknown(String),
+}
+
+impl fmt::Display for TenantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TenantError::Missing => write!(f, "Missing {} header", TENANT_HEADER),
+            TenantError::InvalidHeader => write!(f, "Invalid {} header", TENANT_HEADER),
+            TenantError::Forbidden(tenant) => write!(f, "Not authorized for tenant {}", tenant),
+            TenantError::Unknown(tenant) => write!(f, "Unknown tenant {}", tenant),
+        }
+    }
+}
+
+impl ResponseError for TenantError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            TenantError::Missing | TenantError::InvalidHeader => StatusCode::BAD_REQUEST,
+            TenantError::Forbidden(_) | TenantError::Unknown(_) => StatusCode::FORBIDDEN,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ApiError::new(self.status_code(), self.to_string()).error_response()
+    }
+}
+
+/// Resolves the tenant from the credentials, then the tenant header, then the configured
+/// default. A header naming another tenant than the credentials is refused, as is any
+/// tenant that is not configured.
+pub fn resolve_tenant(req: &HttpRequest, config: &AppConfig) -> Result<String, TenantError> {
+    let header = match req.headers().get(TENANT_HEADER).map(|v| v.to_str()) {
+        Some(Ok(value)) => Some(value.trim().to_string()),
+        Some(Err(_)) => return Err(TenantError::InvalidHeader),
+        None => None,
+    };
+    let credential = req.extensions().get::<Tenant>().map(|t| t.0.clone());
+
+    let tenant = match (credential, header) {
+        (Some(credential), Some(header)) if credential != header => {
+            warn!(tenant = %credential, requested = %header, "Credentials do not belong to the requested tenant");
+            return Err(TenantError::Forbidden(header));
+        }
+        (Some(tenant), _) | (None, Some(tenant)) => tenant,
+        (None, None) if !config.defaulttenant.is_empty() => config.defaulttenant.clone(),
+        (None, None) => return Err(TenantError::Missing),
+    };
+
+    if !config.tenants.contains(&tenant) {
+        warn!(tenant = %tenant, "Unknown tenant");
+        return Err(TenantError::Unknown(tenant));
+    }
+    Ok(tenant)
+}
+
+/// Origin named by the `{origin}` route segment, e.g. `/initiate/swift`.
+pub fn resolve_origin(req: &HttpRequest) -> String {
+    req.match_info().get("origin").unwrap_or(DEFAULT_ORIGIN).to_string()
+}
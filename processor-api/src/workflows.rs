@@ -0,0 +1,368 @@
+use core_data::models::workflow::{Workflow, WorkflowChange, WorkflowStatus};
+use core_data::storage::{StorageError, WorkflowStore};
+use serde::{Deserialize, Serialize};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use serde_json::Value;
+use utoipa::{IntoParams, ToSchema};
+use tracing::{error, info, instrument, warn};
+use crate::api::ApiError;
+use crate::auth::{can_access, Principal};
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DiffParams {
+    /// Version to compare from
+    pub from: u16,
+    /// Version to compare to
+    pub to: u16,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WorkflowVersion {
+    pub id: String,
+    pub version: u16,
+    pub name: String,
+    #[schema(value_type = String)]
+    pub status: WorkflowStatus,
+    pub tenant: String,
+    pub origin: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WorkflowValidation {
+    pub valid: bool,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WorkflowStatusChange {
+    pub id: String,
+    pub version: u16,
+    /// Status after the change
+    #[schema(value_type = String)]
+    pub status: WorkflowStatus,
+    /// Versions that were active before and are now deprecated
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub deprecated: Vec<u16>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WorkflowDiff {
+    pub id: String,
+    pub from: u16,
+    pub to: u16,
+    /// Changed fields as `{path, from, to}`, with tasks keyed by id
+    #[schema(value_type = Vec<Object>)]
+    pub changes: Vec<WorkflowChange>,
+}
+
+fn storage_error(e: StorageError) -> ApiError {
+    match e {
+        StorageError::Conflict(msg) => ApiError::conflict(msg),
+        e => {
+            error!(error = %e, "Workflow store failed");
+            ApiError::internal(format!("Storage error: {}", e))
+        }
+    }
+}
+
+/// Workflows of other tenants are reported as not found.
+async fn load(store: &dyn WorkflowStore, principal: &Option<web::ReqData<Principal>>, workflow_id: &str, version: u16) -> Result<Workflow, ApiError> {
+    match store.get(workflow_id, version).await {
+        Ok(Some(workflow)) if can_access(principal, &workflow.tenant) => Ok(workflow),
+        Ok(_) => {
+            warn!(workflow_id = %workflow_id, workflow_version = version, "Workflow not found");
+            Err(ApiError::not_found(format!("Workflow {} version {} not found", workflow_id, version)))
+        }
+        Err(e) => Err(storage_error(e)),
+    }
+}
+
+/// Stores the definition as a new draft version; its status in the body is ignored.
+#[utoipa::path(
+    post,
+    path = "/workflows",
+    tag = "workflows",
+    request_body(content = Object, description = "Workflow definition"),
+    responses(
+        (status = 201, description = "Draft created", body = Object),
+        (status = 403, description = "Tenant not accessible with these credentials", body = ApiError),
+        (status = 409, description = "Version already exists, or the id belongs to another tenant", body = ApiError),
+    ),
+)]
+#[instrument(skip(store, principal, body))]
+pub async fn create_workflow(
+    store: web::Data<dyn WorkflowStore>,
+    principal: Option<web::ReqData<Principal>>,
+    body: web::Json<Workflow>,
+) -> impl Responder {
+    let mut workflow = body.into_inner();
+    workflow.status = WorkflowStatus::Draft;
+    if !can_access(&principal, &workflow.tenant) {
+        return ApiError::forbidden(format!("Not authorized for tenant {}", workflow.tenant)).error_response();
+    }
+    // Messages refer to workflows by id alone, so an id cannot be shared between tenants
+    match store.versions(&workflow.id).await {
+        Ok(versions) if versions.iter().any(|w| w.tenant != workflow.tenant) => {
+            warn!(workflow_id = %workflow.id, "Workflow id belongs to another tenant");
+            return ApiError::conflict(format!("Workflow {} belongs to another tenant", workflow.id)).error_response();
+        }
+        Ok(_) => {}
+        Err(e) => return storage_error(e).error_response(),
+    }
+
+    match store.create(&workflow).await {
+        Ok(()) => {
+            info!(workflow_id = %workflow.id, workflow_version = workflow.version, "Workflow draft created");
+            HttpResponse::Created().json(workflow)
+        }
+        Err(e) => storage_error(e).error_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/workflows/{workflow_id}/versions",
+    tag = "workflows",
+    params(("workflow_id" = String, Path)),
+    responses((status = 200, description = "Stored versions", body = Vec<WorkflowVersion>)),
+)]
+#[instrument(skip(store, principal))]
+pub async fn list_versions(
+    store: web::Data<dyn WorkflowStore>,
+    principal: Option<web::ReqData<Principal>>,
+    workflow_id: web::Path<String>,
+) -> impl Responder {
+    match store.versions(&workflow_id).await {
+        Ok(workflows) => HttpResponse::Ok().json(workflows.iter()
+            .filter(|w| can_access(&principal, &w.tenant))
+            .map(|w| WorkflowVersion {
+                id: w.id.clone(),
+                version: w.version,
+                name: w.name.clone(),
+                status: w.status.clone(),
+                tenant: w.tenant.clone(),
+                origin: w.origin.clone(),
+            })
+            .collect::<Vec<_>>()),
+        Err(e) => storage_error(e).error_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/workflows/{workflow_id}/versions/{version}",
+    tag = "workflows",
+    params(("workflow_id" = String, Path), ("version" = u16, Path)),
+    responses(
+        (status = 200, description = "Workflow definition", body = Object),
+        (status = 404, description = "Unknown workflow version", body = ApiError),
+    ),
+)]
+#[instrument(skip(store, principal))]
+pub async fn get_workflow(
+    store: web::Data<dyn WorkflowStore>,
+    principal: Option<web::ReqData<Principal>>,
+    path: web::Path<(String, u16)>,
+) -> impl Responder {
+    let (workflow_id, version) = path.into_inner();
+    match load(store.get_ref(), &principal, &workflow_id, version).await {
+        Ok(workflow) => HttpResponse::Ok().json(workflow),
+        Err(e) => e.error_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/workflows/{workflow_id}/versions/{version}/validate",
+    tag = "workflows",
+    params(("workflow_id" = String, Path), ("version" = u16, Path)),
+    responses(
+        (status = 200, description = "Validation outcome", body = WorkflowValidation),
+        (status = 404, description = "Unknown workflow version", body = ApiError),
+    ),
+)]
+#[instrument(skip(store, principal))]
+pub async fn validate_workflow(
+    store: web::Data<dyn WorkflowStore>,
+    principal: Option<web::ReqData<Principal>>,
+    path: web::Path<(String, u16)>,
+) -> impl Responder {
+    let (workflow_id, version) = path.into_inner();
+    match load(store.get_ref(), &principal, &workflow_id, version).await {
+        Ok(workflow) => {
+            let errors = workflow.validate().err().unwrap_or_default();
+            HttpResponse::Ok().json(WorkflowValidation {
+                valid: errors.is_empty(),
+                errors,
+            })
+        }
+        Err(e) => e.error_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/workflows/{workflow_id}/versions/{version}/activate",
+    tag = "workflows",
+    params(("workflow_id" = String, Path), ("version" = u16, Path)),
+    responses(
+        (status = 200, description = "Status changed; the previously active versions are deprecated", body = WorkflowStatusChange),
+        (status = 404, description = "Unknown workflow version", body = ApiError),
+        (status = 409, description = "Not allowed from the current status", body = ApiError),
+        (status = 422, description = "Definition is invalid; `details` lists each problem", body = ApiError),
+    ),
+)]
+pub async fn activate_workflow(
+    store: web::Data<dyn WorkflowStore>,
+    principal: Option<web::ReqData<Principal>>,
+    path: web::Path<(String, u16)>,
+) -> impl Responder {
+    let (workflow_id, version) = path.into_inner();
+    change_status(store.get_ref(), &principal, &workflow_id, version, WorkflowStatus::Active).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/workflows/{workflow_id}/versions/{version}/deprecate",
+    tag = "workflows",
+    params(("workflow_id" = String, Path), ("version" = u16, Path)),
+    responses(
+        (status = 200, description = "Status changed", body = WorkflowStatusChange),
+        (status = 404, description = "Unknown workflow version", body = ApiError),
+        (status = 409, description = "Not allowed from the current status", body = ApiError),
+    ),
+)]
+pub async fn deprecate_workflow(
+    store: web::Data<dyn WorkflowStore>,
+    principal: Option<web::ReqData<Principal>>,
+    path: web::Path<(String, u16)>,
+) -> impl Responder {
+    let (workflow_id, version) = path.into_inner();
+    change_status(store.get_ref(), &principal, &workflow_id, version, WorkflowStatus::Deprecated).await
+}
+
+/// Activation is refused while the definition fails validation, since the processor
+/// would skip it when loading, and deprecates the versions active before it.
+#[instrument(skip(store, principal))]
+async fn change_status(
+    store: &dyn WorkflowStore,
+    principal: &Option<web::ReqData<Principal>>,
+    workflow_id: &str,
+    version: u16,
+    status: WorkflowStatus,
+) -> HttpResponse {
+    let workflow = match load(store, principal, workflow_id, version).await {
+        Ok(workflow) => workflow,
+        Err(e) => return e.error_response(),
+    };
+
+    if !workflow.status.can_transition_to(&status) {
+        warn!(from = ?workflow.status, to = ?status, "Invalid workflow status change");
+        return ApiError::conflict(format!(
+            "Workflow {} version {} cannot move from {:?} to {:?}", workflow_id, version, workflow.status, status
+        )).error_response();
+    }
+    if status == WorkflowStatus::Active {
+        if let Err(errors) = workflow.validate() {
+            warn!(errors = ?errors, "Refusing to activate invalid workflow");
+            return ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "Workflow failed validation")
+                .with_code("invalid_workflow")
+                .with_details(errors.into_iter().map(Value::String).collect())
+                .error_response();
+        }
+    }
+
+    let changed = match status {
+        WorkflowStatus::Active => store.activate(workflow_id, version, &workflow.status).await,
+        _ => store.set_status(workflow_id, version, &workflow.status, &status).await.map(|()| Vec::new()),
+    };
+    match changed {
+        Ok(deprecated) => {
+            info!(from = ?workflow.status, to = ?status, deprecated = ?deprecated, "Workflow status changed");
+            HttpResponse::Ok().json(WorkflowStatusChange {
+                id: workflow_id.to_string(),
+                version,
+                status,
+                deprecated,
+            })
+        }
+        Err(e) => storage_error(e).error_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/workflows/{workflow_id}/diff",
+    tag = "workflows",
+    params(("workflow_id" = String, Path), DiffParams),
+    responses(
+        (status = 200, description = "Changes between the versions", body = WorkflowDiff),
+        (status = 404, description = "Unknown workflow version", body = ApiError),
+    ),
+)]
+#[instrument(skip(store, principal))]
+pub async fn diff_workflow(
+    store: web::Data<dyn WorkflowStore>,
+    principal: Option<web::ReqData<Principal>>,
+    workflow_id: web::Path<String>,
+    params: web::Query<DiffParams>,
+) -> impl Responder {
+    let from = match load(store.get_ref(), &principal, &workflow_id, params.from).await {
+        Ok(workflow) => workflow,
+        Err(e) => return e.error_response(),
+    };
+    let to = match load(store.get_ref(), &principal, &workflow_id, params.to).await {
+        Ok(workflow) => workflow,
+        Err(e) => return e.error_response(),
+    };
+
+    HttpResponse::Ok().json(WorkflowDiff {
+        id: workflow_id.into_inner(),
+        from: params.from,
+        to: params.to,
+        changes: from.diff(&to),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use actix_web::test::{call_and_read_body_json, call_service, init_service, TestRequest};
+    use actix_web::App;
+    use core_data::storage::InMemoryWorkflowStore;
+    use serde_json::json;
+    use super::*;
+
+    fn definition(version: u16, tenant: &str) -> Value {
+        let mut workflow: Value = serde_json::from_str(include_str!("../../sample-workflow.json")).unwrap();
+        workflow["version"] = json!(version);
+        workflow["tenant"] = json!(tenant);
+        workflow
+    }
+
+    #[actix_web::test]
+    async fn test_activation_deprecates_the_active_version() {
+        let store: web::Data<dyn WorkflowStore> = web::Data::from(Arc::new(InMemoryWorkflowStore::new()) as Arc<dyn WorkflowStore>);
+        let app = init_service(
+            App::new()
+                .app_data(store)
+                .service(web::resource("/workflows").route(web::post().to(create_workflow)))
+                .service(web::resource("/workflows/{workflow_id}/versions/{version}/activate").route(web::post().to(activate_workflow)))
+        ).await;
+
+        for version in [1, 2] {
+            let response = call_service(&app, TestRequest::post().uri("/workflows").set_json(definition(version, "tenant1")).to_request()).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+        let response = call_service(&app, TestRequest::post().uri("/workflows").set_json(definition(3, "tenant2")).to_request()).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let first: Value = call_and_read_body_json(&app, TestRequest::post().uri("/workflows/payment_processing/versions/1/activate").to_request()).await;
+        assert_eq!(first.get("deprecated"), None);
+        let second: Value = call_and_read_body_json(&app, TestRequest::post().uri("/workflows/payment_processing/versions/2/activate").to_request()).await;
+        assert_eq!(second["status"], "Active");
+        assert_eq!(second["deprecated"], json!([1]));
+    }
+}
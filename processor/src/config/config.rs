@@ -1,4 +1,6 @@
 use std::env;
+use std::net::SocketAddr;
+use std::num::NonZeroU64;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -9,6 +11,23 @@ pub enum ConfigError {
     ParseError(String),
 }
 
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub enum WorkflowSourceKind {
+    #[default]
+    MongoDb,
+    File,
+}
+
+/// When processed messages are written to the message repository.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub enum PersistMode {
+    /// Once the workflow has run to the end, for workflows with `persist_on_complete`
+    #[default]
+    Complete,
+    /// After every pass through the processor, for all workflows
+    EveryStep,
+}
+
 #[derive(Debug, Default, PartialEq, Eq, Clone)]
 pub struct AppConfig {
     pub kafkabootstrapservers: String,
@@ -19,27 +38,81 @@ pub struct AppConfig {
     pub mongodburi: String,
     pub mongodbdatabase: String,
 
+    pub workflowsource: WorkflowSourceKind,
+    pub workflowdir: String,
     pub workflowids: Vec<String>,
+    pub workflowpollintervalms: u64,
+
+    pub schedulerpollintervalms: u64,
+
+    pub persistmode: PersistMode,
+
+    /// Where Prometheus metrics are served; `None` disables them
+    pub metricsaddr: Option<SocketAddr>,
 }
 
 pub fn load_config() -> Result<AppConfig, ConfigError> {
-    let mut config = AppConfig::default();
-
-    config.kafkabootstrapservers = env::var("KAFKABOOTSTRAPSERVERS")?;
-    config.kafkagroupid = env::var("KAFKAGROUPID")?;
-    
-    config.maxconcurrency = env::var("MAXCONCURRENCY")
-        .unwrap_or_else(|_| String::from("1"))
-        .parse()
-        .map_err(|e| ConfigError::ParseError(format!("Invalid batch size: {}", e)))?;
-    
-    config.mongodburi = env::var("MONGODBURI")?;
-    config.mongodbdatabase = env::var("MONGODBDATABASE")?;
-    
-    config.workflowids = env::var("WORKFLOWIDS")?
-        .split(',')
-        .map(String::from)
-        .collect();
+    let workflowsource = match env::var("WORKFLOWSOURCE").unwrap_or_else(|_| String::from("mongodb")).to_lowercase().as_str() {
+        "mongodb" => WorkflowSourceKind::MongoDb,
+        "file" => WorkflowSourceKind::File,
+        other => return Err(ConfigError::ParseError(format!("Invalid workflow source: {}", other))),
+    };
+
+    // MongoDB settings are only required when workflows come from MongoDB
+    let mongodb_var = |name: &str| match workflowsource {
+        WorkflowSourceKind::MongoDb => env::var(name),
+        WorkflowSourceKind::File => Ok(env::var(name).unwrap_or_default()),
+    };
+
+    let config = AppConfig {
+        kafkabootstrapservers: env::var("KAFKABOOTSTRAPSERVERS")?,
+        kafkagroupid: env::var("KAFKAGROUPID")?,
+
+        maxconcurrency: env::var("MAXCONCURRENCY")
+            .unwrap_or_else(|_| String::from("1"))
+            .parse()
+            .map_err(|e| ConfigError::ParseError(format!("Invalid batch size: {}", e)))?,
+
+        mongodburi: mongodb_var("MONGODBURI")?,
+        mongodbdatabase: mongodb_var("MONGODBDATABASE")?,
+
+        workflowdir: match workflowsource {
+            WorkflowSourceKind::File => env::var("WORKFLOWDIR")?,
+            WorkflowSourceKind::MongoDb => env::var("WORKFLOWDIR").unwrap_or_default(),
+        },
+        workflowsource,
+        // An empty list loads every workflow the source holds
+        workflowids: env::var("WORKFLOWIDS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(String::from)
+            .collect(),
+        workflowpollintervalms: env::var("WORKFLOWPOLLINTERVALMS")
+            .unwrap_or_else(|_| String::from("30000"))
+            .parse()
+            .map(NonZeroU64::get)
+            .map_err(|e| ConfigError::ParseError(format!("Invalid workflow poll interval: {}", e)))?,
+
+        schedulerpollintervalms: env::var("SCHEDULERPOLLINTERVALMS")
+            .unwrap_or_else(|_| String::from("1000"))
+            .parse()
+            .map(NonZeroU64::get)
+            .map_err(|e| ConfigError::ParseError(format!("Invalid scheduler poll interval: {}", e)))?,
+
+        persistmode: match env::var("PERSISTMODE").unwrap_or_else(|_| String::from("complete")).to_lowercase().as_str() {
+            "complete" => PersistMode::Complete,
+            "everystep" => PersistMode::EveryStep,
+            other => return Err(ConfigError::ParseError(format!("Invalid persist mode: {}", other))),
+        },
+
+        metricsaddr: match env::var("METRICSADDR").unwrap_or_else(|_| String::from("0.0.0.0:9464")).trim() {
+            "" => None,
+            addr => Some(addr.parse()
+                .map_err(|e| ConfigError::ParseError(format!("Invalid metrics address: {}", e)))?),
+        },
+    };
 
     Ok(config)
 }
\ No newline at end of file
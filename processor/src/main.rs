@@ -1,57 +1,20 @@
 mod config;
 mod processor;
+mod reloader;
+mod scheduler;
+mod source;
 
+use std::sync::Arc;
+use std::time::Duration;
 use crate::processor::*;
-use core_data::models::workflow::Workflow;
+use crate::reloader::{record_loaded, WorkflowReloader};
+use crate::scheduler::Scheduler;
+use crate::source::*;
+use metrics_exporter_prometheus::PrometheusBuilder;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use crate::config::config::load_config;
-use mongodb::{Client, options::ClientOptions, bson::doc}; 
-use futures::stream::TryStreamExt;
-use tracing::{debug, error, info, trace, info_span, instrument};
-
-#[instrument(name = "load_workflows", skip(mongo_uri), fields(db = %db_name))]
-async fn load_workflows(mongo_uri: &str, db_name: &str, workflow_ids: &[String]) -> Result<Vec<Workflow>, mongodb::error::Error> {
-    let start = std::time::Instant::now();
-    debug!(workflow_count = workflow_ids.len(), "Loading workflows from MongoDB");
-
-    let client_options = ClientOptions::parse(mongo_uri).await
-        .map_err(|e| {
-            error!(error = %e, "Failed to parse MongoDB connection options");
-            e
-        })?;
-
-    let client = Client::with_options(client_options)
-        .map_err(|e| {
-            error!(error = %e, "Failed to create MongoDB client");
-            e
-        })?;
-
-    let db = client.database(db_name);
-    let collection = db.collection::<Workflow>("Workflow");
-
-    let filter = doc! {
-        "id": {
-            "$in": workflow_ids
-        }
-    };
-
-    let mut workflows = Vec::new();
-    let mut cursor = collection.find(filter, None).await?;
-    
-    while let Some(workflow) = cursor.try_next().await? {
-        trace!(workflow_id = %workflow.id, "Loaded workflow");
-        workflows.push(workflow);
-    }
-
-    info!(
-        duration_ms = start.elapsed().as_millis(),
-        workflow_count = workflows.len(),
-        "Successfully loaded workflows"
-    );
-
-    Ok(workflows)
-}
-
+use crate::config::config::{load_config, WorkflowSourceKind};
+use core_data::storage::{ApprovalStore, InMemoryApprovalStore, InMemoryMessageRepository, InMemoryTimerStore, MessageRepository, MongoApprovalStore, MongoMessageRepository, MongoTimerStore, TimerStore};
+use tracing::{debug, error, info, info_span, instrument, warn};
 
 #[tokio::main]
 #[instrument(name = "main")]
@@ -85,26 +48,87 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
-    let workflows = match load_workflows(&config.mongodburi, &config.mongodbdatabase, &config.workflowids).await {
-        Ok(wf) => {
+    if let Some(addr) = config.metricsaddr {
+        match PrometheusBuilder::new().with_http_listener(addr).install() {
+            Ok(()) => info!(address = %addr, "Serving metrics"),
+            Err(e) => {
+                error!(error = %e, address = %addr, "Failed to start metrics exporter");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let poll_interval = Duration::from_millis(config.workflowpollintervalms);
+    let source: Arc<dyn WorkflowSource> = match config.workflowsource {
+        WorkflowSourceKind::MongoDb => {
+            match MongoWorkflowSource::connect(&config.mongodburi, &config.mongodbdatabase, config.workflowids.clone(), poll_interval).await {
+                Ok(source) => Arc::new(source),
+                Err(e) => {
+                    error!(
+                        error = %e,
+                        database = %config.mongodbdatabase,
+                        "Failed to connect to workflow store"
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+        WorkflowSourceKind::File => {
+            Arc::new(FileWorkflowSource::new(&config.workflowdir, config.workflowids.clone(), poll_interval))
+        }
+    };
+
+    let workflows = match source.load().await {
+        Ok(loaded) => {
             info!(
-                workflow_count = wf.len(),
-                database = %config.mongodbdatabase,
+                workflow_count = loaded.workflows.len(),
+                rejected_count = loaded.rejected.len(),
+                source = ?config.workflowsource,
                 "Successfully loaded workflows"
             );
-            wf
+            record_loaded(loaded.workflows.len());
+            loaded.workflows
         }
         Err(e) => {
             error!(
                 error = %e,
-                database = %config.mongodbdatabase,
+                source = ?config.workflowsource,
                 "Failed to load workflows"
             );
             std::process::exit(1);
         }
     };
-    
-    let processor = Processor::new(config, workflows)?;
+
+    let (approvals, timers, messages): (Arc<dyn ApprovalStore>, Arc<dyn TimerStore>, Arc<dyn MessageRepository>) = if config.mongodburi.is_empty() {
+        warn!("No MongoDB configured, approvals, scheduled and persisted messages are kept in memory and lost on restart");
+        (Arc::new(InMemoryApprovalStore::new()), Arc::new(InMemoryTimerStore::new()), Arc::new(InMemoryMessageRepository::new()))
+    } else {
+        match mongodb::Client::with_uri_str(&config.mongodburi).await {
+            Ok(client) => {
+                let database = client.database(&config.mongodbdatabase);
+                (
+                    Arc::new(MongoApprovalStore::new(&database)),
+                    Arc::new(MongoTimerStore::new(&database)),
+                    Arc::new(MongoMessageRepository::new(&database)),
+                )
+            }
+            Err(e) => {
+                error!(
+                    error = %e,
+                    database = %config.mongodbdatabase,
+                    "Failed to connect to message stores"
+                );
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let reloader = WorkflowReloader::new(source);
+    let scheduler = Scheduler::new(timers.clone(), Duration::from_millis(config.schedulerpollintervalms));
+
+    let processor = Arc::new(Processor::new(config, workflows, approvals, timers, messages)?);
+    tokio::spawn(reloader.run(processor.clone()));
+    tokio::spawn(scheduler.run(processor.clone()));
     processor.run().await?;
 
     Ok(())
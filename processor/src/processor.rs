@@ -11,11 +11,15 @@ use serde_json::Error as JsonError;
 use tokio::sync::Semaphore;
 use tokio::task::JoinSet;
 use std::sync::Arc;
+use arc_swap::ArcSwap;
 
 use crate::config::config::*;
+use core_data::models::message::{Message as CoreMessage, MessageStatus, WorkflowOutcome, WorkflowResponseError, TIMEOUT_CODE};
 use core_data::models::workflow::Workflow;
+use core_data::storage::{ApprovalStore, MessageRepository, ParkedMessage, ScheduledMessage, StorageError, TimerStore};
 
 #[derive(Debug, thiserror::Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum ProcessorError {
     #[error("Kafka error: {0}")]
     KafkaError(#[from] KafkaError),
@@ -25,9 +29,14 @@ pub enum ProcessorError {
     ProcessingError(String),
     #[error("Serialization error: {0}")]
     SerializationError(#[from] JsonError),
+    #[error("Storage error: {0}")]
+    StorageError(#[from] StorageError),
 }
 
-type ProcessResult<T> = Result<T, ProcessorError>;
+pub type ProcessResult<T> = Result<T, ProcessorError>;
+
+/// How long past its `timeout_ms` a workflow's thread is waited for
+const TIMEOUT_GRACE: Duration = Duration::from_secs(1);
 
 #[derive(Debug)]
 struct MessageMetadata {
@@ -41,34 +50,81 @@ pub struct Processor {
     consumer: Arc<StreamConsumer>,
     producer: FutureProducer,
     config: AppConfig,
-    workflows: Arc<Vec<Workflow>>,
+    workflows: ArcSwap<Vec<Workflow>>,
+    subscribed_topics: std::sync::Mutex<Vec<String>>,
+    approvals: Arc<dyn ApprovalStore>,
+    timers: Arc<dyn TimerStore>,
+    messages: Arc<dyn MessageRepository>,
     semaphore: Arc<Semaphore>,
 }
 
 impl Processor {
-    #[instrument(skip(config, workflows), fields(group_id = %config.kafkagroupid))]
-    pub fn new(config: AppConfig, workflows: Vec<Workflow>) -> ProcessResult<Self> {
+    #[instrument(skip(config, workflows, approvals, timers, messages), fields(group_id = %config.kafkagroupid))]
+    pub fn new(config: AppConfig, workflows: Vec<Workflow>, approvals: Arc<dyn ApprovalStore>, timers: Arc<dyn TimerStore>, messages: Arc<dyn MessageRepository>) -> ProcessResult<Self> {
         let consumer = Arc::new(Self::create_consumer(&config)?);
         let producer = Self::create_producer(&config)?;
         let semaphore = Arc::new(Semaphore::new(config.maxconcurrency));
 
-        let input_topics: Vec<&str> = workflows.iter()
-            .map(|w| w.input_topic.as_str())
-            .collect();
-        
-        consumer
-            .subscribe(&input_topics)
-            .map_err(ProcessorError::KafkaError)?;
+        let input_topics = Self::input_topics(&workflows);
+        Self::subscribe(&consumer, &input_topics)?;
 
         Ok(Self {
             consumer,
             producer,
             config,
-            workflows: Arc::new(workflows),
+            workflows: ArcSwap::from_pointee(workflows),
+            subscribed_topics: std::sync::Mutex::new(input_topics),
+            approvals,
+            timers,
+            messages,
             semaphore,
         })
     }
 
+    /// Snapshot of the workflows currently used for new work.
+    pub fn workflows(&self) -> Arc<Vec<Workflow>> {
+        self.workflows.load_full()
+    }
+
+    /// Atomically replaces the loaded workflows. Messages already being processed
+    /// keep the snapshot they started with.
+    #[instrument(skip(self, workflows), fields(workflow_count = workflows.len()))]
+    pub fn reload_workflows(&self, workflows: Vec<Workflow>) -> ProcessResult<()> {
+        let input_topics = Self::input_topics(&workflows);
+        let mut subscribed = self.subscribed_topics.lock().unwrap();
+        if *subscribed != input_topics {
+            info!(topics = ?input_topics, "Input topics changed, resubscribing");
+            Self::subscribe(&self.consumer, &input_topics)?;
+            *subscribed = input_topics;
+        }
+
+        self.workflows.store(Arc::new(workflows));
+        Ok(())
+    }
+
+    fn input_topics(workflows: &[Workflow]) -> Vec<String> {
+        // Sub-workflows only run inside their parents, so no messages arrive for them
+        let mut input_topics: Vec<String> = workflows.iter()
+            .filter(|w| !w.sub_workflow)
+            .map(|w| w.input_topic.clone())
+            .collect();
+        input_topics.sort_unstable();
+        input_topics.dedup();
+        input_topics
+    }
+
+    fn subscribe(consumer: &StreamConsumer, input_topics: &[String]) -> ProcessResult<()> {
+        if input_topics.is_empty() {
+            warn!("No workflows loaded, unsubscribing from all topics");
+            consumer.unsubscribe();
+            return Ok(());
+        }
+        let topics: Vec<&str> = input_topics.iter().map(String::as_str).collect();
+        consumer
+            .subscribe(&topics)
+            .map_err(ProcessorError::KafkaError)
+    }
+
     #[instrument(skip(config), fields(bootstrap_servers = %config.kafkabootstrapservers))]
     fn create_consumer(config: &AppConfig) -> ProcessResult<StreamConsumer> {
         ClientConfig::new()
@@ -101,9 +157,13 @@ impl Processor {
                         match message_result {
                             Ok(message) => {
                                 let permit = self.semaphore.clone().acquire_owned().await.unwrap();
-                                let workflows = self.workflows.clone();
+                                let workflows = self.workflows.load_full();
                                 let producer = self.producer.clone();
                                 let consumer = self.consumer.clone();
+                                let approvals = self.approvals.clone();
+                                let timers = self.timers.clone();
+                                let messages = self.messages.clone();
+                                let persist_mode = self.config.persistmode.clone();
                                 let payload = message.payload().unwrap_or_default().to_vec();
                                 
                                 let metadata = MessageMetadata {
@@ -115,7 +175,42 @@ impl Processor {
     
                                 tasks.spawn(async move {
                                     let _permit = permit;
-                                    let processed = Self::process_message(&payload, &workflows).await?;
+                                    let (message, processed, outcome) = Self::process_message(&payload, &workflows).await?;
+
+                                    if Self::should_persist(&message, &outcome, &workflows, &persist_mode) {
+                                        Self::persist_message(messages.as_ref(), &message).await?;
+                                    }
+
+                                    // Parked before the offset is committed so a crash cannot lose it
+                                    if let WorkflowOutcome::Suspended { task_id } = &outcome {
+                                        info!(
+                                            message_id = %message.id(),
+                                            task_id = %task_id,
+                                            "Parking message for approval"
+                                        );
+                                        let workflow = Self::workflow_of(&message, &workflows).cloned();
+                                        let parked = ParkedMessage::new(message, metadata.topic.clone());
+                                        approvals.park(match workflow {
+                                            Some(workflow) => parked.with_workflow(workflow),
+                                            None => parked,
+                                        }).await?;
+                                    } else if let WorkflowOutcome::Scheduled { task_id, due_at } = &outcome {
+                                        info!(
+                                            message_id = %message.id(),
+                                            task_id = %task_id,
+                                            due_at = %due_at,
+                                            "Warehousing message until due"
+                                        );
+                                        timers.schedule(ScheduledMessage::new(message, metadata.topic.clone(), *due_at)).await?;
+                                    } else if let WorkflowOutcome::RetryScheduled { retry_at } = &outcome {
+                                        // The scheduler re-injects it once due, so no worker waits on the delay
+                                        info!(
+                                            message_id = %message.id(),
+                                            retry_at = %retry_at,
+                                            "Holding message until its retry is due"
+                                        );
+                                        timers.schedule(ScheduledMessage::new(message, metadata.topic.clone(), *retry_at)).await?;
+                                    }
                                     
                                     let headers = rdkafka::message::OwnedHeaders::new();
                                     Self::publish_message(&producer, "message_updates", &metadata.key, processed, headers).await?;
@@ -133,19 +228,15 @@ impl Processor {
                         }
                     }
                 }
-            } else {
-                if let Some(result) = tasks.join_next().await {
-                    if let Err(e) = result {
-                        error!("Task joined with error: {}", e);
-                    }
-                }
+            } else if let Some(Err(e)) = tasks.join_next().await {
+                error!("Task joined with error: {}", e);
             }
         }
     }
 
 
     #[instrument(skip(msg, workflows), fields(msg_size = msg.len(), workflow_count = workflows.len()))]
-    async fn process_message(msg: &[u8], workflows: &[Workflow]) -> Result<Vec<u8>, ProcessorError> {
+    async fn process_message(msg: &[u8], workflows: &Arc<Vec<Workflow>>) -> Result<(CoreMessage, Vec<u8>, WorkflowOutcome), ProcessorError> {
         let start = std::time::Instant::now();
 
         if msg.is_empty() {
@@ -155,7 +246,7 @@ impl Processor {
             ));
         }
 
-        let mut message: core_data::models::message::Message = serde_json::from_slice(msg)
+        let mut message: CoreMessage = serde_json::from_slice(msg)
             .map_err(|e| {
                 error!(error = %e, "Failed to deserialize message");
                 ProcessorError::ProcessingError(format!("Error deserializing message: {}", e))
@@ -167,35 +258,41 @@ impl Processor {
             "Processing message"
         );
 
-        let mut workflow_executed = false;
-        for workflow in workflows.iter() {
-            if message.workflow_match(&workflow.tenant, &workflow.origin, &workflow.condition) {
+        let mut outcome = WorkflowOutcome::Finished;
+        match message.workflow_select(workflows) {
+            Some(workflow) => {
                 debug!(
                     workflow_id = %workflow.id,
+                    workflow_version = workflow.version,
                     message_id = %message.id(),
                     "Executing workflow"
                 );
-                workflow_executed = true;
-                message.execute_workflow(&workflow)
-                    .map_err(|e| {
-                        error!(
-                            error = %e,
-                            workflow_id = %workflow.id,
-                            message_id = %message.id(),
-                            "Workflow execution failed"
-                        );
-                        ProcessorError::ProcessingError(format!("Workflow execution error: {}", e))
-                    })?;
-                break;
+                let result = match workflow.timeout_ms {
+                    Some(limit) => Self::execute_with_timeout(&mut message, workflow, workflows.clone(), limit).await?,
+                    None => message.execute_workflow_with(workflow, workflows),
+                };
+                match result {
+                    Ok(workflow_outcome) => outcome = workflow_outcome,
+                    // Failed messages are still published so their progress and audit trail are visible
+                    Err(e) => error!(
+                        error = %e,
+                        workflow_id = %workflow.id,
+                        workflow_version = workflow.version,
+                        message_id = %message.id(),
+                        status = ?message.progress().status,
+                        "Workflow execution failed"
+                    ),
+                }
+            }
+            None => {
+                warn!(
+                    message_id = %message.id(),
+                    tenant = %message.tenant(),
+                    workflow_id = %message.progress().workflow_id,
+                    workflow_version = message.progress().workflow_version,
+                    "No matching workflow found"
+                );
             }
-        }
-
-        if !workflow_executed {
-            warn!(
-                message_id = %message.id(),
-                tenant = %message.tenant(),
-                "No matching workflow found"
-            );
         }
 
         info!(
@@ -204,10 +301,96 @@ impl Processor {
             "Message processing completed"
         );
 
-        serde_json::to_vec(&message).map_err(|e| {
+        let processed = serde_json::to_vec(&message).map_err(|e| {
             error!(error = %e, "Failed to serialize processed message");
             ProcessorError::SerializationError(e)
-        })
+        })?;
+        Ok((message, processed, outcome))
+    }
+
+    /// Whether the message is written to the repository after this pass: always with
+    /// `PersistMode::EveryStep`, otherwise once its workflow finished if it asks for it.
+    fn should_persist(message: &CoreMessage, outcome: &WorkflowOutcome, workflows: &[Workflow], mode: &PersistMode) -> bool {
+        match mode {
+            PersistMode::EveryStep => true,
+            PersistMode::Complete => *outcome == WorkflowOutcome::Finished
+                && Self::workflow_of(message, workflows).is_some_and(|w| w.persist_on_complete),
+        }
+    }
+
+    /// The workflow version the message is running in.
+    fn workflow_of<'a>(message: &CoreMessage, workflows: &'a [Workflow]) -> Option<&'a Workflow> {
+        workflows.iter()
+            .find(|w| w.id == message.progress().workflow_id && w.version == message.progress().workflow_version)
+    }
+
+    /// Upserts the message. A strictly newer copy already stored means this delivery is stale
+    /// (e.g. a redelivery after a crash), so the conflict is logged rather than failing the
+    /// message. A copy at the same version was written by another pass and fails it.
+    #[instrument(skip(messages, message), fields(message_id = %message.id(), version = message.version()))]
+    async fn persist_message(messages: &dyn MessageRepository, message: &CoreMessage) -> ProcessResult<()> {
+        match messages.upsert(message).await {
+            Ok(()) => {
+                debug!("Message persisted");
+                Ok(())
+            }
+            Err(StorageError::Conflict(e)) => {
+                let stored = messages.get(&message.id().to_string()).await?;
+                if stored.is_some_and(|stored| stored.version() > message.version()) {
+                    warn!(error = %e, "Skipped persisting stale message");
+                    return Ok(());
+                }
+                error!(error = %e, "Message already persisted at the same version");
+                Err(StorageError::Conflict(e).into())
+            }
+            Err(e) => {
+                error!(error = %e, "Failed to persist message");
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Publishes a due message back to the topic it came from. A warehoused message is
+    /// released first so it continues after its `Schedule` task; a retry runs its task again.
+    #[instrument(skip(self, scheduled), fields(message_id = %scheduled.message_id, topic = %scheduled.topic))]
+    pub async fn reinject(&self, scheduled: &ScheduledMessage) -> ProcessResult<()> {
+        let mut message = scheduled.message.clone();
+        if message.progress().status == MessageStatus::Scheduled {
+            message.wake()
+                .map_err(|e| ProcessorError::ProcessingError(e.to_string()))?;
+        }
+        let processed = serde_json::to_vec(&message)?;
+        let headers = rdkafka::message::OwnedHeaders::new();
+        Self::publish_message(&self.producer, &scheduled.topic, scheduled.message_id.as_bytes(), processed, headers).await
+    }
+
+    /// Runs the workflow on a blocking thread so a task that never yields cannot hold the
+    /// worker. The engine stops evaluating rules once `timeout_ms` passes and fails the
+    /// message itself, so the thread's result is waited for; only a thread stuck outside rule
+    /// evaluation for another `TIMEOUT_GRACE` is abandoned.
+    #[instrument(skip(message, workflow, workflows), fields(message_id = %message.id(), workflow_id = %workflow.id, limit_ms = limit))]
+    async fn execute_with_timeout(message: &mut CoreMessage, workflow: &Workflow, workflows: Arc<Vec<Workflow>>, limit: u64) -> ProcessResult<Result<WorkflowOutcome, WorkflowResponseError>> {
+        let mut running = message.clone();
+        let owned = workflow.clone();
+        let execution = tokio::task::spawn_blocking(move || {
+            let result = running.execute_workflow_with(&owned, &workflows);
+            (running, result)
+        });
+
+        match tokio::time::timeout(Duration::from_millis(limit) + TIMEOUT_GRACE, execution).await {
+            Ok(Ok((executed, result))) => {
+                *message = executed;
+                Ok(result)
+            }
+            Ok(Err(e)) => Err(ProcessorError::ProcessingError(format!("Workflow execution task failed: {}", e))),
+            Err(_) => {
+                error!(grace_ms = TIMEOUT_GRACE.as_millis(), "Workflow ignored its deadline, abandoning its thread");
+                let description = format!("Workflow timed out after {}ms", limit);
+                let task_id = message.progress().prev_task.clone();
+                message.fail_timeout(workflow, &task_id, description.clone());
+                Ok(Err(WorkflowResponseError::new(workflow.id.clone(), workflow.version, TIMEOUT_CODE as u16, description)))
+            }
+        }
     }
 
     #[instrument(skip(producer, processed_message), fields(topic = %topic, message_size = %processed_message.len()))]
@@ -217,7 +400,7 @@ impl Processor {
             "Producing processed message"
         );
         match producer.send(
-                FutureRecord::to(&topic)
+                FutureRecord::to(topic)
                     .payload(&processed_message)
                     .key(key)
                     .headers(headers),
@@ -275,3 +458,99 @@ impl Processor {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use core_data::models::message::{Encoding, Payload, PayloadFormat, PayloadSchema, StatusCode};
+    use serde_json::json;
+
+    use super::*;
+
+    /// The sample workflow with a parse task that fails and is retried an hour later.
+    fn retry_workflow() -> Workflow {
+        let mut workflow: serde_json::Value = serde_json::from_str(include_str!("../../sample-workflow.json")).unwrap();
+        workflow["tasks"][0]["retry"] = json!({"max_attempts": 3, "backoff": "Fixed", "delay_ms": 3_600_000});
+        serde_json::from_value(workflow).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_pending_retry_does_not_hold_the_worker() {
+        // The payload has no content, so parsing fails with a retryable error
+        let payload = Payload::new_inline(None, PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+        let message = CoreMessage::new(payload, "tenant1".to_string(), "api".to_string(), "payment_processing".to_string(), 1, "initiate".to_string(), None);
+        let msg = serde_json::to_vec(&message).unwrap();
+
+        let processed = tokio::time::timeout(Duration::from_secs(1), Processor::process_message(&msg, &Arc::new(vec![retry_workflow()]))).await;
+        let (message, _, outcome) = processed.expect("process_message waited for the retry").unwrap();
+        let WorkflowOutcome::RetryScheduled { retry_at } = outcome else {
+            panic!("expected a scheduled retry, got {:?}", outcome);
+        };
+        assert!(retry_at > time::OffsetDateTime::now_utc() + time::Duration::minutes(59));
+        assert_eq!(message.progress().retry_at, Some(retry_at));
+        assert_eq!(message.progress().attempt, 1);
+    }
+
+    /// Fetches a long list of amounts and sums it once per rule, far longer than `timeout_ms`.
+    fn slow_workflow(timeout_ms: u64) -> Workflow {
+        let sum = json!({"reduce": [{"var": "amounts"}, {"+": [{"var": "current"}, {"var": "accumulator"}]}, 0]});
+        let rules: Vec<_> = (0..50)
+            .map(|i| json!({"field": format!("metadata.sum_{}", i), "logic": sum, "description": null}))
+            .collect();
+        let mut workflow: serde_json::Value = serde_json::from_str(include_str!("../../sample-workflow.json")).unwrap();
+        workflow["timeout_ms"] = json!(timeout_ms);
+        workflow["tasks"] = json!([
+            {
+                "id": "fetch_amounts", "name": "Fetch amounts", "description": "Fetch amounts",
+                "message_status": "Recieved", "prev_task": "initiate", "prev_status_code": "Success",
+                "condition": null, "function": "Fetch", "input": {"amounts": (0..50_000).collect::<Vec<u64>>()}
+            },
+            {
+                "id": "sum_amounts", "name": "Sum amounts", "description": "Sum amounts",
+                "message_status": "Processing", "prev_task": "fetch_amounts", "prev_status_code": "Success",
+                "condition": null, "function": "Enrich", "input": rules
+            }
+        ]);
+        serde_json::from_value(workflow).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_workflow_timeout_keeps_the_engine_result() {
+        let workflow = slow_workflow(20);
+        let payload = Payload::new_inline(None, PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+        let mut message = CoreMessage::new(payload, "tenant1".to_string(), "api".to_string(), "payment_processing".to_string(), 1, "initiate".to_string(), None);
+
+        let started = std::time::Instant::now();
+        let result = Processor::execute_with_timeout(&mut message, &workflow, Arc::new(vec![workflow.clone()]), 20).await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(20) + TIMEOUT_GRACE);
+        assert_eq!(result.unwrap_err().code, TIMEOUT_CODE as u16);
+
+        // The message comes back from the thread, not failed in its place
+        assert_eq!(message.progress().status, MessageStatus::Failed);
+        assert_eq!(message.progress().prev_status_code, Some(StatusCode::Timeout));
+        assert!(message.audit().iter().any(|audit| audit.task() == "fetch_amounts"));
+    }
+
+    #[tokio::test]
+    async fn test_persist_skips_only_strictly_stale_copies() {
+        let messages = core_data::storage::InMemoryMessageRepository::new();
+        let payload = Payload::new_inline(None, PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+        let received = CoreMessage::new(payload, "tenant1".to_string(), "api".to_string(), "payment_processing".to_string(), 1, "initiate".to_string(), None);
+
+        // A pass with nothing to run still moves the version on
+        let mut workflow: serde_json::Value = serde_json::from_str(include_str!("../../sample-workflow.json")).unwrap();
+        workflow["tasks"] = json!([]);
+        let workflow: Workflow = serde_json::from_value(workflow).unwrap();
+        let mut processed = received.clone();
+        assert!(processed.execute_workflow(&workflow).is_ok());
+        assert!(processed.version() > received.version());
+
+        Processor::persist_message(&messages, &processed).await.unwrap();
+        Processor::persist_message(&messages, &received).await.unwrap();
+        assert!(matches!(
+            Processor::persist_message(&messages, &processed).await,
+            Err(ProcessorError::StorageError(StorageError::Conflict(_)))
+        ));
+        let stored = messages.get(&processed.id().to_string()).await.unwrap().unwrap();
+        assert_eq!(stored.version(), processed.version());
+    }
+}
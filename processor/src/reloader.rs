@@ -0,0 +1,181 @@
+use std::sync::Arc;
+
+use metrics::{counter, gauge};
+use tokio::sync::mpsc;
+use tracing::{error, info, instrument, trace, warn};
+
+use crate::processor::Processor;
+use crate::source::WorkflowSource;
+
+/// Reloads that swapped in new definitions or failed, labelled by `result`
+const RELOADS_METRIC: &str = "workflow_reloads_total";
+/// Definitions that failed to deserialize or validate
+const REJECTED_METRIC: &str = "workflows_rejected_total";
+/// Definitions the processor is running
+const LOADED_METRIC: &str = "workflows_loaded";
+
+/// Reports how many definitions the processor runs.
+pub fn record_loaded(workflow_count: usize) {
+    gauge!(LOADED_METRIC).set(workflow_count as f64);
+}
+
+pub struct WorkflowReloader {
+    source: Arc<dyn WorkflowSource>,
+}
+
+impl WorkflowReloader {
+    pub fn new(source: Arc<dyn WorkflowSource>) -> Self {
+        Self { source }
+    }
+
+    /// Reloads on every change reported by the source and swaps valid definitions into the processor.
+    #[instrument(name = "workflow_reloader", skip(self, processor))]
+    pub async fn run(self, processor: Arc<Processor>) {
+        let (tx, mut rx) = mpsc::channel(1);
+        let source = self.source.clone();
+        tokio::spawn(async move { source.watch(tx).await });
+
+        while rx.recv().await.is_some() {
+            self.reload(&processor).await;
+        }
+        warn!("Workflow source stopped reporting changes");
+    }
+
+    async fn reload(&self, processor: &Processor) {
+        let start = std::time::Instant::now();
+
+        let loaded = match self.source.load().await {
+            Ok(workflows) => workflows,
+            Err(e) => {
+                counter!(RELOADS_METRIC, "result" => "failed").increment(1);
+                error!(error = %e, "Workflow reload failed");
+                return;
+            }
+        };
+
+        // An invalid update never replaces a definition that is already running
+        let current = processor.workflows();
+        let mut workflows = loaded.workflows;
+        for (id, version) in &loaded.rejected {
+            if let Some(previous) = current.iter().find(|w| &w.id == id && w.version == *version) {
+                warn!(workflow_id = %id, workflow_version = version, "Keeping previous definition of invalid workflow");
+                workflows.push(previous.clone());
+            }
+        }
+        counter!(REJECTED_METRIC).increment(loaded.rejected.len() as u64);
+
+        if *current == workflows {
+            trace!("Workflows unchanged");
+            return;
+        }
+
+        let workflow_count = workflows.len();
+        match processor.reload_workflows(workflows) {
+            Ok(()) => {
+                counter!(RELOADS_METRIC, "result" => "succeeded").increment(1);
+                record_loaded(workflow_count);
+                info!(
+                    duration_ms = start.elapsed().as_millis(),
+                    workflow_count = workflow_count,
+                    rejected_count = loaded.rejected.len(),
+                    "Workflows reloaded"
+                );
+            }
+            Err(e) => {
+                counter!(RELOADS_METRIC, "result" => "failed").increment(1);
+                error!(error = %e, "Failed to apply reloaded workflows");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use core_data::models::workflow::Workflow;
+    use core_data::storage::{InMemoryApprovalStore, InMemoryMessageRepository, InMemoryTimerStore};
+
+    use super::*;
+    use crate::config::config::AppConfig;
+    use crate::source::{LoadedWorkflows, WorkflowSourceError};
+
+    /// Hands out one prepared load result, then fails; reports a single change.
+    struct StubSource {
+        next: Mutex<Option<LoadedWorkflows>>,
+    }
+
+    impl StubSource {
+        fn new(workflows: Vec<Workflow>, rejected: Vec<(String, u16)>) -> Arc<Self> {
+            Arc::new(Self { next: Mutex::new(Some(LoadedWorkflows { workflows, rejected })) })
+        }
+
+        fn failing() -> Arc<Self> {
+            Arc::new(Self { next: Mutex::new(None) })
+        }
+    }
+
+    #[async_trait]
+    impl WorkflowSource for StubSource {
+        async fn load(&self) -> Result<LoadedWorkflows, WorkflowSourceError> {
+            self.next.lock().unwrap().take()
+                .ok_or_else(|| std::io::Error::other("source unavailable").into())
+        }
+
+        async fn watch(&self, changes: mpsc::Sender<()>) {
+            let _ = changes.send(()).await;
+        }
+    }
+
+    fn sample(version: u16) -> Workflow {
+        let mut workflow: Workflow = serde_json::from_str(include_str!("../../sample-workflow.json")).unwrap();
+        workflow.version = version;
+        workflow
+    }
+
+    /// A processor whose Kafka clients are never used; librdkafka connects lazily.
+    fn processor(workflows: Vec<Workflow>) -> Arc<Processor> {
+        let config = AppConfig {
+            kafkabootstrapservers: "127.0.0.1:1".to_string(),
+            kafkagroupid: "reloader-test".to_string(),
+            maxconcurrency: 1,
+            ..Default::default()
+        };
+        Arc::new(Processor::new(
+            config,
+            workflows,
+            Arc::new(InMemoryApprovalStore::new()),
+            Arc::new(InMemoryTimerStore::new()),
+            Arc::new(InMemoryMessageRepository::new()),
+        ).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_run_swaps_in_reloaded_workflows() {
+        let processor = processor(vec![sample(1)]);
+        let reloader = WorkflowReloader::new(StubSource::new(vec![sample(1), sample(2)], Vec::new()));
+
+        // Returns once the source stops reporting changes
+        reloader.run(processor.clone()).await;
+        assert_eq!(*processor.workflows(), vec![sample(1), sample(2)]);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_update_keeps_previous_definition() {
+        let processor = processor(vec![sample(1)]);
+        let reloader = WorkflowReloader::new(StubSource::new(vec![sample(2)], vec![("payment_processing".to_string(), 1)]));
+
+        reloader.reload(&processor).await;
+        assert_eq!(*processor.workflows(), vec![sample(2), sample(1)]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_load_keeps_workflows() {
+        let processor = processor(vec![sample(1)]);
+        let reloader = WorkflowReloader::new(StubSource::failing());
+
+        reloader.reload(&processor).await;
+        assert_eq!(*processor.workflows(), vec![sample(1)]);
+    }
+}
@@ -0,0 +1,129 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use core_data::storage::{ScheduledMessage, TimerStore};
+use tracing::{debug, error, info, instrument};
+
+use crate::processor::{ProcessResult, Processor};
+
+/// Messages claimed from the timer store per query
+const BATCH_SIZE: usize = 100;
+
+pub struct Scheduler {
+    timers: Arc<dyn TimerStore>,
+    poll_interval: Duration,
+}
+
+impl Scheduler {
+    pub fn new(timers: Arc<dyn TimerStore>, poll_interval: Duration) -> Self {
+        Self { timers, poll_interval }
+    }
+
+    /// Re-injects warehoused messages and pending retries into their workflow once they are due.
+    #[instrument(name = "scheduler", skip(self, processor), fields(interval_ms = self.poll_interval.as_millis()))]
+    pub async fn run(self, processor: Arc<Processor>) {
+        let mut interval = tokio::time::interval(self.poll_interval);
+        loop {
+            interval.tick().await;
+            self.release_due(|scheduled| {
+                let processor = processor.clone();
+                async move { processor.reinject(&scheduled).await }
+            }).await;
+        }
+    }
+
+    /// Claims due messages a batch at a time and hands each to `reinject`. When one fails, it
+    /// and the rest of its batch go back into the store for the next poll.
+    async fn release_due<F, Fut>(&self, reinject: F)
+    where
+        F: Fn(ScheduledMessage) -> Fut,
+        Fut: Future<Output = ProcessResult<()>>,
+    {
+        loop {
+            let due = match self.timers.take_due(time::OffsetDateTime::now_utc(), BATCH_SIZE).await {
+                Ok(due) => due,
+                Err(e) => {
+                    error!(error = %e, "Failed to query due messages");
+                    return;
+                }
+            };
+            if due.is_empty() {
+                return;
+            }
+            debug!(due_count = due.len(), "Releasing due messages");
+
+            for (index, scheduled) in due.iter().enumerate() {
+                match reinject(scheduled.clone()).await {
+                    Ok(()) => info!(
+                        message_id = %scheduled.message_id,
+                        workflow_id = %scheduled.workflow_id,
+                        "Scheduled message re-injected"
+                    ),
+                    Err(e) => {
+                        error!(error = %e, message_id = %scheduled.message_id, "Failed to re-inject scheduled message");
+                        for unreleased in &due[index..] {
+                            if let Err(e) = self.timers.schedule(unreleased.clone()).await {
+                                error!(error = %e, message_id = %unreleased.message_id, "Failed to restore scheduled message");
+                            }
+                        }
+                        return;
+                    }
+                }
+            }
+            if due.len() < BATCH_SIZE {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use core_data::models::message::{Encoding, Message, Payload, PayloadFormat, PayloadSchema};
+    use core_data::storage::InMemoryTimerStore;
+
+    use super::*;
+    use crate::processor::ProcessorError;
+
+    fn scheduled(due_at: time::OffsetDateTime) -> ScheduledMessage {
+        let payload = Payload::new_inline(None, PayloadFormat::Xml, PayloadSchema::ISO20022, Encoding::Utf8);
+        let message = Message::new(payload, "tenant1".to_string(), "api".to_string(), "payment_processing".to_string(), 1, "initiate".to_string(), None);
+        ScheduledMessage::new(message, "payment_incoming".to_string(), due_at)
+    }
+
+    #[tokio::test]
+    async fn test_failed_reinject_restores_the_rest_of_the_batch() {
+        let timers = Arc::new(InMemoryTimerStore::new());
+        let now = time::OffsetDateTime::now_utc();
+        let mut ids = Vec::new();
+        for age in [3, 2, 1] {
+            let message = scheduled(now - time::Duration::minutes(age));
+            ids.push(message.message_id.clone());
+            timers.schedule(message).await.unwrap();
+        }
+        let scheduler = Scheduler::new(timers.clone(), Duration::from_secs(1));
+
+        // The second message fails, so it and the third are put back
+        let attempted = Mutex::new(Vec::new());
+        scheduler.release_due(|scheduled| {
+            let mut attempted = attempted.lock().unwrap();
+            attempted.push(scheduled.message_id);
+            let failed = attempted.len() == 2;
+            async move {
+                if failed {
+                    Err(ProcessorError::ProcessingError("broker unavailable".to_string()))
+                } else {
+                    Ok(())
+                }
+            }
+        }).await;
+        assert_eq!(*attempted.lock().unwrap(), ids[..2]);
+
+        let remaining = timers.take_due(now, BATCH_SIZE).await.unwrap();
+        let remaining: Vec<_> = remaining.iter().map(|s| s.message_id.clone()).collect();
+        assert_eq!(remaining, ids[1..]);
+    }
+}
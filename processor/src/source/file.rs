@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use core_data::models::workflow::{Workflow, WorkflowStatus};
+use notify::{RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, instrument, warn};
+
+use super::{LoadedWorkflows, WorkflowSource, WorkflowSourceError};
+
+/// Reads workflow definitions from `.json`, `.yaml` and `.yml` files in a directory.
+pub struct FileWorkflowSource {
+    dir: PathBuf,
+    workflow_ids: Vec<String>,
+    poll_interval: Duration,
+    /// Last successfully parsed `(id, version)` per file, so a broken edit keeps the previous definition
+    known: Mutex<HashMap<PathBuf, (String, u16)>>,
+}
+
+impl FileWorkflowSource {
+    pub fn new(dir: impl Into<PathBuf>, workflow_ids: Vec<String>, poll_interval: Duration) -> Self {
+        Self {
+            dir: dir.into(),
+            workflow_ids,
+            poll_interval,
+            known: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn parse(path: &Path) -> Result<Workflow, String> {
+        let content = tokio::fs::read_to_string(path).await.map_err(|e| e.to_string())?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str(&content).map_err(|e| e.to_string()),
+            _ => serde_yaml::from_str(&content).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn is_workflow_file(path: &Path) -> bool {
+        matches!(path.extension().and_then(|e| e.to_str()), Some("json" | "yaml" | "yml"))
+    }
+}
+
+#[async_trait]
+impl WorkflowSource for FileWorkflowSource {
+    #[instrument(name = "load_workflows", skip(self), fields(dir = %self.dir.display()))]
+    async fn load(&self) -> Result<LoadedWorkflows, WorkflowSourceError> {
+        let start = std::time::Instant::now();
+        debug!("Loading workflows from directory");
+
+        let mut paths = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if Self::is_workflow_file(&path) && tokio::fs::metadata(&path).await.is_ok_and(|m| m.is_file()) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        // Files are read before taking the lock, which is not held across awaits
+        let mut parsed = Vec::with_capacity(paths.len());
+        for path in paths {
+            let workflow = Self::parse(&path).await;
+            parsed.push((path, workflow));
+        }
+
+        let mut loaded = LoadedWorkflows::default();
+        let mut known = self.known.lock().unwrap();
+        for (path, workflow) in parsed {
+            if let Ok(workflow) = &workflow {
+                // Same selection as the MongoDB source
+                if !self.workflow_ids.is_empty() && !self.workflow_ids.contains(&workflow.id) {
+                    continue;
+                }
+                if workflow.status == WorkflowStatus::Draft {
+                    continue;
+                }
+                known.insert(path.clone(), (workflow.id.clone(), workflow.version));
+            }
+            let key = known.get(&path)
+                .cloned()
+                .unwrap_or_else(|| (path.display().to_string(), 0));
+            loaded.accept(key, workflow);
+        }
+
+        info!(
+            duration_ms = start.elapsed().as_millis(),
+            workflow_count = loaded.workflows.len(),
+            rejected_count = loaded.rejected.len(),
+            "Successfully loaded workflows"
+        );
+
+        Ok(loaded)
+    }
+
+    /// Uses filesystem notifications, falling back to polling when they are unavailable.
+    #[instrument(name = "watch_workflows", skip(self, changes), fields(dir = %self.dir.display()))]
+    async fn watch(&self, changes: mpsc::Sender<()>) {
+        let notifier = changes.clone();
+        let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            match event {
+                Ok(event) if event.paths.iter().any(|p| Self::is_workflow_file(p)) => {
+                    // A full channel already has a reload pending
+                    let _ = notifier.try_send(());
+                }
+                Ok(_) => {}
+                Err(e) => error!(error = %e, "Workflow directory watch error"),
+            }
+        });
+
+        match watcher {
+            Ok(mut watcher) => match watcher.watch(&self.dir, RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    info!("Watching workflow directory for changes");
+                    // Keep the watcher alive for as long as someone is listening
+                    changes.closed().await;
+                    return;
+                }
+                Err(e) => warn!(error = %e, "Failed to watch workflow directory"),
+            },
+            Err(e) => warn!(error = %e, "Filesystem notifications unavailable"),
+        }
+
+        info!(interval_ms = self.poll_interval.as_millis(), "Polling workflow directory for changes");
+        let mut interval = tokio::time::interval(self.poll_interval);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if changes.send(()).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(version: u16) -> Workflow {
+        let mut workflow: Workflow = serde_json::from_str(include_str!("../../../sample-workflow.json")).unwrap();
+        workflow.version = version;
+        workflow
+    }
+
+    fn write(dir: &Path, name: &str, content: &str) {
+        std::fs::write(dir.join(name), content).unwrap();
+    }
+
+    fn source(dir: &Path) -> FileWorkflowSource {
+        FileWorkflowSource::new(dir, Vec::new(), Duration::from_millis(50))
+    }
+
+    #[tokio::test]
+    async fn test_load_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut draft = sample(3);
+        draft.status = WorkflowStatus::Draft;
+        write(dir.path(), "v1.json", &serde_json::to_string(&sample(1)).unwrap());
+        write(dir.path(), "v2.yaml", &serde_yaml::to_string(&sample(2)).unwrap());
+        write(dir.path(), "v3.yml", &serde_yaml::to_string(&draft).unwrap());
+        write(dir.path(), "notes.txt", "not a workflow");
+
+        let loaded = source(dir.path()).load().await.unwrap();
+        assert_eq!(loaded.workflows, vec![sample(1), sample(2)]);
+        assert!(loaded.rejected.is_empty());
+
+        let other = FileWorkflowSource::new(dir.path(), vec!["other".to_string()], Duration::from_millis(50));
+        assert!(other.load().await.unwrap().workflows.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut invalid = sample(2);
+        invalid.input_topic = String::new();
+        write(dir.path(), "broken.json", "{ \"id\": ");
+        write(dir.path(), "invalid.json", &serde_json::to_string(&invalid).unwrap());
+        write(dir.path(), "valid.json", &serde_json::to_string(&sample(1)).unwrap());
+
+        let loaded = source(dir.path()).load().await.unwrap();
+        assert_eq!(loaded.workflows, vec![sample(1)]);
+        assert_eq!(loaded.rejected, vec![
+            (dir.path().join("broken.json").display().to_string(), 0),
+            ("payment_processing".to_string(), 2),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_broken_edit_is_rejected_under_previous_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = source(dir.path());
+        write(dir.path(), "workflow.json", &serde_json::to_string(&sample(1)).unwrap());
+        assert_eq!(source.load().await.unwrap().workflows, vec![sample(1)]);
+
+        write(dir.path(), "workflow.json", "{ \"id\": ");
+        let loaded = source.load().await.unwrap();
+        assert!(loaded.workflows.is_empty());
+        assert_eq!(loaded.rejected, vec![("payment_processing".to_string(), 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_watch_picks_up_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = std::sync::Arc::new(source(dir.path()));
+        let (tx, mut rx) = mpsc::channel(1);
+        let watcher = source.clone();
+        tokio::spawn(async move { watcher.watch(tx).await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        write(dir.path(), "workflow.yaml", &serde_yaml::to_string(&sample(1)).unwrap());
+        let change = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await;
+        assert_eq!(change, Ok(Some(())));
+        assert_eq!(source.load().await.unwrap().workflows, vec![sample(1)]);
+    }
+}
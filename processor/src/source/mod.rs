@@ -0,0 +1,64 @@
+mod file;
+mod mongo;
+
+use async_trait::async_trait;
+use core_data::models::workflow::Workflow;
+use tokio::sync::mpsc;
+use tracing::{error, trace};
+
+pub use self::file::FileWorkflowSource;
+pub use self::mongo::MongoWorkflowSource;
+
+#[derive(Debug, thiserror::Error)]
+#[allow(clippy::enum_variant_names)]
+pub enum WorkflowSourceError {
+    #[error("MongoDB error: {0}")]
+    MongoError(#[from] mongodb::error::Error),
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+#[derive(Debug, Default)]
+pub struct LoadedWorkflows {
+    pub workflows: Vec<Workflow>,
+    /// `(id, version)` of definitions that failed to deserialize or validate
+    pub rejected: Vec<(String, u16)>,
+}
+
+impl LoadedWorkflows {
+    /// Keeps the workflow if it deserialized and passes validation, otherwise records it as rejected.
+    pub(crate) fn accept(&mut self, key: (String, u16), workflow: Result<Workflow, String>) {
+        let workflow = match workflow {
+            Ok(workflow) => workflow,
+            Err(e) => {
+                error!(workflow_id = %key.0, workflow_version = key.1, error = %e, "Skipping workflow that could not be deserialized");
+                self.rejected.push(key);
+                return;
+            }
+        };
+
+        if let Err(errors) = workflow.validate() {
+            error!(
+                workflow_id = %workflow.id,
+                workflow_version = workflow.version,
+                errors = ?errors,
+                "Skipping invalid workflow"
+            );
+            self.rejected.push((workflow.id, workflow.version));
+            return;
+        }
+
+        trace!(workflow_id = %workflow.id, workflow_version = workflow.version, "Loaded workflow");
+        self.workflows.push(workflow);
+    }
+}
+
+/// Where workflow definitions are read from.
+#[async_trait]
+pub trait WorkflowSource: Send + Sync {
+    /// Loads every active or deprecated definition of the configured workflow ids.
+    async fn load(&self) -> Result<LoadedWorkflows, WorkflowSourceError>;
+
+    /// Sends on `changes` whenever definitions may have changed. Runs until the receiver is dropped.
+    async fn watch(&self, changes: mpsc::Sender<()>);
+}
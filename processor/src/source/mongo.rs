@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::stream::TryStreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::{options::ClientOptions, Client, Collection};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, instrument, warn};
+
+use super::{LoadedWorkflows, WorkflowSource, WorkflowSourceError};
+
+/// `(id, version)` of a stored definition. Drivers and shells write the version as either
+/// a 32 or 64 bit integer, so both are read; anything else is an error.
+fn workflow_key(document: &Document) -> Result<(String, u16), String> {
+    let id = document.get_str("id").map_err(|e| format!("Invalid workflow id: {}", e))?;
+    let version = match document.get("version") {
+        Some(Bson::Int32(version)) => u16::try_from(*version).map_err(|e| e.to_string()),
+        Some(Bson::Int64(version)) => u16::try_from(*version).map_err(|e| e.to_string()),
+        Some(other) => Err(format!("expected an integer, found {:?}", other.element_type())),
+        None => Err("missing".to_string()),
+    };
+    let version = version.map_err(|e| format!("Invalid version of workflow {}: {}", id, e))?;
+    Ok((id.to_string(), version))
+}
+
+pub struct MongoWorkflowSource {
+    collection: Collection<Document>,
+    workflow_ids: Vec<String>,
+    poll_interval: Duration,
+}
+
+impl MongoWorkflowSource {
+    #[instrument(name = "connect_workflows", skip(mongo_uri, workflow_ids), fields(db = %db_name))]
+    pub async fn connect(mongo_uri: &str, db_name: &str, workflow_ids: Vec<String>, poll_interval: Duration) -> Result<Self, WorkflowSourceError> {
+        let client_options = ClientOptions::parse(mongo_uri).await
+            .map_err(|e| {
+                error!(error = %e, "Failed to parse MongoDB connection options");
+                e
+            })?;
+
+        let client = Client::with_options(client_options)
+            .map_err(|e| {
+                error!(error = %e, "Failed to create MongoDB client");
+                e
+            })?;
+
+        Ok(Self {
+            collection: client.database(db_name).collection::<Document>("Workflow"),
+            workflow_ids,
+            poll_interval,
+        })
+    }
+}
+
+#[async_trait]
+impl WorkflowSource for MongoWorkflowSource {
+    #[instrument(name = "load_workflows", skip(self), fields(workflow_count = self.workflow_ids.len()))]
+    async fn load(&self) -> Result<LoadedWorkflows, WorkflowSourceError> {
+        let start = std::time::Instant::now();
+        debug!("Loading workflows from MongoDB");
+
+        // Deprecated versions stay loaded so in-flight messages can finish on them
+        let mut filter = doc! {
+            "status": {
+                "$in": ["Active", "Deprecated"]
+            }
+        };
+        if !self.workflow_ids.is_empty() {
+            filter.insert("id", doc! { "$in": &self.workflow_ids });
+        }
+
+        let mut loaded = LoadedWorkflows::default();
+        let mut cursor = self.collection.find(filter, None).await?;
+
+        while let Some(document) = cursor.try_next().await? {
+            match workflow_key(&document) {
+                Ok(key) => loaded.accept(key, mongodb::bson::from_document(document).map_err(|e| e.to_string())),
+                Err(e) => {
+                    let id = document.get_str("id").unwrap_or_default().to_string();
+                    loaded.accept((id, 0), Err(e));
+                }
+            }
+        }
+
+        info!(
+            duration_ms = start.elapsed().as_millis(),
+            workflow_count = loaded.workflows.len(),
+            rejected_count = loaded.rejected.len(),
+            "Successfully loaded workflows"
+        );
+
+        Ok(loaded)
+    }
+
+    /// Uses change streams, falling back to polling when they are unavailable (e.g. standalone MongoDB).
+    #[instrument(name = "watch_workflows", skip(self, changes))]
+    async fn watch(&self, changes: mpsc::Sender<()>) {
+        match self.collection.watch(None, None).await {
+            Ok(mut stream) => {
+                info!("Watching workflow collection for changes");
+                loop {
+                    match stream.try_next().await {
+                        Ok(Some(event)) => {
+                            debug!(operation = ?event.operation_type, "Workflow change detected");
+                            if changes.send(()).await.is_err() {
+                                return;
+                            }
+                        }
+                        Ok(None) => {
+                            warn!("Workflow change stream closed");
+                            break;
+                        }
+                        Err(e) => {
+                            error!(error = %e, "Workflow change stream failed");
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                warn!(error = %e, "Change streams unavailable");
+            }
+        }
+
+        info!(interval_ms = self.poll_interval.as_millis(), "Polling workflow collection for changes");
+        let mut interval = tokio::time::interval(self.poll_interval);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if changes.send(()).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workflow_key_reads_any_integer_version() {
+        assert_eq!(workflow_key(&doc! { "id": "payments", "version": 3_i32 }), Ok(("payments".to_string(), 3)));
+        assert_eq!(workflow_key(&doc! { "id": "payments", "version": 4_i64 }), Ok(("payments".to_string(), 4)));
+    }
+
+    #[test]
+    fn test_workflow_key_rejects_invalid_version() {
+        assert!(workflow_key(&doc! { "id": "payments", "version": "3" }).is_err());
+        assert!(workflow_key(&doc! { "id": "payments", "version": 3.0 }).is_err());
+        assert!(workflow_key(&doc! { "id": "payments", "version": 70_000_i64 }).is_err());
+        assert!(workflow_key(&doc! { "id": "payments" }).is_err());
+    }
+
+    #[test]
+    fn test_int64_version_deserializes() {
+        let sample: serde_json::Value = serde_json::from_str(include_str!("../../../sample-workflow.json")).unwrap();
+        let mut document = mongodb::bson::to_document(&sample).unwrap();
+        document.insert("version", 2_i64);
+
+        assert_eq!(workflow_key(&document), Ok(("payment_processing".to_string(), 2)));
+        let workflow: core_data::models::workflow::Workflow = mongodb::bson::from_document(document).unwrap();
+        assert_eq!(workflow.version, 2);
+    }
+}